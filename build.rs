@@ -31,6 +31,9 @@ fn main() {
         copy_file_to_build_dir("test/valkey.conf");
         copy_file_to_build_dir("test/valkey-ldap-client.crt");
         copy_file_to_build_dir("test/valkey-ldap-client.key");
+        copy_file_to_build_dir("test/valkey-ldap-client.pkcs1.key");
+        copy_file_to_build_dir("test/valkey-ldap-client.enc.key");
+        copy_file_to_build_dir("test/valkey-ldap-client.p12");
         copy_file_to_build_dir("scripts/docker/certs/valkey-ldap-ca.crt");
     }
 }
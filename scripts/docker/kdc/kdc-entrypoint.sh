@@ -0,0 +1,26 @@
+#!/bin/bash
+
+set -e
+
+REALM=VALKEY.IO
+
+if [ ! -f /var/lib/krb5kdc/principal ]; then
+    # Short ticket lifetimes exercise the ticket renewal of the module
+    cat > /etc/krb5kdc/kdc.conf <<KDC_CONF
+[realms]
+    ${REALM} = {
+        max_life = 10m
+    }
+KDC_CONF
+    kdb5_util create -s -r ${REALM} -P valkey-kdc-master
+    kadmin.local -q "addprinc -randkey ldap/ldap-gssapi@${REALM}"
+    kadmin.local -q "addprinc -randkey valkey-svc@${REALM}"
+fi
+
+# The keytabs of the LDAP server and of the search bind user of the module
+rm -f /kerberos/*.keytab
+kadmin.local -q "ktadd -norandkey -k /kerberos/ldap.keytab ldap/ldap-gssapi@${REALM}"
+kadmin.local -q "ktadd -norandkey -k /kerberos/valkey.keytab valkey-svc@${REALM}"
+chmod 644 /kerberos/*.keytab
+
+exec krb5kdc -n
@@ -26,6 +26,11 @@ openssl x509 -req -in valkey-ldap.csr -copy_extensions copy -CA valkey-ldap-ca.c
 openssl req -newkey rsa:2048 -nodes -keyout valkey-ldap-client.key -out valkey-ldap-client.csr -subj /CN=valkey -addext subjectAltName=DNS:valkey
 openssl x509 -req -in valkey-ldap-client.csr -copy_extensions copy -CA valkey-ldap-ca.crt -CAkey valkey-ldap-ca.key -CAcreateserial -out valkey-ldap-client.crt -days 365 -sha256
 
+# LDAP client key in the PKCS#1 and encrypted PKCS#8 formats, and as a PKCS#12 bundle
+openssl rsa -in valkey-ldap-client.key -traditional -out valkey-ldap-client.pkcs1.key
+openssl pkcs8 -topk8 -in valkey-ldap-client.key -passout pass:valkey -out valkey-ldap-client.enc.key
+openssl pkcs12 -export -in valkey-ldap-client.crt -inkey valkey-ldap-client.key -passout pass:valkey -out valkey-ldap-client.p12
+
 rm -f *.csr *.srl
 
 # Move client certificates to test directory
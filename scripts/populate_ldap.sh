@@ -8,5 +8,29 @@ done
 ADMIN_PASSWD=admin123!
 ADMIN_DN="cn=admin,dc=valkey,dc=io"
 
+# Define the nsAccountLock attribute of 389-DS to test the account status check
+docker exec -i ldap ldapadd -Y EXTERNAL -H ldapi:/// < test/ldap_schema.txt
+docker exec -i ldap-2 ldapadd -Y EXTERNAL -H ldapi:/// < test/ldap_schema.txt
+
 ldapadd -x -w ${ADMIN_PASSWD} -D ${ADMIN_DN} < test/ldap_users.txt
 ldapadd -H ldap://localhost:390 -x -w ${ADMIN_PASSWD} -D ${ADMIN_DN} < test/ldap_users.txt
+
+# Map the subject of the client certificate to the admin account for SASL EXTERNAL binds
+docker exec -i ldap ldapmodify -Y EXTERNAL -H ldapi:/// < test/ldap_config.txt
+docker exec -i ldap-2 ldapmodify -Y EXTERNAL -H ldapi:/// < test/ldap_config.txt
+
+# Enable the password policy overlay, with a default policy that requires the
+# users to change their password after it is reset
+for server in ldap ldap-2; do
+    docker exec -i $server ldapadd -Y EXTERNAL -H ldapi:/// -f /etc/ldap/schema/ppolicy.ldif
+    docker exec -i $server ldapmodify -Y EXTERNAL -H ldapi:/// < test/ldap_ppolicy_config.txt
+done
+ldapadd -x -w ${ADMIN_PASSWD} -D ${ADMIN_DN} < test/ldap_ppolicy_users.txt
+ldapadd -H ldap://localhost:390 -x -w ${ADMIN_PASSWD} -D ${ADMIN_DN} < test/ldap_ppolicy_users.txt
+
+if [ ! -z "$VALKEY_LDAP_GSSAPI_TESTS" ]; then
+    docker exec -i ldap-gssapi ldapadd -Y EXTERNAL -H ldapi:/// < test/ldap_schema.txt
+    docker exec -i ldap-gssapi ldapadd -x -w ${ADMIN_PASSWD} -D ${ADMIN_DN} < test/ldap_users.txt
+    # Map the Kerberos principal of the module to the admin account for SASL GSSAPI binds
+    docker exec -i ldap-gssapi ldapmodify -Y EXTERNAL -H ldapi:/// < test/ldap_gssapi_config.txt
+fi
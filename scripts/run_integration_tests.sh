@@ -4,7 +4,15 @@ while [[ ! $PWD/ = */valkey-ldap/ ]]; do
     cd ..
 done
 
-cargo build || exit 1
+CARGO_FLAGS=
+if [ ! -z "$VALKEY_LDAP_GSSAPI_TESTS" ]; then
+    CARGO_FLAGS="--features gssapi"
+fi
+if [ ! -z "$VALKEY_LDAP_RUSTLS_TESTS" ]; then
+    CARGO_FLAGS="--no-default-features --features tls-rustls,min-valkey-compatibility-version-8-0"
+fi
+
+cargo build $CARGO_FLAGS || exit 1
 
 DOCKER_COMPOSE_RUNNING=`docker compose ls --filter name=valkey-ldap -q && true`
 
@@ -11,7 +11,20 @@ else
     VALKEY_VERSION=$1
 fi
 
-cargo build
+# The GSSAPI tests need the KDC containers and a module built with GSSAPI support
+CARGO_FLAGS=
+COMPOSE_PROFILES="--profile valkey-${VALKEY_VERSION}"
+if [ ! -z "$VALKEY_LDAP_GSSAPI_TESTS" ]; then
+    CARGO_FLAGS="--features gssapi"
+    COMPOSE_PROFILES="${COMPOSE_PROFILES} --profile gssapi"
+fi
+
+# The TLS tests can also run against a module built with the rustls backend
+if [ ! -z "$VALKEY_LDAP_RUSTLS_TESTS" ]; then
+    CARGO_FLAGS="--no-default-features --features tls-rustls,min-valkey-compatibility-version-8-0"
+fi
+
+cargo build $CARGO_FLAGS
 
 DOCKER_COMPOSE_RUNNING=`docker compose ls --filter name=valkey-ldap -q && true`
 
@@ -20,8 +33,8 @@ if [ ! -z $DOCKER_COMPOSE_RUNNING ]; then
 else
     pushd scripts/docker > /dev/null
 
-    docker compose --profile valkey-${VALKEY_VERSION} up -d --wait
-    docker compose --profile valkey-${VALKEY_VERSION} logs -f > /tmp/valkey-ldap.log 2>&1 &
+    docker compose ${COMPOSE_PROFILES} up -d --wait
+    docker compose ${COMPOSE_PROFILES} logs -f > /tmp/valkey-ldap.log 2>&1 &
 
     popd > /dev/null
 fi
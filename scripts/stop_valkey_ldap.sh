@@ -16,6 +16,7 @@ pushd scripts/docker > /dev/null
 docker compose --profile valkey-7.2 down
 docker compose --profile valkey-8.0 down
 docker compose --profile valkey-8.1 down
+docker compose --profile gssapi down
 docker compose rm -f valkey-7.2 valkey-8.0 valkey-8.1
 
 popd > /dev/null
@@ -0,0 +1,697 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use lazy_static::lazy_static;
+use log::{debug, error, warn};
+use sha2::{Digest, Sha256};
+
+use crate::configs::LdapAuditEvents;
+use crate::vkldap::errors::VkLdapFailureReason;
+
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+const REDACTED: &str = "[REDACTED]";
+const TAIL_CHUNK_SIZE: u64 = 4096;
+
+#[derive(Clone)]
+pub struct AuditSettings {
+    pub path: Option<PathBuf>,
+    pub key_file: Option<PathBuf>,
+    pub events: LdapAuditEvents,
+    pub max_size: u64,
+    pub max_files: u32,
+}
+
+/// The kind of attempt of an audit record.
+#[derive(Clone, Copy)]
+pub enum AuditEvent {
+    Auth,
+    PasswordChange,
+}
+
+impl AuditEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditEvent::Auth => "auth",
+            AuditEvent::PasswordChange => "passwd",
+        }
+    }
+}
+
+/// An authentication or password change attempt to be recorded in the audit
+/// log.
+///
+/// There is intentionally no field to hold the password of the attempt.
+pub struct AuditRecord {
+    pub timestamp: SystemTime,
+    pub event: AuditEvent,
+    pub username: String,
+    pub user_dn: Option<String>,
+    pub client_id: u64,
+    pub client_addr: Option<String>,
+    pub server: Option<String>,
+    pub latency: Duration,
+    pub outcome: std::result::Result<(), VkLdapFailureReason>,
+}
+
+impl AuditRecord {
+    /// Replaces the username, and the attribute values of the user DN, that
+    /// are equal to the password. This covers clients that send the password
+    /// in the username by mistake, and the DNs built from such a username.
+    /// Fields that merely contain the password are kept, since a short
+    /// password may be part of any name.
+    pub fn redact(&mut self, password: &str) {
+        if password.is_empty() {
+            return;
+        }
+
+        if self.username == password {
+            self.username = REDACTED.to_string();
+        }
+        if let Some(user_dn) = &self.user_dn {
+            self.user_dn = Some(redact_dn(user_dn, password));
+        }
+    }
+
+    fn is_recorded(&self, events: &LdapAuditEvents) -> bool {
+        match events {
+            LdapAuditEvents::All => true,
+            LdapAuditEvents::Failures => self.outcome.is_err(),
+            LdapAuditEvents::Successes => self.outcome.is_ok(),
+        }
+    }
+
+    /// Returns the record as a JSON object.
+    pub fn to_json(&self) -> String {
+        let mut json = self.to_json_fields();
+        json.push('}');
+        json
+    }
+
+    /// Returns the record as a JSON object without the closing brace, so that
+    /// more fields can be appended.
+    fn to_json_fields(&self) -> String {
+        let (outcome, reason) = match &self.outcome {
+            Ok(_) => ("success", None),
+            Err(reason) => ("failure", Some(reason.as_str())),
+        };
+
+        let mut json = String::from("{");
+        json.push_str(&format!(
+            "\"timestamp\":{}",
+            json_string(&format_timestamp(self.timestamp))
+        ));
+        json.push_str(&format!(",\"event\":{}", json_string(self.event.as_str())));
+        json.push_str(&format!(",\"username\":{}", json_string(&self.username)));
+        json.push_str(&format!(
+            ",\"user_dn\":{}",
+            json_opt_string(self.user_dn.as_deref())
+        ));
+        json.push_str(&format!(",\"client_id\":{}", self.client_id));
+        json.push_str(&format!(
+            ",\"client_addr\":{}",
+            json_opt_string(self.client_addr.as_deref())
+        ));
+        json.push_str(&format!(
+            ",\"server\":{}",
+            json_opt_string(self.server.as_deref())
+        ));
+        json.push_str(&format!(",\"latency_ms\":{}", self.latency.as_millis()));
+        json.push_str(&format!(",\"outcome\":{}", json_string(outcome)));
+        json.push_str(&format!(",\"reason\":{}", json_opt_string(reason)));
+        json
+    }
+}
+
+/// Replaces the attribute values of a DN that are equal to the password.
+fn redact_dn(dn: &str, password: &str) -> String {
+    let mut components = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (idx, c) in dn.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' => escaped = true,
+            ',' => {
+                components.push(&dn[start..idx]);
+                start = idx + 1;
+            }
+            _ => {}
+        }
+    }
+    components.push(&dn[start..]);
+
+    components
+        .into_iter()
+        .map(|component| match component.split_once('=') {
+            Some((attribute, value)) if value == password => format!("{attribute}={REDACTED}"),
+            _ => component.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn json_string(value: &str) -> String {
+    let mut res = String::with_capacity(value.len() + 2);
+    res.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            '\r' => res.push_str("\\r"),
+            '\t' => res.push_str("\\t"),
+            c if (c as u32) < 0x20 => res.push_str(&format!("\\u{:04x}", c as u32)),
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
+
+fn json_opt_string(value: Option<&str>) -> String {
+    match value {
+        Some(value) => json_string(value),
+        None => "null".to_string(),
+    }
+}
+
+/// Formats the timestamp as an RFC 3339 UTC date with millisecond precision.
+fn format_timestamp(timestamp: SystemTime) -> String {
+    let since_epoch = timestamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let days = (secs / 86400) as i64;
+    let secs_of_day = secs % 86400;
+
+    // Converts the number of days since the epoch to a civil date, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        secs_of_day / 3600,
+        (secs_of_day % 3600) / 60,
+        secs_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Returns the hash of a record: the HMAC-SHA256 of the previous hash followed
+/// by the record text when a key is configured, and a plain SHA-256 otherwise.
+fn chain_hash(key: Option<&[u8]>, prev_hash: &str, payload: &str) -> String {
+    match key {
+        Some(key) => {
+            let mut mac =
+                Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+            mac.update(prev_hash.as_bytes());
+            mac.update(payload.as_bytes());
+            hex_encode(&mac.finalize().into_bytes())
+        }
+        None => {
+            let mut hasher = Sha256::new();
+            hasher.update(prev_hash.as_bytes());
+            hasher.update(payload.as_bytes());
+            hex_encode(&hasher.finalize())
+        }
+    }
+}
+
+/// Splits an audit log line into the record text covered by the hash and the
+/// hash itself.
+fn split_record(line: &str) -> Option<(&str, &str)> {
+    let start = line.rfind(",\"hash\":\"")?;
+    let hash_start = start + ",\"hash\":\"".len();
+    let hash = line.get(hash_start..hash_start + GENESIS_HASH.len())?;
+    Some((&line[..start], hash))
+}
+
+/// Returns the last `count` records of an audit log file. The file is read
+/// backwards from its end, so that opening a large file stays cheap.
+fn read_last_records(path: &Path, count: usize) -> io::Result<Vec<String>> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+
+    // Reads until the newline before the first of the records is found, or
+    // the start of the file is reached.
+    let mut pos = file.seek(SeekFrom::End(0))?;
+    let mut tail = Vec::new();
+    while pos > 0 && tail.iter().filter(|b| **b == b'\n').count() <= count {
+        let len = pos.min(TAIL_CHUNK_SIZE);
+        pos -= len;
+        let mut chunk = vec![0; len as usize];
+        file.seek(SeekFrom::Start(pos))?;
+        file.read_exact(&mut chunk)?;
+        chunk.extend_from_slice(&tail);
+        tail = chunk;
+    }
+
+    let mut records: Vec<String> = String::from_utf8_lossy(&tail)
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect();
+    Ok(records.split_off(records.len().saturating_sub(count)))
+}
+
+fn rotated_path(path: &Path, idx: u32) -> PathBuf {
+    PathBuf::from(format!("{}.{idx}", path.display()))
+}
+
+fn read_key(path: &Path) -> io::Result<Vec<u8>> {
+    let mut key = fs::read(path)?;
+    while matches!(key.last(), Some(b'\n' | b'\r')) {
+        key.pop();
+    }
+    if key.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the key is empty",
+        ));
+    }
+    Ok(key)
+}
+
+struct AuditLogWriter {
+    settings: Option<AuditSettings>,
+    key: Option<Vec<u8>>,
+    file: Option<File>,
+    size: u64,
+    last_hash: String,
+}
+
+impl AuditLogWriter {
+    fn new() -> AuditLogWriter {
+        AuditLogWriter {
+            settings: None,
+            key: None,
+            file: None,
+            size: 0,
+            last_hash: GENESIS_HASH.to_string(),
+        }
+    }
+
+    fn configure(&mut self, settings: AuditSettings) {
+        let (path_changed, key_changed) = match &self.settings {
+            Some(current) => (
+                current.path != settings.path,
+                current.key_file != settings.key_file,
+            ),
+            None => (true, true),
+        };
+
+        if key_changed {
+            self.key = match &settings.key_file {
+                Some(key_file) => match read_key(key_file) {
+                    Ok(key) => Some(key),
+                    Err(err) => {
+                        error!(
+                            "failed to read the audit log key file {}: {err}",
+                            key_file.display()
+                        );
+                        None
+                    }
+                },
+                None => None,
+            };
+            if settings.path.is_some() && self.key.is_none() {
+                warn!(
+                    "the audit log records are hashed without a key, set ldap.audit_log_key_file to make the log tamper-evident"
+                );
+            }
+        }
+        self.settings = Some(settings);
+
+        if path_changed {
+            self.file = None;
+            if let Err(err) = self.open() {
+                error!("failed to open the audit log file: {err}");
+            }
+        }
+    }
+
+    /// Opens the audit log file and continues its hash chain from its last
+    /// record, or from the last record of the most recent rotated file when it
+    /// is empty. Only the hash of that record is checked, so that a log edited
+    /// at its end while the server was not running is reported; checking the
+    /// whole chain is left to the readers of the log.
+    fn open(&mut self) -> io::Result<()> {
+        let Some(path) = self.settings.as_ref().and_then(|s| s.path.clone()) else {
+            return Ok(());
+        };
+
+        let mut records = read_last_records(&path, 2)?;
+        if records.len() < 2 {
+            let mut rotated = read_last_records(&rotated_path(&path, 1), 2 - records.len())?;
+            rotated.append(&mut records);
+            records = rotated;
+        }
+        self.last_hash = self.continue_chain(&path, &records);
+
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        self.size = file.metadata()?.len();
+        self.file = Some(file);
+
+        debug!("opened audit log file {}", path.display());
+        Ok(())
+    }
+
+    /// Returns the hash of the last of the `records`, after checking it
+    /// against the hash of the record before it, if any.
+    fn continue_chain(&self, path: &Path, records: &[String]) -> String {
+        let Some(last) = records.last() else {
+            return GENESIS_HASH.to_string();
+        };
+        let prev_hash = match records.len() {
+            1 => Some(GENESIS_HASH),
+            _ => split_record(&records[0]).map(|(_, hash)| hash),
+        };
+
+        match (split_record(last), prev_hash) {
+            (Some((payload, hash)), Some(prev_hash)) => {
+                if chain_hash(self.key.as_deref(), prev_hash, payload) != hash {
+                    error!(
+                        "the hash of the last record of the audit log file {} does not match",
+                        path.display()
+                    );
+                }
+                hash.to_string()
+            }
+            (Some((_, hash)), None) => hash.to_string(),
+            (None, _) => {
+                error!(
+                    "the last record of the audit log file {} has no hash",
+                    path.display()
+                );
+                prev_hash.unwrap_or(GENESIS_HASH).to_string()
+            }
+        }
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let Some(settings) = self.settings.clone() else {
+            return Ok(());
+        };
+        let Some(path) = settings.path else {
+            return Ok(());
+        };
+
+        self.file = None;
+
+        let _ = fs::remove_file(rotated_path(&path, settings.max_files));
+        for idx in (1..settings.max_files).rev() {
+            let from = rotated_path(&path, idx);
+            if from.exists() {
+                fs::rename(&from, rotated_path(&path, idx + 1))?;
+            }
+        }
+        fs::rename(&path, rotated_path(&path, 1))?;
+
+        // The hash chain continues in the new file, so that removing a rotated
+        // file from the sequence is also detected.
+        let last_hash = self.last_hash.clone();
+        self.open()?;
+        self.last_hash = last_hash;
+        Ok(())
+    }
+
+    fn write(&mut self, record: AuditRecord) -> io::Result<()> {
+        let Some(settings) = &self.settings else {
+            return Ok(());
+        };
+        if settings.path.is_none() || !record.is_recorded(&settings.events) {
+            return Ok(());
+        }
+        let max_size = settings.max_size;
+
+        if self.file.is_none() {
+            self.open()?;
+        }
+
+        let mut json = record.to_json_fields();
+        let line_len = json.len() + ",\"hash\":\"\"}\n".len() + GENESIS_HASH.len();
+        if max_size > 0 && self.size > 0 && self.size + line_len as u64 > max_size {
+            self.rotate()?;
+        }
+
+        // Each record is chained to the previous one by including the hash of
+        // the previous record in the hash of the current record.
+        let hash = chain_hash(self.key.as_deref(), &self.last_hash, &json);
+        json.push_str(&format!(",\"hash\":\"{hash}\"}}\n"));
+
+        if let Some(file) = &mut self.file {
+            file.write_all(json.as_bytes())?;
+            self.size += json.len() as u64;
+            self.last_hash = hash;
+        }
+
+        Ok(())
+    }
+}
+
+enum AuditMessage {
+    Configure(AuditSettings),
+    Record(AuditRecord),
+    Shutdown,
+}
+
+fn audit_log_loop(rx: mpsc::Receiver<AuditMessage>) {
+    debug!("initiating audit log loop");
+
+    let mut writer = AuditLogWriter::new();
+
+    while let Ok(msg) = rx.recv() {
+        match msg {
+            AuditMessage::Configure(settings) => writer.configure(settings),
+            AuditMessage::Record(record) => {
+                if let Err(err) = writer.write(record) {
+                    error!("failed to write to the audit log: {err}");
+                }
+            }
+            AuditMessage::Shutdown => break,
+        }
+    }
+
+    debug!("exiting audit log loop");
+}
+
+struct AuditLog {
+    sender: Option<mpsc::Sender<AuditMessage>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+lazy_static! {
+    static ref AUDIT_LOG: Mutex<AuditLog> = Mutex::new(AuditLog {
+        sender: None,
+        thread: None,
+    });
+}
+
+fn send_message(msg: AuditMessage) {
+    let audit_log = AUDIT_LOG.lock().unwrap();
+    if let Some(sender) = &audit_log.sender {
+        if sender.send(msg).is_err() {
+            error!("the audit log thread is not running");
+        }
+    }
+}
+
+/// Starts the thread that writes the audit log. Records are written outside
+/// of the main thread to avoid blocking it on disk I/O.
+pub fn start_audit_log() {
+    let mut audit_log = AUDIT_LOG.lock().unwrap();
+
+    let (tx, rx) = mpsc::channel();
+    let res = thread::Builder::new()
+        .name("ldap-audit-log".to_string())
+        .spawn(move || audit_log_loop(rx));
+
+    match res {
+        Ok(handle) => {
+            audit_log.sender = Some(tx);
+            audit_log.thread = Some(handle);
+        }
+        Err(err) => error!("failed to start the audit log thread: {err}"),
+    }
+}
+
+pub fn stop_audit_log() {
+    let mut audit_log = AUDIT_LOG.lock().unwrap();
+
+    if let Some(sender) = audit_log.sender.take() {
+        let _ = sender.send(AuditMessage::Shutdown);
+    }
+    if let Some(handle) = audit_log.thread.take() {
+        if handle.join().is_err() {
+            error!("failed to stop the audit log thread");
+        }
+    }
+}
+
+pub fn configure_audit_log(settings: AuditSettings) {
+    send_message(AuditMessage::Configure(settings));
+}
+
+pub fn record_auth_event(record: AuditRecord) {
+    send_message(AuditMessage::Record(record));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks the hash chain of an audit log file, starting from the hash of
+    /// the last record of the previous file. Returns the hash of the last
+    /// record, and the line number of the first record that does not match.
+    fn verify_chain(key: Option<&[u8]>, path: &Path, prev_hash: &str) -> (String, Option<usize>) {
+        let mut last_hash = prev_hash.to_string();
+        let mut broken_at = None;
+        let content = fs::read_to_string(path).unwrap();
+        for (idx, line) in content.lines().enumerate() {
+            let Some((payload, hash)) = split_record(line) else {
+                broken_at.get_or_insert(idx + 1);
+                continue;
+            };
+            if broken_at.is_none() && chain_hash(key, &last_hash, payload) != hash {
+                broken_at = Some(idx + 1);
+            }
+            last_hash = hash.to_string();
+        }
+        (last_hash, broken_at)
+    }
+
+    fn test_record(username: &str) -> AuditRecord {
+        AuditRecord {
+            timestamp: SystemTime::now(),
+            event: AuditEvent::Auth,
+            username: username.to_string(),
+            user_dn: None,
+            client_id: 1,
+            client_addr: None,
+            server: None,
+            latency: Duration::from_millis(1),
+            outcome: Ok(()),
+        }
+    }
+
+    #[test]
+    fn test_redact() {
+        let mut record = test_record("user1@123");
+        record.user_dn = Some("cn=user1@123,ou=devops,dc=valkey,dc=io".to_string());
+        record.redact("user1@123");
+        assert_eq!(record.username, REDACTED);
+        assert_eq!(
+            record.user_dn.as_deref(),
+            Some("cn=[REDACTED],ou=devops,dc=valkey,dc=io")
+        );
+
+        // A password that is only part of the fields is not redacted.
+        let mut record = test_record("alice");
+        record.user_dn = Some("cn=alice\\,a,ou=devops,dc=valkey,dc=io".to_string());
+        record.redact("a");
+        assert_eq!(record.username, "alice");
+        assert_eq!(
+            record.user_dn.as_deref(),
+            Some("cn=alice\\,a,ou=devops,dc=valkey,dc=io")
+        );
+    }
+
+    #[test]
+    fn test_hash_chain_across_rotation_and_restart() {
+        let dir = std::env::temp_dir().join(format!("ldap-audit-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("audit.log");
+        let key_file = dir.join("audit.key");
+        fs::write(&key_file, "secret\n").unwrap();
+
+        let settings = AuditSettings {
+            path: Some(path.clone()),
+            key_file: Some(key_file.clone()),
+            events: LdapAuditEvents::All,
+            max_size: 400,
+            max_files: 5,
+        };
+        let mut writer = AuditLogWriter::new();
+        writer.configure(settings.clone());
+        for idx in 0..4 {
+            writer.write(test_record(&format!("user{idx}"))).unwrap();
+        }
+        assert!(rotated_path(&path, 1).exists());
+
+        // A new writer, as after a restart, continues the chain.
+        let mut writer = AuditLogWriter::new();
+        writer.configure(settings);
+        writer.write(test_record("user4")).unwrap();
+
+        let key = read_key(&key_file).unwrap();
+        let mut last_hash = GENESIS_HASH.to_string();
+        let rotated: Vec<u32> = (1..=5)
+            .filter(|idx| rotated_path(&path, *idx).exists())
+            .collect();
+        assert!(rotated.len() > 1);
+        for idx in rotated.into_iter().rev() {
+            let (hash, broken_at) = verify_chain(Some(&key), &rotated_path(&path, idx), &last_hash);
+            assert_eq!(broken_at, None);
+            last_hash = hash;
+        }
+        let (_, broken_at) = verify_chain(Some(&key), &path, &last_hash);
+        assert_eq!(broken_at, None);
+
+        // The hashes cannot be recomputed without the key.
+        let (_, broken_at) = verify_chain(None, &path, &last_hash);
+        assert_eq!(broken_at, Some(1));
+
+        // Editing a record breaks the chain.
+        let content = fs::read_to_string(&path).unwrap();
+        fs::write(&path, content.replacen("user", "resu", 1)).unwrap();
+        let (_, broken_at) = verify_chain(Some(&key), &path, &last_hash);
+        assert_eq!(broken_at, Some(1));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_read_last_records() {
+        let dir = std::env::temp_dir().join(format!("ldap-audit-tail-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("audit.log");
+
+        assert!(read_last_records(&path, 2).unwrap().is_empty());
+
+        // The records span several chunks of the backwards read.
+        let lines: Vec<String> = (0..1000).map(|idx| format!("record {idx}")).collect();
+        fs::write(&path, lines.join("\n") + "\n").unwrap();
+        assert_eq!(
+            read_last_records(&path, 2).unwrap(),
+            vec!["record 998", "record 999"]
+        );
+        assert_eq!(read_last_records(&path, 1000).unwrap(), lines);
+
+        fs::write(&path, "record 0\n").unwrap();
+        assert_eq!(read_last_records(&path, 2).unwrap(), vec!["record 0"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,32 +1,191 @@
-use std::os::raw::c_int;
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+use std::sync::Mutex;
+use std::time::SystemTime;
 
-use log::{debug, error};
+use lazy_static::lazy_static;
+use log::{debug, error, warn};
 use valkey_module::BlockedClient;
-use valkey_module::{AUTH_HANDLED, AUTH_NOT_HANDLED, Context, Status, ValkeyError, ValkeyString};
+use valkey_module::{
+    AUTH_HANDLED, AUTH_NOT_HANDLED, Context, Status, ValkeyError, ValkeyString, raw,
+};
 
+use crate::audit::{self, AuditEvent, AuditRecord};
 use crate::configs;
-use crate::vkldap;
-use crate::vkldap::errors::VkLdapError;
+use crate::events;
+use crate::sessions;
+use crate::stats;
+use crate::vkldap::errors::{VkLdapError, VkLdapFailureReason};
+use crate::vkldap::scheduler::TaskHandle;
+use crate::vkldap::{self, VkLdapAuthInfo, VkLdapAuthResult};
+
+lazy_static! {
+    // The LDAP tasks of the clients that are blocked waiting for authentication,
+    // indexed by client ID.
+    static ref PENDING_AUTH_TASKS: Mutex<HashMap<u64, TaskHandle>> = Mutex::new(HashMap::new());
+}
+
+/// Cancels the LDAP task of a client that disconnected before the
+/// authentication finished, so that it stops using LDAP resources.
+pub fn on_client_disconnected(client_id: u64) {
+    let task = PENDING_AUTH_TASKS.lock().unwrap().remove(&client_id);
+    if let Some(task) = task {
+        debug!("client {client_id} disconnected, cancelling its LDAP authentication");
+        task.cancel();
+    }
+}
+
+/// Adds an authentication failure entry to the ACL LOG. The client info of the
+/// entry is filled by Valkey from the client being authenticated, and the
+/// object field holds the reason of the failure.
+pub fn add_acl_log_entry(ctx: &Context, username: &ValkeyString, reason: VkLdapFailureReason) {
+    let object = ctx.create_string(format!("ldap:{reason}"));
+    unsafe {
+        raw::RedisModule_ACLAddLogEntryByUserName.unwrap()(
+            ctx.ctx,
+            username.inner,
+            object.inner,
+            raw::RedisModuleACLLogEntryReason_REDISMODULE_ACL_LOG_AUTH,
+        );
+    }
+}
+
+extern "C" fn client_user_changed_callback(client_id: u64, _privdata: *mut c_void) {
+    sessions::on_client_user_changed(client_id);
+}
+
+/// Authenticates the client of the context as the ACL user. Unlike
+/// `Context::authenticate_client_with_acl_user`, it registers a callback that
+/// Valkey calls when the client changes its user, so that the LDAP session of
+/// the client is removed as soon as it re-authenticates.
+fn authenticate_client(ctx: &Context, username: &ValkeyString) -> Status {
+    let res = unsafe {
+        raw::RedisModule_AuthenticateClientWithACLUser.unwrap()(
+            ctx.ctx,
+            username.as_slice().as_ptr().cast::<c_char>(),
+            username.len(),
+            Some(client_user_changed_callback),
+            ptr::null_mut(),
+            ptr::null_mut(),
+        )
+    };
+
+    if res == raw::REDISMODULE_OK as c_int {
+        Status::Ok
+    } else {
+        Status::Err
+    }
+}
+
+/// Returns the address of the client of the context, as `ip:port`.
+pub fn get_client_addr(ctx: &Context) -> Option<String> {
+    ctx.get_client_info().ok().map(|client_info| {
+        let addr = unsafe { CStr::from_ptr(client_info.addr.as_ptr()) };
+        format!("{}:{}", addr.to_string_lossy(), client_info.port)
+    })
+}
+
+/// Records the authentication attempt in the statistics and in the audit log,
+/// and publishes it to the configured stream and channel. The client address
+/// is taken from the client being authenticated.
+fn record_auth_event(
+    ctx: &Context,
+    username: &ValkeyString,
+    password: &ValkeyString,
+    info: &VkLdapAuthInfo,
+    outcome: Result<(), VkLdapFailureReason>,
+) {
+    let mut record = AuditRecord {
+        timestamp: SystemTime::now(),
+        event: AuditEvent::Auth,
+        username: username.to_string(),
+        user_dn: info.user_dn.clone(),
+        client_id: ctx.get_client_id(),
+        client_addr: get_client_addr(ctx),
+        server: info.server.clone(),
+        latency: info.latency,
+        outcome,
+    };
+    record.redact(&password.to_string());
+
+    match outcome {
+        Ok(_) => stats::record_auth_success(),
+        Err(reason) => stats::record_auth_failure(reason),
+    }
+
+    events::publish_auth_event(ctx, &record);
+    audit::record_auth_event(record);
+}
+
+/// Logs and counts the password policy warnings sent by the LDAP server on a
+/// successful bind.
+fn handle_password_policy_warnings(username: &ValkeyString, info: &VkLdapAuthInfo) {
+    if let Some(expires_in) = info.password_expires_in {
+        warn!(
+            "the password of LDAP user {username} expires in {} seconds",
+            expires_in.as_secs()
+        );
+        stats::record_password_expiration_warning();
+    }
+    if let Some(grace_logins) = info.grace_logins_remaining {
+        warn!(
+            "LDAP user {username} logged in with an expired password, {grace_logins} grace logins remaining"
+        );
+        stats::record_grace_login();
+    }
+}
 
 fn auth_reply_callback(
     ctx: &Context,
     username: ValkeyString,
-    _: ValkeyString,
-    priv_data: Option<&Result<(), VkLdapError>>,
+    password: ValkeyString,
+    priv_data: Option<&VkLdapAuthResult>,
 ) -> Result<c_int, ValkeyError> {
-    if let Some(res) = priv_data {
-        match res {
-            Ok(_) => match ctx.authenticate_client_with_acl_user(&username) {
+    if let Some(VkLdapAuthResult { result, info }) = priv_data {
+        match result {
+            Ok(_) => match authenticate_client(ctx, &username) {
                 Status::Ok => {
                     debug!("successfully authenticated LDAP user {username}");
+                    handle_password_policy_warnings(&username, info);
+                    sessions::on_client_authenticated(
+                        ctx.get_client_id(),
+                        username.to_string(),
+                        info.user_dn.clone(),
+                    );
+                    record_auth_event(ctx, &username, &password, info, Ok(()));
                     Ok(AUTH_HANDLED)
                 }
-                Status::Err => Err(ValkeyError::Str("Failed to authenticate with ACL")),
+                Status::Err => {
+                    let reason = VkLdapFailureReason::UserNotFound;
+                    add_acl_log_entry(ctx, &username, reason);
+                    record_auth_event(ctx, &username, &password, info, Err(reason));
+                    Err(ValkeyError::Str("Failed to authenticate with ACL"))
+                }
             },
+            Err(err @ VkLdapError::AuthTimeout(_)) => {
+                error!("LDAP authentication timed out for user {username}");
+                add_acl_log_entry(ctx, &username, err.failure_reason());
+                record_auth_event(ctx, &username, &password, info, Err(err.failure_reason()));
+                Err(ValkeyError::Str("LDAP authentication timed out"))
+            }
             Err(err) => {
                 debug!("failed to authenticate LDAP user {username}");
                 error!("LDAP authentication failure: {err}");
-                Ok(AUTH_NOT_HANDLED)
+                let reason = err.failure_reason();
+                record_auth_event(ctx, &username, &password, info, Err(reason));
+
+                // When the module does not handle the authentication, Valkey
+                // checks the password of the ACL user, and adds its own ACL
+                // LOG entry if that fails too.
+                if configs::is_acl_password_fallback_enabled(ctx) && !reason.is_definitive() {
+                    return Ok(AUTH_NOT_HANDLED);
+                }
+                add_acl_log_entry(ctx, &username, reason);
+                Err(ValkeyError::Str(
+                    "-WRONGPASS invalid username-password pair or user is disabled.",
+                ))
             }
         }
     } else {
@@ -36,7 +195,7 @@ fn auth_reply_callback(
     }
 }
 
-fn free_callback(_: &Context, _: Result<(), VkLdapError>) {}
+fn free_callback(_: &Context, _: VkLdapAuthResult) {}
 
 pub fn ldap_auth_blocking_callback(
     ctx: &Context,
@@ -53,10 +212,13 @@ pub fn ldap_auth_blocking_callback(
 
     let user_str = username.to_string();
     let pass_str = password.to_string();
+    let client_id = ctx.get_client_id();
 
     let blocked_client = ctx.block_client_on_auth(auth_reply_callback, Some(free_callback));
 
-    let callback = move |blocked_client: Option<BlockedClient<Result<(), VkLdapError>>>, result| {
+    let callback = move |blocked_client: Option<BlockedClient<VkLdapAuthResult>>, result| {
+        PENDING_AUTH_TASKS.lock().unwrap().remove(&client_id);
+
         assert!(blocked_client.is_some());
         let mut blocked_client = blocked_client.unwrap();
         if let Err(e) = blocked_client.set_blocked_private_data(result) {
@@ -64,6 +226,10 @@ pub fn ldap_auth_blocking_callback(
         }
     };
 
+    // We hold the lock while submitting the task to guarantee that the task
+    // handle is registered before the callback tries to remove it.
+    let mut pending_tasks = PENDING_AUTH_TASKS.lock().unwrap();
+
     let res = if use_bind_mode {
         vkldap::vk_ldap_bind(user_str, pass_str, callback, blocked_client)
     } else {
@@ -71,7 +237,10 @@ pub fn ldap_auth_blocking_callback(
     };
 
     match res {
-        Ok(_) => Ok(AUTH_HANDLED),
+        Ok(task) => {
+            pending_tasks.insert(client_id, task);
+            Ok(AUTH_HANDLED)
+        }
         Err(err) => {
             error!("failed to submit ldap bind request: {err}");
             Ok(AUTH_NOT_HANDLED)
@@ -0,0 +1,43 @@
+use std::os::raw::c_void;
+
+use valkey_module::{Context, Status, raw};
+
+use crate::auth;
+use crate::sessions;
+
+// The `client_changed_event_handler` macro from valkey-module does not expose
+// the ID of the client that triggered the event. Therefore, we subscribe to the
+// client change event directly to know which client disconnected.
+extern "C" fn client_change_callback(
+    _ctx: *mut raw::RedisModuleCtx,
+    _eid: raw::RedisModuleEvent,
+    subevent: u64,
+    data: *mut c_void,
+) {
+    if subevent != raw::REDISMODULE_SUBEVENT_CLIENT_CHANGE_DISCONNECTED || data.is_null() {
+        return;
+    }
+
+    let client_info = unsafe { &*(data as *const raw::RedisModuleClientInfo) };
+    auth::on_client_disconnected(client_info.id);
+    sessions::on_client_disconnected(client_info.id);
+}
+
+pub fn subscribe_to_client_events(ctx: &Context) -> Status {
+    let res = unsafe {
+        raw::RedisModule_SubscribeToServerEvent.unwrap()(
+            ctx.ctx,
+            raw::RedisModuleEvent {
+                id: raw::REDISMODULE_EVENT_CLIENT_CHANGE,
+                dataver: 1,
+            },
+            Some(client_change_callback),
+        )
+    };
+
+    if res == raw::REDISMODULE_OK as i32 {
+        Status::Ok
+    } else {
+        Status::Err
+    }
+}
@@ -1,8 +1,397 @@
-use log::error;
-use valkey_module::{InfoContext, ValkeyError, ValkeyResult};
+use std::os::raw::c_int;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::{debug, error, warn};
+use valkey_module::{
+    BlockedClient, Context, InfoContext, NextArg, ThreadSafeContext, ValkeyError, ValkeyResult,
+    ValkeyString, ValkeyValue, raw,
+};
 use valkey_module_macros::info_command_handler;
 
-use crate::vkldap::{get_servers_health_status, server::VkLdapServerStatus};
+use crate::audit::{self, AuditEvent, AuditRecord};
+use crate::auth;
+use crate::configs::{self, LdapSearchScope};
+use crate::sessions;
+use crate::stats;
+use crate::vkldap::errors::VkLdapError;
+use crate::vkldap::{
+    self, VkLdapAuthResult, VkLdapSearchEntry, VkLdapSearchRequest, VkLdapServerAction,
+    VkLdapServerDetails, get_servers_health_status, server::VkLdapServerStatus,
+};
+
+const LDAP_SEARCH_DEFAULT_SIZE_LIMIT: i64 = 100;
+
+/// Whether the ACL user of the client may change the LDAP password of any
+/// user, which requires the same permission as changing the password of any
+/// ACL user, i.e., running `ACL SETUSER`.
+fn can_change_any_password(ctx: &Context) -> bool {
+    let username = ctx.get_current_user();
+    let user = unsafe { raw::RedisModule_GetModuleUserFromUserName.unwrap()(username.inner) };
+    if user.is_null() {
+        return false;
+    }
+
+    let command = [ctx.create_string("ACL"), ctx.create_string("SETUSER")];
+    let mut argv = command.iter().map(|arg| arg.inner).collect::<Vec<_>>();
+    let res = unsafe {
+        raw::RedisModule_ACLCheckCommandPermissions.unwrap()(
+            user,
+            argv.as_mut_ptr(),
+            argv.len() as c_int,
+        )
+    };
+    unsafe { raw::RedisModule_FreeModuleUser.unwrap()(user) };
+
+    res == raw::REDISMODULE_OK as c_int
+}
+
+/// Adds a denied command entry to the ACL LOG, for the client that tried to
+/// change the password of another user.
+fn add_acl_log_denied_entry(ctx: &Context) {
+    let username = ctx.get_current_user();
+    let object = ctx.create_string("ldap.passwd");
+    unsafe {
+        raw::RedisModule_ACLAddLogEntryByUserName.unwrap()(
+            ctx.ctx,
+            username.inner,
+            object.inner,
+            raw::RedisModuleACLLogEntryReason_REDISMODULE_ACL_LOG_CMD,
+        );
+    }
+}
+
+/// LDAP.PASSWD [username] <old password> <new password>
+///
+/// Changes the LDAP password of the user with the Password Modify extended
+/// operation. Without a username, the password of the LDAP user the client
+/// authenticated with is changed. The password of another user can only be
+/// changed by the clients allowed to run `ACL SETUSER`.
+pub fn ldap_passwd_command(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
+    let session_username = sessions::get_session_username(ctx.get_client_id());
+    let (username, old_password, new_password) = match args.len() {
+        3 => match session_username {
+            Some(username) => (username, args[1].to_string(), args[2].to_string()),
+            None => {
+                return Err(ValkeyError::Str(
+                    "ERR the client is not authenticated with an LDAP user, the username must be specified",
+                ));
+            }
+        },
+        4 => {
+            let username = args[1].to_string();
+            if session_username.as_ref() != Some(&username) && !can_change_any_password(ctx) {
+                warn!(
+                    "denied the change of the LDAP password of user {username} to Valkey user {}",
+                    ctx.get_current_user()
+                );
+                add_acl_log_denied_entry(ctx);
+                return Err(ValkeyError::Str(
+                    "NOPERM only the password of the LDAP user the client authenticated with can be changed",
+                ));
+            }
+            (username, args[2].to_string(), args[3].to_string())
+        }
+        _ => return Err(ValkeyError::WrongArity),
+    };
+
+    debug!("changing the password of LDAP user {username}");
+
+    let use_bind_mode = configs::is_bind_mode(ctx);
+    let client_id = ctx.get_client_id();
+    let client_addr = auth::get_client_addr(ctx);
+    let blocked_client = ctx.block_client();
+
+    // The attempts are recorded like the AUTH ones, since the command checks
+    // the old password of the user.
+    let passwd_username = username.clone();
+    let passwords = [old_password.clone(), new_password.clone()];
+    let callback = move |blocked_client: Option<BlockedClient>, auth_res: VkLdapAuthResult| {
+        assert!(blocked_client.is_some());
+        let thread_ctx = ThreadSafeContext::with_blocked_client(blocked_client.unwrap());
+        let VkLdapAuthResult { result, info } = auth_res;
+        let outcome = result
+            .as_ref()
+            .map(|_| ())
+            .map_err(VkLdapError::failure_reason);
+
+        if let Err(reason) = outcome {
+            let ctx = thread_ctx.lock();
+            let username = ctx.create_string(passwd_username.as_str());
+            auth::add_acl_log_entry(&ctx, &username, reason);
+        }
+
+        let mut record = AuditRecord {
+            timestamp: SystemTime::now(),
+            event: AuditEvent::PasswordChange,
+            username: passwd_username.clone(),
+            user_dn: info.user_dn,
+            client_id,
+            client_addr: client_addr.clone(),
+            server: info.server,
+            latency: info.latency,
+            outcome,
+        };
+        for password in &passwords {
+            record.redact(password);
+        }
+        audit::record_auth_event(record);
+
+        match result {
+            Ok(_) => thread_ctx.reply(Ok(ValkeyValue::SimpleStringStatic("OK"))),
+            Err(err) => {
+                error!("failed to change the LDAP password: {err}");
+                thread_ctx.reply(Err(ValkeyError::String(format!("ERR {err}"))))
+            }
+        };
+    };
+
+    let res = vkldap::vk_ldap_modify_password(
+        username,
+        old_password,
+        new_password,
+        use_bind_mode,
+        callback,
+        blocked_client,
+    );
+
+    match res {
+        Ok(_) => Ok(ValkeyValue::NoReply),
+        Err(err) => {
+            error!("failed to submit ldap password modify request: {err}");
+            Err(ValkeyError::String(format!("ERR {err}")))
+        }
+    }
+}
+
+fn search_entry_to_value(entry: VkLdapSearchEntry) -> ValkeyValue {
+    let mut attributes = Vec::with_capacity(entry.attributes.len() * 2);
+    for (name, values) in entry.attributes {
+        attributes.push(ValkeyValue::BulkString(name));
+        attributes.push(ValkeyValue::Array(
+            values.into_iter().map(ValkeyValue::StringBuffer).collect(),
+        ));
+    }
+    ValkeyValue::Array(vec![
+        ValkeyValue::BulkString(entry.dn),
+        ValkeyValue::Array(attributes),
+    ])
+}
+
+/// LDAP.SEARCH <base> <base|one|sub> <filter> [SIZELIMIT <n>] [ATTRIBUTES <attr> ...]
+///
+/// Runs a search with the service account and the pooled connections of the
+/// module, to troubleshoot the search settings. Each entry is replied as the
+/// DN followed by a flat list of attribute names and their values.
+pub fn ldap_search_command(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
+    let mut args = args.into_iter().skip(1);
+
+    let base = args.next_string()?;
+    let scope = match args.next_string()?.to_lowercase().as_str() {
+        "base" => LdapSearchScope::Base,
+        "one" => LdapSearchScope::OneLevel,
+        "sub" => LdapSearchScope::SubTree,
+        _ => return Err(ValkeyError::Str("ERR the scope must be base, one or sub")),
+    };
+    let filter = args.next_string()?;
+
+    let mut size_limit = LDAP_SEARCH_DEFAULT_SIZE_LIMIT;
+    let mut attributes = Vec::new();
+    while let Ok(option) = args.next_string() {
+        match option.to_uppercase().as_str() {
+            "SIZELIMIT" => {
+                size_limit = args.next_i64()?;
+                if !(0..=i32::MAX as i64).contains(&size_limit) {
+                    return Err(ValkeyError::Str("ERR the size limit is out of range"));
+                }
+            }
+            "ATTRIBUTES" => {
+                attributes.extend(args.by_ref().map(|arg| arg.to_string_lossy()));
+                if attributes.is_empty() {
+                    return Err(ValkeyError::WrongArity);
+                }
+            }
+            _ => {
+                return Err(ValkeyError::String(format!(
+                    "ERR unknown option '{option}'"
+                )));
+            }
+        }
+    }
+
+    let request = VkLdapSearchRequest {
+        base,
+        scope,
+        filter,
+        attributes,
+        size_limit: size_limit as i32,
+    };
+
+    let blocked_client = ctx.block_client();
+
+    let callback = |blocked_client: Option<BlockedClient>,
+                    result: Result<Vec<VkLdapSearchEntry>, VkLdapError>| {
+        assert!(blocked_client.is_some());
+        let thread_ctx = ThreadSafeContext::with_blocked_client(blocked_client.unwrap());
+        match result {
+            Ok(entries) => thread_ctx.reply(Ok(ValkeyValue::Array(
+                entries.into_iter().map(search_entry_to_value).collect(),
+            ))),
+            Err(err) => thread_ctx.reply(Err(ValkeyError::String(format!("ERR {err}")))),
+        };
+    };
+
+    match vkldap::vk_ldap_search(request, callback, blocked_client) {
+        Ok(_) => Ok(ValkeyValue::NoReply),
+        Err(err) => {
+            error!("failed to submit ldap search request: {err}");
+            Err(ValkeyError::String(format!("ERR {err}")))
+        }
+    }
+}
+
+fn server_details_to_value(details: VkLdapServerDetails) -> ValkeyValue {
+    let server = &details.server;
+
+    let status = match server.get_status() {
+        _ if server.is_marked_down() => "down",
+        VkLdapServerStatus::HEALTHY => "healthy",
+        VkLdapServerStatus::DEGRADED(_) => "degraded",
+        VkLdapServerStatus::UNHEALTHY(_) => "unhealthy",
+    };
+    let last_transition = server
+        .get_last_transition()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    let string = |value: &str| ValkeyValue::BulkString(value.to_string());
+    let integer = |value: u64| ValkeyValue::Integer(value as i64);
+
+    let fields = vec![
+        ("url", string(server.get_url_ref().as_str())),
+        ("id", integer(server.get_id() as u64)),
+        ("status", string(status)),
+        (
+            "last_error",
+            server.get_last_error().map_or(ValkeyValue::Null, string),
+        ),
+        (
+            "last_transition_ms",
+            integer(last_transition.as_millis() as u64),
+        ),
+        (
+            "ping_time_ms",
+            server.get_ping_time().map_or(ValkeyValue::Null, |time| {
+                ValkeyValue::Float(time.as_micros() as f64 / 1000.0)
+            }),
+        ),
+        ("pool_size", integer(details.pool.size as u64)),
+        ("idle_connections", integer(details.pool.idle as u64)),
+        (
+            "borrowed_connections",
+            integer(details.pool.borrowed as u64),
+        ),
+        (
+            "reconnecting_connections",
+            integer(details.pool.reconnecting as u64),
+        ),
+        ("waiters", integer(details.pool.waiters as u64)),
+        ("pool_epoch", integer(details.pool.epoch)),
+        ("operations", integer(details.counters.operations)),
+        (
+            "connection_errors",
+            integer(details.counters.connection_errors),
+        ),
+    ];
+
+    ValkeyValue::Array(
+        fields
+            .into_iter()
+            .flat_map(|(name, value)| [string(name), value])
+            .collect(),
+    )
+}
+
+/// LDAP.SERVERS [DOWN|UP|REFRESH <server>]
+///
+/// Without arguments, replies with the details of each server. The
+/// subcommands mark a server down or up, or re-create the connections of its
+/// pool. The server is given by its index in `ldap.servers`, its URL or its
+/// host.
+pub fn ldap_servers_command(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
+    let mut args = args.into_iter().skip(1);
+
+    let Ok(subcommand) = args.next_string() else {
+        return match vkldap::get_servers_details() {
+            Ok(servers) => Ok(ValkeyValue::Array(
+                servers.into_iter().map(server_details_to_value).collect(),
+            )),
+            Err(err) => {
+                error!("failed to get the list of servers: {err}");
+                Err(ValkeyError::String(format!("ERR {err}")))
+            }
+        };
+    };
+
+    let action = match subcommand.to_uppercase().as_str() {
+        "DOWN" => VkLdapServerAction::MarkDown,
+        "UP" => VkLdapServerAction::MarkUp,
+        "REFRESH" => VkLdapServerAction::RefreshPool,
+        _ => {
+            return Err(ValkeyError::String(format!(
+                "ERR unknown subcommand '{subcommand}'"
+            )));
+        }
+    };
+    let server = args.next_string()?;
+    args.done()?;
+
+    let blocked_client = ctx.block_client();
+
+    let callback = |blocked_client: Option<BlockedClient>, result: Result<(), VkLdapError>| {
+        assert!(blocked_client.is_some());
+        let thread_ctx = ThreadSafeContext::with_blocked_client(blocked_client.unwrap());
+        match result {
+            Ok(_) => thread_ctx.reply(Ok(ValkeyValue::SimpleStringStatic("OK"))),
+            Err(err) => thread_ctx.reply(Err(ValkeyError::String(format!("ERR {err}")))),
+        };
+    };
+
+    match vkldap::vk_ldap_server_action(server, action, callback, blocked_client) {
+        Ok(_) => Ok(ValkeyValue::NoReply),
+        Err(err) => {
+            error!("failed to submit ldap server action: {err}");
+            Err(ValkeyError::String(format!("ERR {err}")))
+        }
+    }
+}
+
+/// LDAP.RELOADTLS
+///
+/// Reloads the TLS files from disk, and rolls the pooled connections of every
+/// server to them.
+pub fn ldap_reloadtls_command(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
+    let mut args = args.into_iter().skip(1);
+    args.done()?;
+
+    let blocked_client = ctx.block_client();
+
+    let callback = |blocked_client: Option<BlockedClient>, result: Result<bool, VkLdapError>| {
+        assert!(blocked_client.is_some());
+        let thread_ctx = ThreadSafeContext::with_blocked_client(blocked_client.unwrap());
+        match result {
+            Ok(_) => thread_ctx.reply(Ok(ValkeyValue::SimpleStringStatic("OK"))),
+            Err(err) => thread_ctx.reply(Err(ValkeyError::String(format!("ERR {err}")))),
+        };
+    };
+
+    match vkldap::vk_ldap_reload_tls(callback, blocked_client) {
+        Ok(_) => Ok(ValkeyValue::NoReply),
+        Err(err) => {
+            error!("failed to submit TLS reload: {err}");
+            Err(ValkeyError::String(format!("ERR {err}")))
+        }
+    }
+}
 
 #[info_command_handler]
 fn add_ldap_status_section(ctx: &InfoContext, _for_crash_report: bool) -> ValkeyResult<()> {
@@ -24,6 +413,9 @@ fn add_ldap_status_section(ctx: &InfoContext, _for_crash_report: bool) -> Valkey
             .field("host", server.get_host_string())?;
 
         match server.get_status() {
+            _ if server.is_marked_down() => {
+                dict = dict.field("status", "down")?;
+            }
             VkLdapServerStatus::HEALTHY => {
                 dict = dict.field("status", "healthy")?;
 
@@ -37,6 +429,17 @@ fn add_ldap_status_section(ctx: &InfoContext, _for_crash_report: bool) -> Valkey
                     None => {}
                 }
             }
+            VkLdapServerStatus::DEGRADED(reason) => {
+                dict = dict.field("status", "degraded")?;
+                dict = dict.field("reason", reason.as_str())?;
+
+                if let Some(time) = server.get_ping_time() {
+                    dict = dict.field(
+                        "ping_time_ms",
+                        (time.as_micros() as f64 / 1000.0).to_string(),
+                    )?;
+                }
+            }
             VkLdapServerStatus::UNHEALTHY(err_msg) => {
                 dict = dict.field("status", "unhealthy")?;
                 dict = dict.field("error", err_msg.as_str())?;
@@ -46,6 +449,41 @@ fn add_ldap_status_section(ctx: &InfoContext, _for_crash_report: bool) -> Valkey
         builder = dict.build_dictionary()?;
     }
 
+    let builder = builder
+        .build_section()?
+        .add_section("sessions")
+        .field(
+            "active_sessions",
+            sessions::get_active_sessions_count().to_string(),
+        )?
+        .build_section()?;
+
+    let builder = builder
+        .add_section("tls")
+        .field(
+            "tls_pin_mismatches",
+            vkldap::get_tls_pin_mismatches().to_string(),
+        )?
+        .build_section()?;
+
+    let mut builder = builder
+        .add_section("auth")
+        .field("auth_successes", stats::get_auth_successes().to_string())?
+        .field(
+            "auth_password_expiration_warnings",
+            stats::get_password_expiration_warnings().to_string(),
+        )?
+        .field("auth_grace_logins", stats::get_grace_logins().to_string())?;
+
+    let auth_failures = stats::get_auth_failures();
+    if !auth_failures.is_empty() {
+        let mut dict = builder.add_dictionary("auth_failures");
+        for (reason, count) in auth_failures {
+            dict = dict.field(reason, count.to_string())?;
+        }
+        builder = dict.build_dictionary()?;
+    }
+
     builder.build_section()?.build_info()?;
 
     Ok(())
@@ -1,16 +1,25 @@
 use std::collections::LinkedList;
+use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Duration;
 
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
 use lazy_static::lazy_static;
 use valkey_module::{
     ConfigurationValue, ValkeyError, ValkeyGILGuard, ValkeyLockIndicator, ValkeyString,
     configuration::ConfigurationContext,
 };
 
+use crate::audit::{self, AuditSettings};
+use crate::sessions;
 use crate::vkldap::failure_detector;
-use crate::vkldap::settings::VkLdapSettings;
+use crate::vkldap::passwd_file::{VkPasswordFile, parse_passwords};
+use crate::vkldap::server::{VkLdapServerOptions, find_mixed_weight_priority};
+use crate::vkldap::settings::{VkLdapSettings, VkTlsPolicy};
+use crate::vkldap::tls_reloader;
 use crate::vkldap::{self, settings::VkConnectionSettings};
-use log::{debug, error};
+use log::{debug, error, warn};
 use url::Url;
 
 macro_rules! enum_configuration2 {
@@ -72,6 +81,44 @@ enum_configuration2! {
     }
 }
 
+enum_configuration2! {
+    #[derive(PartialEq)]
+    pub enum LdapHealthCheck {
+        WhoAmI = ("whoami", 1),
+        RootDse = ("rootdse", 2),
+        ServiceBind = ("service-bind", 3),
+        Search = ("search", 4),
+    }
+}
+
+enum_configuration2! {
+    #[derive(PartialEq)]
+    pub enum LdapBindMechanism {
+        Simple = ("simple", 1),
+        External = ("external", 2),
+        Gssapi = ("gssapi", 3),
+    }
+}
+
+enum_configuration2! {
+    #[derive(PartialEq)]
+    pub enum LdapTlsVersion {
+        Tls10 = ("tls1.0", 1),
+        Tls11 = ("tls1.1", 2),
+        Tls12 = ("tls1.2", 3),
+        Tls13 = ("tls1.3", 4),
+    }
+}
+
+enum_configuration2! {
+    #[derive(PartialEq)]
+    pub enum LdapAuditEvents {
+        All = ("all", 1),
+        Failures = ("failures", 2),
+        Successes = ("successes", 3),
+    }
+}
+
 lazy_static! {
     pub static ref LDAP_SERVER_LIST: ValkeyGILGuard<ValkeyString> =
         ValkeyGILGuard::new(ValkeyString::create(None, ""));
@@ -85,7 +132,21 @@ lazy_static! {
         ValkeyGILGuard::new(ValkeyString::create(None, ""));
     pub static ref LDAP_TLS_KEY_PATH: ValkeyGILGuard<ValkeyString> =
         ValkeyGILGuard::new(ValkeyString::create(None, ""));
+    pub static ref LDAP_TLS_KEY_PASSPHRASE: ValkeyGILGuard<ValkeyString> =
+        ValkeyGILGuard::new(ValkeyString::create(None, ""));
+    pub static ref LDAP_TLS_CA_CERT_DIR: ValkeyGILGuard<ValkeyString> =
+        ValkeyGILGuard::new(ValkeyString::create(None, ""));
+    pub static ref LDAP_TLS_MIN_VERSION: ValkeyGILGuard<LdapTlsVersion> =
+        ValkeyGILGuard::new(LdapTlsVersion::Tls12);
+    pub static ref LDAP_TLS_USE_SYSTEM_CA: ValkeyGILGuard<bool> = ValkeyGILGuard::new(true);
+    pub static ref LDAP_TLS_SERVER_NAME: ValkeyGILGuard<ValkeyString> =
+        ValkeyGILGuard::new(ValkeyString::create(None, ""));
+    pub static ref LDAP_TLS_SKIP_VERIFY: ValkeyGILGuard<bool> = ValkeyGILGuard::default();
+    pub static ref LDAP_TLS_PINNED_KEYS: ValkeyGILGuard<ValkeyString> =
+        ValkeyGILGuard::new(ValkeyString::create(None, ""));
     pub static ref LDAP_USE_STARTTLS: ValkeyGILGuard<bool> = ValkeyGILGuard::default();
+    pub static ref LDAP_SEARCH_ACCOUNT_STATUS_CHECK: ValkeyGILGuard<bool> =
+        ValkeyGILGuard::default();
     pub static ref LDAP_AUTH_MODE: ValkeyGILGuard<LdapAuthMode> =
         ValkeyGILGuard::new(LdapAuthMode::Bind);
     pub static ref LDAP_SEARCH_BASE: ValkeyGILGuard<ValkeyString> =
@@ -100,12 +161,56 @@ lazy_static! {
         ValkeyGILGuard::new(ValkeyString::create(None, ""));
     pub static ref LDAP_SEARCH_BIND_PASSWD: ValkeyGILGuard<ValkeyString> =
         ValkeyGILGuard::new(ValkeyString::create(None, ""));
+    pub static ref LDAP_SEARCH_BIND_PASSWD_FILE: ValkeyGILGuard<ValkeyString> =
+        ValkeyGILGuard::new(ValkeyString::create(None, ""));
+    pub static ref LDAP_GSSAPI_KEYTAB: ValkeyGILGuard<ValkeyString> =
+        ValkeyGILGuard::new(ValkeyString::create(None, ""));
+    pub static ref LDAP_GSSAPI_PRINCIPAL: ValkeyGILGuard<ValkeyString> =
+        ValkeyGILGuard::new(ValkeyString::create(None, ""));
+    pub static ref LDAP_SEARCH_BIND_MECHANISM: ValkeyGILGuard<LdapBindMechanism> =
+        ValkeyGILGuard::new(LdapBindMechanism::Simple);
     pub static ref LDAP_SEARCH_DN_ATTRIBUTE: ValkeyGILGuard<ValkeyString> =
         ValkeyGILGuard::new(ValkeyString::create(None, ""));
     pub static ref LDAP_CONNECTION_POOL_SIZE: ValkeyGILGuard<i64> = ValkeyGILGuard::new(2);
     pub static ref LDAP_FAILURE_DETECTOR_INTERVAL: ValkeyGILGuard<i64> = ValkeyGILGuard::new(1);
     pub static ref LDAP_TIMEOUT_CONNECTION: ValkeyGILGuard<i64> = ValkeyGILGuard::new(10);
     pub static ref LDAP_TIMEOUT_LDAP_OPERATION: ValkeyGILGuard<i64> = ValkeyGILGuard::new(10);
+    pub static ref LDAP_TIMEOUT_AUTH_TOTAL_MS: ValkeyGILGuard<i64> = ValkeyGILGuard::new(0);
+    pub static ref LDAP_FAILURE_DETECTOR_INTERVAL_MS: ValkeyGILGuard<i64> = ValkeyGILGuard::new(0);
+    pub static ref LDAP_TIMEOUT_CONNECTION_MS: ValkeyGILGuard<i64> = ValkeyGILGuard::new(0);
+    pub static ref LDAP_TIMEOUT_LDAP_OPERATION_MS: ValkeyGILGuard<i64> = ValkeyGILGuard::new(0);
+    pub static ref LDAP_HEALTH_CHECK: ValkeyGILGuard<LdapHealthCheck> =
+        ValkeyGILGuard::new(LdapHealthCheck::WhoAmI);
+    pub static ref LDAP_HEALTH_CHECK_TIMEOUT_MS: ValkeyGILGuard<i64> = ValkeyGILGuard::new(5000);
+    pub static ref LDAP_HEALTH_CHECK_SLOW_THRESHOLD_MS: ValkeyGILGuard<i64> =
+        ValkeyGILGuard::new(0);
+    pub static ref LDAP_HEDGE_DELAY_MS: ValkeyGILGuard<i64> = ValkeyGILGuard::new(0);
+    pub static ref LDAP_HEDGE_DELAY_PERCENTILE: ValkeyGILGuard<i64> = ValkeyGILGuard::new(0);
+    pub static ref LDAP_HEDGE_BINDS: ValkeyGILGuard<bool> = ValkeyGILGuard::default();
+    pub static ref LDAP_ACL_PASSWORD_FALLBACK: ValkeyGILGuard<bool> = ValkeyGILGuard::new(true);
+    pub static ref LDAP_HEALTH_CHECK_SEARCH_BASE: ValkeyGILGuard<ValkeyString> =
+        ValkeyGILGuard::new(ValkeyString::create(None, ""));
+    pub static ref LDAP_HEALTH_CHECK_SEARCH_FILTER: ValkeyGILGuard<ValkeyString> =
+        ValkeyGILGuard::new(ValkeyString::create(None, ""));
+    pub static ref LDAP_AUDIT_LOG_FILE: ValkeyGILGuard<ValkeyString> =
+        ValkeyGILGuard::new(ValkeyString::create(None, ""));
+    pub static ref LDAP_AUDIT_LOG_KEY_FILE: ValkeyGILGuard<ValkeyString> =
+        ValkeyGILGuard::new(ValkeyString::create(None, ""));
+    pub static ref LDAP_AUDIT_LOG_EVENTS: ValkeyGILGuard<LdapAuditEvents> =
+        ValkeyGILGuard::new(LdapAuditEvents::All);
+    pub static ref LDAP_AUDIT_LOG_MAX_SIZE: ValkeyGILGuard<i64> = ValkeyGILGuard::new(104857600);
+    pub static ref LDAP_AUDIT_LOG_MAX_FILES: ValkeyGILGuard<i64> = ValkeyGILGuard::new(5);
+    pub static ref LDAP_AUTH_EVENTS_STREAM: ValkeyGILGuard<ValkeyString> =
+        ValkeyGILGuard::new(ValkeyString::create(None, ""));
+    pub static ref LDAP_AUTH_EVENTS_STREAM_MAXLEN: ValkeyGILGuard<i64> = ValkeyGILGuard::new(10000);
+    pub static ref LDAP_AUTH_EVENTS_CHANNEL: ValkeyGILGuard<ValkeyString> =
+        ValkeyGILGuard::new(ValkeyString::create(None, ""));
+    pub static ref LDAP_MAX_SESSION_LIFETIME: ValkeyGILGuard<i64> = ValkeyGILGuard::new(0);
+    pub static ref LDAP_REVALIDATION_INTERVAL: ValkeyGILGuard<i64> = ValkeyGILGuard::new(0);
+    pub static ref LDAP_REVALIDATION_GRACE_PERIOD: ValkeyGILGuard<i64> = ValkeyGILGuard::new(0);
+    pub static ref LDAP_REVALIDATION_FILTER: ValkeyGILGuard<ValkeyString> =
+        ValkeyGILGuard::new(ValkeyString::create(None, ""));
+    pub static ref LDAP_TLS_RELOAD_INTERVAL: ValkeyGILGuard<i64> = ValkeyGILGuard::new(0);
 }
 
 pub fn refresh_ldap_settings_cache<T: ValkeyLockIndicator>(ctx: &T) {
@@ -118,8 +223,21 @@ pub fn refresh_ldap_settings_cache<T: ValkeyLockIndicator>(ctx: &T) {
         get_search_attribute(ctx),
         get_search_bind_dn(ctx),
         get_search_bind_passwd(ctx),
+        get_search_bind_passwd_file(ctx).map(|path| Arc::new(VkPasswordFile::new(path))),
+        get_search_bind_mechanism(ctx),
         get_search_dn_attribute(ctx),
+        is_search_account_status_check_enabled(ctx),
         get_timeout_ldap_operation(ctx),
+        get_timeout_auth_total(ctx),
+        get_health_check(ctx),
+        get_health_check_timeout(ctx),
+        get_health_check_slow_threshold(ctx),
+        get_health_check_search_base(ctx),
+        get_health_check_search_filter(ctx),
+        get_hedge_delay(ctx),
+        get_hedge_delay_percentile(ctx),
+        is_hedge_binds_enabled(ctx),
+        get_revalidation_filter(ctx),
     );
     vkldap::refresh_ldap_settings(settings);
 }
@@ -130,6 +248,15 @@ pub fn refresh_connection_settings_cache<T: ValkeyLockIndicator>(ctx: &T) {
         get_tls_ca_cert_path(ctx),
         get_tls_cert_path(ctx),
         get_tls_key_path(ctx),
+        get_tls_key_passphrase(ctx),
+        VkTlsPolicy {
+            ca_cert_dir: get_tls_ca_cert_dir(ctx),
+            min_version: get_tls_min_version(ctx),
+            use_system_ca: *LDAP_TLS_USE_SYSTEM_CA.lock(ctx),
+            server_name: get_tls_server_name(ctx),
+            pinned_keys: get_tls_pinned_keys(ctx),
+            skip_verify: *LDAP_TLS_SKIP_VERIFY.lock(ctx),
+        },
         get_connection_pool_size(ctx),
         get_timeout_connection(ctx),
     );
@@ -154,11 +281,22 @@ pub fn process_server_list(server_list: String) -> Result<(), ValkeyError> {
     for url_str in urls {
         let parse_res = Url::parse(url_str);
         match parse_res {
-            Ok(url) => url_list.push_back(url),
-            Err(e) => return Err(ValkeyError::String(e.to_string())),
+            Ok(mut url) => {
+                let options = parse_server_options(&url)?;
+                url.set_query(None);
+                url_list.push_back((url, options))
+            }
+            Err(e) => return Err(ValkeyError::String(format!("server '{url_str}': {e}"))),
         }
     }
 
+    let options: Vec<&VkLdapServerOptions> = url_list.iter().map(|(_, options)| options).collect();
+    if let Some(priority) = find_mixed_weight_priority(&options) {
+        return Err(ValkeyError::String(format!(
+            "the servers with priority {priority} must either all have a weight or none"
+        )));
+    }
+
     let res = vkldap::clear_server_list();
     if let Err(err) = res {
         error!("clear server list returned an error: {err}");
@@ -167,9 +305,9 @@ pub fn process_server_list(server_list: String) -> Result<(), ValkeyError> {
         ));
     }
 
-    for url in url_list {
+    for (url, options) in url_list {
         debug!("adding server URL {url:?}");
-        let res = vkldap::add_server(url);
+        let res = vkldap::add_server(url, options);
         if let Err(err) = res {
             error!("add server returned an error: {err}");
             return Err(ValkeyError::Str(
@@ -181,6 +319,141 @@ pub fn process_server_list(server_list: String) -> Result<(), ValkeyError> {
     Ok(())
 }
 
+fn parse_server_option<T: std::str::FromStr>(
+    url: &Url,
+    key: &str,
+    value: &str,
+) -> Result<T, ValkeyError> {
+    value.parse::<T>().map_err(|_| {
+        ValkeyError::String(format!(
+            "server '{url}': invalid value '{value}' for option '{key}'"
+        ))
+    })
+}
+
+/// Parses a public key pin, the base64 encoded SHA-256 digest of a subject
+/// public key info, optionally prefixed with "sha256//". Returns the digest
+/// in the canonical base64 encoding.
+fn parse_pinned_key(pin: &str) -> Result<String, String> {
+    let digest = pin.strip_prefix("sha256//").unwrap_or(pin);
+    // A '+' in a URL query is decoded as a space, and base64 has no spaces.
+    let digest = digest.replace(' ', "+");
+
+    match BASE64.decode(&digest) {
+        Ok(bytes) if bytes.len() == 32 => Ok(BASE64.encode(&bytes)),
+        _ => Err(format!(
+            "invalid pin '{pin}', expected the base64 encoded SHA-256 digest of a public key"
+        )),
+    }
+}
+
+fn parse_server_options(url: &Url) -> Result<VkLdapServerOptions, ValkeyError> {
+    let mut options = VkLdapServerOptions::default();
+
+    for (key, value) in url.query_pairs() {
+        match key.as_ref() {
+            "starttls" => {
+                options.use_starttls = match value.as_ref() {
+                    "yes" | "true" | "1" => Some(true),
+                    "no" | "false" | "0" => Some(false),
+                    _ => {
+                        return Err(ValkeyError::String(format!(
+                            "server '{url}': invalid value '{value}' for option 'starttls'"
+                        )));
+                    }
+                }
+            }
+            "ca" => {
+                if value.is_empty() {
+                    return Err(ValkeyError::String(format!(
+                        "server '{url}': option 'ca' requires a file path"
+                    )));
+                }
+                options.ca_cert_path = Some(value.to_string());
+            }
+            "pool_size" => {
+                let pool_size: usize = parse_server_option(url, &key, &value)?;
+                if !(1..=8192).contains(&pool_size) {
+                    return Err(ValkeyError::String(format!(
+                        "server '{url}': option 'pool_size' must be between 1 and 8192"
+                    )));
+                }
+                options.connection_pool_size = Some(pool_size);
+            }
+            "timeout" => {
+                let timeout: u64 = parse_server_option(url, &key, &value)?;
+                options.timeout_connection = Some(Duration::from_secs(timeout));
+            }
+            "timeout_ms" => {
+                let timeout: u64 = parse_server_option(url, &key, &value)?;
+                options.timeout_connection = Some(Duration::from_millis(timeout));
+            }
+            "server_name" => {
+                if value.is_empty() {
+                    return Err(ValkeyError::String(format!(
+                        "server '{url}': option 'server_name' requires a hostname"
+                    )));
+                }
+                options.tls_server_name = Some(value.to_string());
+            }
+            "pin" => {
+                let pin = parse_pinned_key(&value).map_err(|err| {
+                    ValkeyError::String(format!("server '{url}': option 'pin': {err}"))
+                })?;
+                options.tls_pinned_keys.get_or_insert_default().push(pin);
+            }
+            "weight" => options.weight = Some(parse_server_option(url, &key, &value)?),
+            "priority" => options.priority = parse_server_option(url, &key, &value)?,
+            _ => {
+                return Err(ValkeyError::String(format!(
+                    "server '{url}': unknown option '{key}'"
+                )));
+            }
+        }
+    }
+
+    if url.scheme() == "ldapi" {
+        if url.host_str().unwrap_or_default().is_empty() {
+            return Err(ValkeyError::String(format!(
+                "server '{url}': ldapi URLs require the percent-encoded path of the socket, \
+                 e.g., ldapi://%2Fvar%2Frun%2Fslapd%2Fldapi"
+            )));
+        }
+
+        // The connections through a Unix socket never use TLS.
+        if options.use_starttls.is_some()
+            || options.ca_cert_path.is_some()
+            || options.tls_server_name.is_some()
+            || options.tls_pinned_keys.is_some()
+        {
+            warn!("server '{url}': ignoring the TLS options of a Unix socket server");
+        }
+    }
+
+    Ok(options)
+}
+
+pub fn refresh_audit_settings<T: ValkeyLockIndicator>(ctx: &T) {
+    let settings = AuditSettings {
+        path: get_audit_log_file(ctx),
+        key_file: get_audit_log_key_file(ctx),
+        events: get_audit_log_events(ctx),
+        max_size: *LDAP_AUDIT_LOG_MAX_SIZE.lock(ctx) as u64,
+        max_files: *LDAP_AUDIT_LOG_MAX_FILES.lock(ctx) as u32,
+    };
+    audit::configure_audit_log(settings);
+}
+
+pub fn refresh_session_settings<T: ValkeyLockIndicator>(ctx: &T) {
+    sessions::set_revalidation_interval(get_revalidation_interval(ctx));
+    sessions::set_revalidation_grace_period(get_revalidation_grace_period(ctx));
+    sessions::set_max_session_lifetime(get_max_session_lifetime(ctx));
+}
+
+pub fn refresh_kerberos_settings<T: ValkeyLockIndicator>(ctx: &T) {
+    vkldap::set_kerberos_credentials(get_gssapi_keytab(ctx), get_gssapi_principal(ctx));
+}
+
 pub fn on_ldap_setting_change<G, T: ConfigurationValue<G>>(
     ctx: &ConfigurationContext,
     _name: &str,
@@ -197,12 +470,44 @@ pub fn on_connection_setting_change<G, T: ConfigurationValue<G>>(
     refresh_connection_settings_cache(ctx);
 }
 
+pub fn on_kerberos_setting_change<G, T: ConfigurationValue<G>>(
+    ctx: &ConfigurationContext,
+    _name: &str,
+    _val: &'static T,
+) {
+    refresh_kerberos_settings(ctx);
+}
+
+pub fn on_audit_setting_change<G, T: ConfigurationValue<G>>(
+    ctx: &ConfigurationContext,
+    _name: &str,
+    _val: &'static T,
+) {
+    refresh_audit_settings(ctx);
+}
+
+pub fn on_session_setting_change<G, T: ConfigurationValue<G>>(
+    ctx: &ConfigurationContext,
+    _name: &str,
+    _val: &'static T,
+) {
+    refresh_session_settings(ctx);
+}
+
 pub fn failure_detector_interval_changed<G, T: ConfigurationValue<G>>(
     ctx: &ConfigurationContext,
     _name: &str,
     _val: &'static T,
 ) {
-    failure_detector::set_failure_detector_interval(get_failure_detector_interval_secs(ctx));
+    failure_detector::set_failure_detector_interval(get_failure_detector_interval(ctx));
+}
+
+pub fn tls_reload_interval_changed<G, T: ConfigurationValue<G>>(
+    ctx: &ConfigurationContext,
+    _name: &str,
+    _val: &'static T,
+) {
+    tls_reloader::set_tls_reload_interval(get_tls_reload_interval(ctx));
 }
 
 pub fn ldap_server_list_set_callback(
@@ -214,6 +519,70 @@ pub fn ldap_server_list_set_callback(
     process_server_list(val_str)
 }
 
+pub fn tls_pinned_keys_set_callback(
+    config_ctx: &ConfigurationContext,
+    _: &str,
+    value: &'static ValkeyGILGuard<ValkeyString>,
+) -> Result<(), ValkeyError> {
+    let val_str = value.get(config_ctx).to_string_lossy();
+    for pin in val_str.split_whitespace() {
+        parse_pinned_key(pin).map_err(ValkeyError::String)?;
+    }
+    Ok(())
+}
+
+pub fn gssapi_setting_set_callback(
+    config_ctx: &ConfigurationContext,
+    _: &str,
+    value: &'static ValkeyGILGuard<ValkeyString>,
+) -> Result<(), ValkeyError> {
+    let val_str = value.get(config_ctx).to_string_lossy();
+    if !val_str.is_empty() && !cfg!(feature = "gssapi") {
+        return Err(ValkeyError::Str(
+            "the module was built without the gssapi feature",
+        ));
+    }
+    Ok(())
+}
+
+pub fn tls_client_file_set_callback(
+    config_ctx: &ConfigurationContext,
+    _: &str,
+    value: &'static ValkeyGILGuard<ValkeyString>,
+) -> Result<(), ValkeyError> {
+    let path = value.get(config_ctx).to_string_lossy();
+    if path.is_empty() {
+        return Ok(());
+    }
+    vkldap::check_tls_client_file(&path).map_err(|err| ValkeyError::String(err.to_string()))
+}
+
+pub fn tls_key_passphrase_set_callback(
+    config_ctx: &ConfigurationContext,
+    _: &str,
+    value: &'static ValkeyGILGuard<ValkeyString>,
+) -> Result<(), ValkeyError> {
+    let passphrase = value.get(config_ctx).to_string_lossy();
+    vkldap::check_tls_key_passphrase(&passphrase)
+        .map_err(|err| ValkeyError::String(err.to_string()))
+}
+
+pub fn search_bind_passwd_file_set_callback(
+    config_ctx: &ConfigurationContext,
+    _: &str,
+    value: &'static ValkeyGILGuard<ValkeyString>,
+) -> Result<(), ValkeyError> {
+    let path = value.get(config_ctx).to_string_lossy();
+    if path.is_empty() {
+        return Ok(());
+    }
+    let contents = std::fs::read_to_string(&path)
+        .map_err(|err| ValkeyError::String(format!("failed to read {path}: {err}")))?;
+    parse_passwords(&contents)
+        .map_err(|err| ValkeyError::String(format!("invalid password file {path}: {err}")))?;
+    Ok(())
+}
+
 pub fn get_bind_dn_prefix<T: ValkeyLockIndicator>(ctx: &T) -> String {
     let bind_dn_prefix = LDAP_BIND_DN_PREFIX.lock(ctx);
     bind_dn_prefix.to_string_lossy()
@@ -251,6 +620,55 @@ pub fn get_tls_key_path<T: ValkeyLockIndicator>(ctx: &T) -> Option<String> {
     }
 }
 
+pub fn get_tls_key_passphrase<T: ValkeyLockIndicator>(ctx: &T) -> Option<String> {
+    let passphrase = LDAP_TLS_KEY_PASSPHRASE.lock(ctx);
+    let passphrase_str = passphrase.to_string();
+    match passphrase_str.as_str() {
+        "" => None,
+        _ => Some(passphrase_str),
+    }
+}
+
+pub fn get_tls_ca_cert_dir<T: ValkeyLockIndicator>(ctx: &T) -> Option<String> {
+    let ca_cert_dir = LDAP_TLS_CA_CERT_DIR.lock(ctx);
+    let ca_cert_dir_str = ca_cert_dir.to_string();
+    match ca_cert_dir_str.as_str() {
+        "" => None,
+        _ => Some(ca_cert_dir_str),
+    }
+}
+
+pub fn get_tls_min_version<T: ValkeyLockIndicator>(ctx: &T) -> LdapTlsVersion {
+    let min_version = LDAP_TLS_MIN_VERSION.lock(ctx);
+    min_version.clone()
+}
+
+pub fn get_tls_server_name<T: ValkeyLockIndicator>(ctx: &T) -> Option<String> {
+    let server_name = LDAP_TLS_SERVER_NAME.lock(ctx);
+    let server_name_str = server_name.to_string();
+    match server_name_str.as_str() {
+        "" => None,
+        _ => Some(server_name_str),
+    }
+}
+
+pub fn get_tls_pinned_keys<T: ValkeyLockIndicator>(ctx: &T) -> Vec<String> {
+    let pinned_keys = LDAP_TLS_PINNED_KEYS.lock(ctx);
+    pinned_keys
+        .to_string_lossy()
+        .split_whitespace()
+        .filter_map(|pin| match parse_pinned_key(pin) {
+            Ok(pin) => Some(pin),
+            Err(err) => {
+                // The set callback rejects such pins, so this only happens
+                // with a value that bypassed it.
+                error!("ignoring the TLS pinned key '{pin}': {err}");
+                None
+            }
+        })
+        .collect()
+}
+
 pub fn is_starttls_enabled<T: ValkeyLockIndicator>(ctx: &T) -> bool {
     let use_starttls = LDAP_USE_STARTTLS.lock(ctx);
     *use_starttls
@@ -266,6 +684,11 @@ pub fn is_bind_mode<T: ValkeyLockIndicator>(ctx: &T) -> bool {
     *auth_mode == LdapAuthMode::Bind
 }
 
+pub fn is_acl_password_fallback_enabled<T: ValkeyLockIndicator>(ctx: &T) -> bool {
+    let fallback = LDAP_ACL_PASSWORD_FALLBACK.lock(ctx);
+    *fallback
+}
+
 pub fn get_search_base<T: ValkeyLockIndicator>(ctx: &T) -> Option<String> {
     let search_base = LDAP_SEARCH_BASE.lock(ctx);
     let search_base_str = search_base.to_string();
@@ -316,27 +739,224 @@ pub fn get_search_bind_passwd<T: ValkeyLockIndicator>(ctx: &T) -> Option<String>
     }
 }
 
+pub fn get_search_bind_passwd_file<T: ValkeyLockIndicator>(ctx: &T) -> Option<String> {
+    let passwd_file = LDAP_SEARCH_BIND_PASSWD_FILE.lock(ctx);
+    let passwd_file_str = passwd_file.to_string();
+    match passwd_file_str.as_str() {
+        "" => None,
+        _ => Some(passwd_file_str),
+    }
+}
+
+pub fn get_gssapi_keytab<T: ValkeyLockIndicator>(ctx: &T) -> Option<String> {
+    let keytab = LDAP_GSSAPI_KEYTAB.lock(ctx);
+    let keytab_str = keytab.to_string();
+    match keytab_str.as_str() {
+        "" => None,
+        _ => Some(keytab_str),
+    }
+}
+
+pub fn get_gssapi_principal<T: ValkeyLockIndicator>(ctx: &T) -> Option<String> {
+    let principal = LDAP_GSSAPI_PRINCIPAL.lock(ctx);
+    let principal_str = principal.to_string();
+    match principal_str.as_str() {
+        "" => None,
+        _ => Some(principal_str),
+    }
+}
+
+pub fn get_search_bind_mechanism<T: ValkeyLockIndicator>(ctx: &T) -> LdapBindMechanism {
+    let mechanism = LDAP_SEARCH_BIND_MECHANISM.lock(ctx);
+    mechanism.clone()
+}
+
 pub fn get_search_dn_attribute<T: ValkeyLockIndicator>(ctx: &T) -> String {
     let dn_attribute = LDAP_SEARCH_DN_ATTRIBUTE.lock(ctx);
     dn_attribute.to_string()
 }
 
+pub fn is_search_account_status_check_enabled<T: ValkeyLockIndicator>(ctx: &T) -> bool {
+    let status_check = LDAP_SEARCH_ACCOUNT_STATUS_CHECK.lock(ctx);
+    *status_check
+}
+
 pub fn get_connection_pool_size<T: ValkeyLockIndicator>(ctx: &T) -> usize {
     let pool_size = LDAP_CONNECTION_POOL_SIZE.lock(ctx);
     *pool_size as usize
 }
 
-pub fn get_failure_detector_interval_secs<T: ValkeyLockIndicator>(ctx: &T) -> u64 {
-    let interval = LDAP_FAILURE_DETECTOR_INTERVAL.lock(ctx);
-    *interval as u64
+/// Returns the duration of a config that has a seconds and a milliseconds
+/// variant. The milliseconds variant takes precedence when it's set.
+fn get_duration_config<T: ValkeyLockIndicator>(
+    ctx: &T,
+    secs_config: &ValkeyGILGuard<i64>,
+    millis_config: &ValkeyGILGuard<i64>,
+) -> Duration {
+    let millis = *millis_config.lock(ctx);
+    if millis > 0 {
+        return Duration::from_millis(millis as u64);
+    }
+    let secs = *secs_config.lock(ctx);
+    Duration::from_secs(secs as u64)
+}
+
+pub fn get_failure_detector_interval<T: ValkeyLockIndicator>(ctx: &T) -> Duration {
+    get_duration_config(
+        ctx,
+        &LDAP_FAILURE_DETECTOR_INTERVAL,
+        &LDAP_FAILURE_DETECTOR_INTERVAL_MS,
+    )
 }
 
 pub fn get_timeout_connection<T: ValkeyLockIndicator>(ctx: &T) -> Duration {
-    let timeout = LDAP_TIMEOUT_CONNECTION.lock(ctx);
-    Duration::from_secs(*timeout as u64)
+    get_duration_config(ctx, &LDAP_TIMEOUT_CONNECTION, &LDAP_TIMEOUT_CONNECTION_MS)
 }
 
 pub fn get_timeout_ldap_operation<T: ValkeyLockIndicator>(ctx: &T) -> Duration {
-    let timeout = LDAP_TIMEOUT_LDAP_OPERATION.lock(ctx);
-    Duration::from_secs(*timeout as u64)
+    get_duration_config(
+        ctx,
+        &LDAP_TIMEOUT_LDAP_OPERATION,
+        &LDAP_TIMEOUT_LDAP_OPERATION_MS,
+    )
+}
+
+pub fn get_health_check<T: ValkeyLockIndicator>(ctx: &T) -> LdapHealthCheck {
+    let health_check = LDAP_HEALTH_CHECK.lock(ctx);
+    health_check.clone()
+}
+
+pub fn get_health_check_timeout<T: ValkeyLockIndicator>(ctx: &T) -> Duration {
+    let timeout = LDAP_HEALTH_CHECK_TIMEOUT_MS.lock(ctx);
+    Duration::from_millis(*timeout as u64)
+}
+
+pub fn get_health_check_slow_threshold<T: ValkeyLockIndicator>(ctx: &T) -> Option<Duration> {
+    let threshold = LDAP_HEALTH_CHECK_SLOW_THRESHOLD_MS.lock(ctx);
+    match *threshold {
+        0 => None,
+        _ => Some(Duration::from_millis(*threshold as u64)),
+    }
+}
+
+pub fn get_health_check_search_base<T: ValkeyLockIndicator>(ctx: &T) -> Option<String> {
+    let search_base = LDAP_HEALTH_CHECK_SEARCH_BASE.lock(ctx);
+    let search_base_str = search_base.to_string();
+    match search_base_str.as_str() {
+        "" => None,
+        _ => Some(search_base_str),
+    }
+}
+
+pub fn get_health_check_search_filter<T: ValkeyLockIndicator>(ctx: &T) -> Option<String> {
+    let search_filter = LDAP_HEALTH_CHECK_SEARCH_FILTER.lock(ctx);
+    let search_filter_str = search_filter.to_string();
+    match search_filter_str.as_str() {
+        "" => None,
+        _ => Some(search_filter_str),
+    }
+}
+
+pub fn get_timeout_auth_total<T: ValkeyLockIndicator>(ctx: &T) -> Option<Duration> {
+    let timeout = LDAP_TIMEOUT_AUTH_TOTAL_MS.lock(ctx);
+    match *timeout {
+        0 => None,
+        _ => Some(Duration::from_millis(*timeout as u64)),
+    }
+}
+
+pub fn get_hedge_delay<T: ValkeyLockIndicator>(ctx: &T) -> Option<Duration> {
+    let delay = LDAP_HEDGE_DELAY_MS.lock(ctx);
+    match *delay {
+        0 => None,
+        _ => Some(Duration::from_millis(*delay as u64)),
+    }
+}
+
+pub fn get_hedge_delay_percentile<T: ValkeyLockIndicator>(ctx: &T) -> Option<f64> {
+    let percentile = LDAP_HEDGE_DELAY_PERCENTILE.lock(ctx);
+    match *percentile {
+        0 => None,
+        _ => Some(*percentile as f64),
+    }
+}
+
+pub fn is_hedge_binds_enabled<T: ValkeyLockIndicator>(ctx: &T) -> bool {
+    let hedge_binds = LDAP_HEDGE_BINDS.lock(ctx);
+    *hedge_binds
+}
+
+pub fn get_audit_log_file<T: ValkeyLockIndicator>(ctx: &T) -> Option<PathBuf> {
+    let path = LDAP_AUDIT_LOG_FILE.lock(ctx);
+    let path_str = path.to_string();
+    match path_str.as_str() {
+        "" => None,
+        _ => Some(PathBuf::from(path_str)),
+    }
+}
+
+pub fn get_audit_log_key_file<T: ValkeyLockIndicator>(ctx: &T) -> Option<PathBuf> {
+    let path = LDAP_AUDIT_LOG_KEY_FILE.lock(ctx);
+    let path_str = path.to_string();
+    match path_str.as_str() {
+        "" => None,
+        _ => Some(PathBuf::from(path_str)),
+    }
+}
+
+pub fn get_audit_log_events<T: ValkeyLockIndicator>(ctx: &T) -> LdapAuditEvents {
+    let events = LDAP_AUDIT_LOG_EVENTS.lock(ctx);
+    events.clone()
+}
+
+pub fn get_auth_events_stream<T: ValkeyLockIndicator>(ctx: &T) -> Option<String> {
+    let stream = LDAP_AUTH_EVENTS_STREAM.lock(ctx);
+    let stream_str = stream.to_string();
+    match stream_str.as_str() {
+        "" => None,
+        _ => Some(stream_str),
+    }
+}
+
+pub fn get_auth_events_stream_maxlen<T: ValkeyLockIndicator>(ctx: &T) -> i64 {
+    let maxlen = LDAP_AUTH_EVENTS_STREAM_MAXLEN.lock(ctx);
+    *maxlen
+}
+
+pub fn get_auth_events_channel<T: ValkeyLockIndicator>(ctx: &T) -> Option<String> {
+    let channel = LDAP_AUTH_EVENTS_CHANNEL.lock(ctx);
+    let channel_str = channel.to_string();
+    match channel_str.as_str() {
+        "" => None,
+        _ => Some(channel_str),
+    }
+}
+
+pub fn get_max_session_lifetime<T: ValkeyLockIndicator>(ctx: &T) -> Duration {
+    let max_lifetime = LDAP_MAX_SESSION_LIFETIME.lock(ctx);
+    Duration::from_secs(*max_lifetime as u64)
+}
+
+pub fn get_revalidation_interval<T: ValkeyLockIndicator>(ctx: &T) -> Duration {
+    let interval = LDAP_REVALIDATION_INTERVAL.lock(ctx);
+    Duration::from_secs(*interval as u64)
+}
+
+pub fn get_revalidation_grace_period<T: ValkeyLockIndicator>(ctx: &T) -> Duration {
+    let grace_period = LDAP_REVALIDATION_GRACE_PERIOD.lock(ctx);
+    Duration::from_secs(*grace_period as u64)
+}
+
+pub fn get_revalidation_filter<T: ValkeyLockIndicator>(ctx: &T) -> Option<String> {
+    let filter = LDAP_REVALIDATION_FILTER.lock(ctx);
+    let filter_str = filter.to_string();
+    match filter_str.as_str() {
+        "" => None,
+        _ => Some(filter_str),
+    }
+}
+
+pub fn get_tls_reload_interval<T: ValkeyLockIndicator>(ctx: &T) -> Duration {
+    let interval = LDAP_TLS_RELOAD_INTERVAL.lock(ctx);
+    Duration::from_secs(*interval as u64)
 }
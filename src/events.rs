@@ -0,0 +1,72 @@
+use log::{error, warn};
+use valkey_module::{CallOptionsBuilder, CallResult, Context, ContextFlags, raw};
+
+use crate::audit::AuditRecord;
+use crate::configs;
+
+/// Publishes the authentication attempt to the configured stream and Pub/Sub
+/// channel, so that Valkey clients can consume the authentication events.
+pub fn publish_auth_event(ctx: &Context, record: &AuditRecord) {
+    // Replicas cannot write to the stream, which gets the events of their
+    // primary through replication, so they only publish to the channel.
+    let is_replica = ctx.get_flags().contains(ContextFlags::SLAVE);
+    if let Some(stream_key) = configs::get_auth_events_stream(ctx).filter(|_| !is_replica) {
+        add_to_stream(ctx, &stream_key, record);
+    }
+
+    if let Some(channel) = configs::get_auth_events_channel(ctx) {
+        let payload = record.to_json();
+        let res: CallResult = ctx.call_ext(
+            "PUBLISH",
+            &CallOptionsBuilder::new().errors_as_replies().build(),
+            &[channel.as_str(), payload.as_str()][..],
+        );
+        if let Err(err) = res {
+            error!("failed to publish auth event to channel {channel}: {err}");
+        }
+    }
+}
+
+fn add_to_stream(ctx: &Context, stream_key: &str, record: &AuditRecord) {
+    let maxlen = configs::get_auth_events_stream_maxlen(ctx).to_string();
+    let latency = record.latency.as_millis().to_string();
+    let (outcome, reason) = match &record.outcome {
+        Ok(_) => ("success", ""),
+        Err(reason) => ("failure", reason.as_str()),
+    };
+
+    let mut args = vec![stream_key];
+    if maxlen != "0" {
+        args.extend(["MAXLEN", "~", maxlen.as_str()]);
+    }
+    args.extend(["*", "user", record.username.as_str()]);
+    args.extend(["outcome", outcome, "reason", reason]);
+    args.extend(["server", record.server.as_deref().unwrap_or("")]);
+    args.extend(["latency_ms", latency.as_str()]);
+    args.extend(["client_addr", record.client_addr.as_deref().unwrap_or("")]);
+
+    // The stream is written through a detached context, so that it goes to
+    // DB 0 whatever the DB selected by the authenticating client, and the
+    // write is refused when Valkey is out of memory. A failed write never
+    // blocks the authentication.
+    let stream_ctx = unsafe {
+        let detached_ctx = raw::RedisModule_GetDetachedThreadSafeContext.unwrap()(ctx.ctx);
+        raw::RedisModule_SelectDb.unwrap()(detached_ctx, 0);
+        Context::new(detached_ctx)
+    };
+    {
+        let res: CallResult = stream_ctx.call_ext(
+            "XADD",
+            &CallOptionsBuilder::new()
+                .verify_oom()
+                .errors_as_replies()
+                .replicate()
+                .build(),
+            args.as_slice(),
+        );
+        if let Err(err) = res {
+            warn!("failed to add auth event to stream {stream_key}: {err}");
+        }
+    }
+    unsafe { raw::RedisModule_FreeThreadSafeContext.unwrap()(stream_ctx.ctx) };
+}
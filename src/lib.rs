@@ -1,7 +1,12 @@
+mod audit;
 mod auth;
+mod client_events;
 mod commands;
 mod configs;
+mod events;
 mod logging;
+mod sessions;
+mod stats;
 mod version;
 mod vkldap;
 
@@ -15,6 +20,7 @@ use logging::standard_log_implementation;
 use version::module_version;
 use vkldap::failure_detector;
 use vkldap::scheduler;
+use vkldap::tls_reloader;
 
 fn initializer(ctx: &Context, _args: &[ValkeyString]) -> Status {
     ctx.log_debug("initializing LDAP module");
@@ -25,10 +31,23 @@ fn initializer(ctx: &Context, _args: &[ValkeyString]) -> Status {
     }
 
     scheduler::start_job_scheduler();
-    failure_detector::start_failure_detector_thread();
+    failure_detector::start_failure_detector();
+    tls_reloader::start_tls_reloader();
+    #[cfg(feature = "gssapi")]
+    vkldap::kerberos::start_kerberos_renewer();
+    audit::start_audit_log();
+    sessions::start_session_manager();
+
+    if let Status::Err = client_events::subscribe_to_client_events(ctx) {
+        ctx.log_warning("failed to subscribe to client events");
+    }
 
     configs::refresh_ldap_settings_cache(ctx);
     configs::refresh_connection_settings_cache(ctx);
+    configs::refresh_audit_settings(ctx);
+    configs::refresh_session_settings(ctx);
+    configs::refresh_kerberos_settings(ctx);
+    tls_reloader::set_tls_reload_interval(configs::get_tls_reload_interval(ctx));
 
     let server_list = configs::LDAP_SERVER_LIST.lock(ctx).to_string_lossy();
     if let Err(err) = configs::process_server_list(server_list) {
@@ -41,11 +60,17 @@ fn initializer(ctx: &Context, _args: &[ValkeyString]) -> Status {
 fn deinitializer(ctx: &Context) -> Status {
     ctx.log_debug("shutting down LDAP module");
 
-    if let Err(err) = failure_detector::shutdown_failure_detector_thread() {
+    if let Err(err) = failure_detector::shutdown_failure_detector() {
         error!("{err}");
         return Status::Err;
     }
 
+    tls_reloader::shutdown_tls_reloader();
+    #[cfg(feature = "gssapi")]
+    vkldap::kerberos::shutdown_kerberos_renewer();
+    sessions::shutdown_session_manager();
+    audit::stop_audit_log();
+
     if let Err(err) = vkldap::clear_server_list() {
         error!("{err}");
         return Status::Err;
@@ -69,7 +94,15 @@ valkey_module! {
     auth: [
         ldap_auth_blocking_callback
     ],
-    commands: [],
+    acl_categories: [
+        "ldap",
+    ]
+    commands: [
+        ["ldap.passwd", commands::ldap_passwd_command, "no-monitor no-slowlog", 0, 0, 0, "ldap"],
+        ["ldap.search", commands::ldap_search_command, "admin", 0, 0, 0, "ldap"],
+        ["ldap.servers", commands::ldap_servers_command, "admin", 0, 0, 0, "ldap"],
+        ["ldap.reloadtls", commands::ldap_reloadtls_command, "admin", 0, 0, 0, "ldap"],
+    ],
     configurations: [
         i64: [
             [
@@ -90,6 +123,15 @@ valkey_module! {
                 ConfigurationFlags::DEFAULT,
                 Some(Box::new(configs::failure_detector_interval_changed))
             ],
+            [
+                "failure_detector_interval_ms",
+                &*configs::LDAP_FAILURE_DETECTOR_INTERVAL_MS,
+                0,
+                0,
+                std::i64::MAX,
+                ConfigurationFlags::DEFAULT,
+                Some(Box::new(configs::failure_detector_interval_changed))
+            ],
             [
                 "timeout_connection",
                 &*configs::LDAP_TIMEOUT_CONNECTION,
@@ -99,6 +141,15 @@ valkey_module! {
                 ConfigurationFlags::DEFAULT,
                 Some(Box::new(configs::on_connection_setting_change))
             ],
+            [
+                "timeout_connection_ms",
+                &*configs::LDAP_TIMEOUT_CONNECTION_MS,
+                0,
+                0,
+                std::i64::MAX,
+                ConfigurationFlags::DEFAULT,
+                Some(Box::new(configs::on_connection_setting_change))
+            ],
             [
                 "timeout_ldap_operation",
                 &*configs::LDAP_TIMEOUT_LDAP_OPERATION,
@@ -107,7 +158,124 @@ valkey_module! {
                 std::i64::MAX,
                 ConfigurationFlags::DEFAULT,
                 Some(Box::new(configs::on_ldap_setting_change))
-            ]
+            ],
+            [
+                "timeout_ldap_operation_ms",
+                &*configs::LDAP_TIMEOUT_LDAP_OPERATION_MS,
+                0,
+                0,
+                std::i64::MAX,
+                ConfigurationFlags::DEFAULT,
+                Some(Box::new(configs::on_ldap_setting_change))
+            ],
+            [
+                "timeout_auth_total_ms",
+                &*configs::LDAP_TIMEOUT_AUTH_TOTAL_MS,
+                0,
+                0,
+                std::i64::MAX,
+                ConfigurationFlags::DEFAULT,
+                Some(Box::new(configs::on_ldap_setting_change))
+            ],
+            [
+                "hedge_delay_ms",
+                &*configs::LDAP_HEDGE_DELAY_MS,
+                0,
+                0,
+                std::i64::MAX,
+                ConfigurationFlags::DEFAULT,
+                Some(Box::new(configs::on_ldap_setting_change))
+            ],
+            [
+                "hedge_delay_percentile",
+                &*configs::LDAP_HEDGE_DELAY_PERCENTILE,
+                0,
+                0,
+                100,
+                ConfigurationFlags::DEFAULT,
+                Some(Box::new(configs::on_ldap_setting_change))
+            ],
+            [
+                "health_check_timeout_ms",
+                &*configs::LDAP_HEALTH_CHECK_TIMEOUT_MS,
+                5000,
+                1,
+                std::i64::MAX,
+                ConfigurationFlags::DEFAULT,
+                Some(Box::new(configs::on_ldap_setting_change))
+            ],
+            [
+                "health_check_slow_threshold_ms",
+                &*configs::LDAP_HEALTH_CHECK_SLOW_THRESHOLD_MS,
+                0,
+                0,
+                std::i64::MAX,
+                ConfigurationFlags::DEFAULT,
+                Some(Box::new(configs::on_ldap_setting_change))
+            ],
+            [
+                "audit_log_max_size",
+                &*configs::LDAP_AUDIT_LOG_MAX_SIZE,
+                104857600,
+                0,
+                std::i64::MAX,
+                ConfigurationFlags::DEFAULT,
+                Some(Box::new(configs::on_audit_setting_change))
+            ],
+            [
+                "audit_log_max_files",
+                &*configs::LDAP_AUDIT_LOG_MAX_FILES,
+                5,
+                1,
+                1000,
+                ConfigurationFlags::DEFAULT,
+                Some(Box::new(configs::on_audit_setting_change))
+            ],
+            [
+                "auth_events_stream_maxlen",
+                &*configs::LDAP_AUTH_EVENTS_STREAM_MAXLEN,
+                10000,
+                0,
+                std::i64::MAX,
+                ConfigurationFlags::DEFAULT,
+                None
+            ],
+            [
+                "revalidation_interval",
+                &*configs::LDAP_REVALIDATION_INTERVAL,
+                0,
+                0,
+                std::i64::MAX,
+                ConfigurationFlags::DEFAULT,
+                Some(Box::new(configs::on_session_setting_change))
+            ],
+            [
+                "tls_reload_interval",
+                &*configs::LDAP_TLS_RELOAD_INTERVAL,
+                0,
+                0,
+                86400,
+                ConfigurationFlags::DEFAULT,
+                Some(Box::new(configs::tls_reload_interval_changed))
+            ],
+            [
+                "max_session_lifetime",
+                &*configs::LDAP_MAX_SESSION_LIFETIME,
+                0,
+                0,
+                std::i64::MAX,
+                ConfigurationFlags::DEFAULT,
+                Some(Box::new(configs::on_session_setting_change))
+            ],
+            [
+                "revalidation_grace_period",
+                &*configs::LDAP_REVALIDATION_GRACE_PERIOD,
+                0,
+                0,
+                std::i64::MAX,
+                ConfigurationFlags::DEFAULT,
+                Some(Box::new(configs::on_session_setting_change))
+            ],
         ],
         string: [
             [
@@ -144,15 +312,47 @@ valkey_module! {
                 &*configs::LDAP_TLS_CERT_PATH,
                 "",
                 ConfigurationFlags::DEFAULT,
-                Some(Box::new(configs::on_connection_setting_change))
+                Some(Box::new(configs::on_connection_setting_change)),
+                Some(Box::new(configs::tls_client_file_set_callback))
             ],
             [
                 "tls_key_path",
                 &*configs::LDAP_TLS_KEY_PATH,
                 "",
                 ConfigurationFlags::DEFAULT,
+                Some(Box::new(configs::on_connection_setting_change)),
+                Some(Box::new(configs::tls_client_file_set_callback))
+            ],
+            [
+                "tls_key_passphrase",
+                &*configs::LDAP_TLS_KEY_PASSPHRASE,
+                "",
+                ConfigurationFlags::SENSITIVE | ConfigurationFlags::HIDDEN,
+                Some(Box::new(configs::on_connection_setting_change)),
+                Some(Box::new(configs::tls_key_passphrase_set_callback))
+            ],
+            [
+                "tls_ca_cert_dir",
+                &*configs::LDAP_TLS_CA_CERT_DIR,
+                "",
+                ConfigurationFlags::DEFAULT,
                 Some(Box::new(configs::on_connection_setting_change))
             ],
+            [
+                "tls_server_name",
+                &*configs::LDAP_TLS_SERVER_NAME,
+                "",
+                ConfigurationFlags::DEFAULT,
+                Some(Box::new(configs::on_connection_setting_change))
+            ],
+            [
+                "tls_pinned_keys",
+                &*configs::LDAP_TLS_PINNED_KEYS,
+                "",
+                ConfigurationFlags::DEFAULT,
+                Some(Box::new(configs::on_connection_setting_change)),
+                Some(Box::new(configs::tls_pinned_keys_set_callback))
+            ],
             [
                 "search_base",
                 &*configs::LDAP_SEARCH_BASE,
@@ -188,6 +388,30 @@ valkey_module! {
                 ConfigurationFlags::SENSITIVE | ConfigurationFlags::HIDDEN,
                 Some(Box::new(configs::on_ldap_setting_change))
             ],
+            [
+                "search_bind_passwd_file",
+                &*configs::LDAP_SEARCH_BIND_PASSWD_FILE,
+                "",
+                ConfigurationFlags::DEFAULT,
+                Some(Box::new(configs::on_ldap_setting_change)),
+                Some(Box::new(configs::search_bind_passwd_file_set_callback))
+            ],
+            [
+                "gssapi_keytab",
+                &*configs::LDAP_GSSAPI_KEYTAB,
+                "",
+                ConfigurationFlags::DEFAULT,
+                Some(Box::new(configs::on_kerberos_setting_change)),
+                Some(Box::new(configs::gssapi_setting_set_callback))
+            ],
+            [
+                "gssapi_principal",
+                &*configs::LDAP_GSSAPI_PRINCIPAL,
+                "",
+                ConfigurationFlags::DEFAULT,
+                Some(Box::new(configs::on_kerberos_setting_change)),
+                Some(Box::new(configs::gssapi_setting_set_callback))
+            ],
             [
                 "search_dn_attribute",
                 &*configs::LDAP_SEARCH_DN_ATTRIBUTE,
@@ -195,6 +419,55 @@ valkey_module! {
                 ConfigurationFlags::DEFAULT,
                 Some(Box::new(configs::on_ldap_setting_change))
             ],
+            [
+                "health_check_search_base",
+                &*configs::LDAP_HEALTH_CHECK_SEARCH_BASE,
+                "",
+                ConfigurationFlags::DEFAULT,
+                Some(Box::new(configs::on_ldap_setting_change))
+            ],
+            [
+                "health_check_search_filter",
+                &*configs::LDAP_HEALTH_CHECK_SEARCH_FILTER,
+                "objectClass=*",
+                ConfigurationFlags::DEFAULT,
+                Some(Box::new(configs::on_ldap_setting_change))
+            ],
+            [
+                "audit_log_file",
+                &*configs::LDAP_AUDIT_LOG_FILE,
+                "",
+                ConfigurationFlags::DEFAULT,
+                Some(Box::new(configs::on_audit_setting_change))
+            ],
+            [
+                "audit_log_key_file",
+                &*configs::LDAP_AUDIT_LOG_KEY_FILE,
+                "",
+                ConfigurationFlags::DEFAULT,
+                Some(Box::new(configs::on_audit_setting_change))
+            ],
+            [
+                "auth_events_stream",
+                &*configs::LDAP_AUTH_EVENTS_STREAM,
+                "",
+                ConfigurationFlags::DEFAULT,
+                None
+            ],
+            [
+                "auth_events_channel",
+                &*configs::LDAP_AUTH_EVENTS_CHANNEL,
+                "",
+                ConfigurationFlags::DEFAULT,
+                None
+            ],
+            [
+                "revalidation_filter",
+                &*configs::LDAP_REVALIDATION_FILTER,
+                "objectClass=*",
+                ConfigurationFlags::DEFAULT,
+                Some(Box::new(configs::on_ldap_setting_change))
+            ],
         ],
         bool: [
             [
@@ -204,6 +477,41 @@ valkey_module! {
                 ConfigurationFlags::DEFAULT,
                 Some(Box::new(configs::on_connection_setting_change))
             ],
+            [
+                "tls_use_system_ca",
+                &*configs::LDAP_TLS_USE_SYSTEM_CA,
+                true,
+                ConfigurationFlags::DEFAULT,
+                Some(Box::new(configs::on_connection_setting_change))
+            ],
+            [
+                "tls_skip_verify",
+                &*configs::LDAP_TLS_SKIP_VERIFY,
+                false,
+                ConfigurationFlags::DEFAULT,
+                Some(Box::new(configs::on_connection_setting_change))
+            ],
+            [
+                "search_account_status_check",
+                &*configs::LDAP_SEARCH_ACCOUNT_STATUS_CHECK,
+                false,
+                ConfigurationFlags::DEFAULT,
+                Some(Box::new(configs::on_ldap_setting_change))
+            ],
+            [
+                "hedge_binds",
+                &*configs::LDAP_HEDGE_BINDS,
+                false,
+                ConfigurationFlags::DEFAULT,
+                Some(Box::new(configs::on_ldap_setting_change))
+            ],
+            [
+                "acl_password_fallback",
+                &*configs::LDAP_ACL_PASSWORD_FALLBACK,
+                true,
+                ConfigurationFlags::DEFAULT,
+                None
+            ],
         ],
         enum: [
             [
@@ -220,6 +528,34 @@ valkey_module! {
                 ConfigurationFlags::DEFAULT,
                 Some(Box::new(configs::on_ldap_setting_change))
             ],
+            [
+                "search_bind_mechanism",
+                &*configs::LDAP_SEARCH_BIND_MECHANISM,
+                configs::LdapBindMechanism::Simple,
+                ConfigurationFlags::DEFAULT,
+                Some(Box::new(configs::on_ldap_setting_change))
+            ],
+            [
+                "health_check",
+                &*configs::LDAP_HEALTH_CHECK,
+                configs::LdapHealthCheck::WhoAmI,
+                ConfigurationFlags::DEFAULT,
+                Some(Box::new(configs::on_ldap_setting_change))
+            ],
+            [
+                "tls_min_version",
+                &*configs::LDAP_TLS_MIN_VERSION,
+                configs::LdapTlsVersion::Tls12,
+                ConfigurationFlags::DEFAULT,
+                Some(Box::new(configs::on_connection_setting_change))
+            ],
+            [
+                "audit_log_events",
+                &*configs::LDAP_AUDIT_LOG_EVENTS,
+                configs::LdapAuditEvents::All,
+                ConfigurationFlags::DEFAULT,
+                Some(Box::new(configs::on_audit_setting_change))
+            ],
         ],
         module_args_as_configuration: false,
     ]
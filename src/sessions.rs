@@ -0,0 +1,277 @@
+use std::collections::HashMap;
+use std::ptr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use futures::future;
+use lazy_static::lazy_static;
+use log::{debug, error, info};
+use valkey_module::raw;
+
+use crate::vkldap;
+use crate::vkldap::scheduler::BackgroundLoop;
+
+/// A client that was authenticated through LDAP.
+struct LdapSession {
+    username: String,
+    user_dn: Option<String>,
+    authenticated_at: Instant,
+    revalidation_failed_since: Option<Instant>,
+}
+
+lazy_static! {
+    // The LDAP authenticated clients, indexed by client ID.
+    static ref SESSIONS: Mutex<HashMap<u64, LdapSession>> = Mutex::new(HashMap::new());
+}
+
+pub fn on_client_authenticated(client_id: u64, username: String, user_dn: Option<String>) {
+    SESSIONS.lock().unwrap().insert(
+        client_id,
+        LdapSession {
+            username,
+            user_dn,
+            authenticated_at: Instant::now(),
+            revalidation_failed_since: None,
+        },
+    );
+}
+
+pub fn on_client_disconnected(client_id: u64) {
+    SESSIONS.lock().unwrap().remove(&client_id);
+}
+
+/// Called by Valkey when a client authenticated through LDAP is deauthenticated
+/// or authenticates again, either through LDAP or as any other ACL user.
+pub fn on_client_user_changed(client_id: u64) {
+    if SESSIONS.lock().unwrap().remove(&client_id).is_some() {
+        debug!("client {client_id} changed its user, removing its LDAP session");
+    }
+}
+
+/// Returns the LDAP username the client authenticated with, if any.
+pub fn get_session_username(client_id: u64) -> Option<String> {
+    SESSIONS
+        .lock()
+        .unwrap()
+        .get(&client_id)
+        .map(|session| session.username.clone())
+}
+
+pub fn get_active_sessions_count() -> usize {
+    SESSIONS.lock().unwrap().len()
+}
+
+/// Deauthenticates and closes the clients. The GIL is acquired with a try-lock
+/// because the main thread holds the GIL while waiting for the session manager
+/// to stop during the module unload.
+async fn close_clients(client_ids: &[u64]) {
+    loop {
+        // The context is not kept across the sleep, as it cannot be sent to
+        // another thread of the runtime.
+        let closed = unsafe {
+            let ctx = raw::RedisModule_GetThreadSafeContext.unwrap()(ptr::null_mut());
+            let locked = raw::RedisModule_ThreadSafeContextTryLock.unwrap()(ctx)
+                == raw::REDISMODULE_OK as i32;
+            if locked {
+                for client_id in client_ids {
+                    raw::RedisModule_DeauthenticateAndCloseClient.unwrap()(ctx, *client_id);
+                }
+                raw::RedisModule_ThreadSafeContextUnlock.unwrap()(ctx);
+            }
+            raw::RedisModule_FreeThreadSafeContext.unwrap()(ctx);
+            locked
+        };
+
+        if closed || SESSION_MANAGER.background_loop.should_stop() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+async fn revalidation_iteration() {
+    let user_dns: Vec<String> = {
+        let sessions = SESSIONS.lock().unwrap();
+        let mut user_dns: Vec<String> = sessions
+            .values()
+            .filter_map(|session| session.user_dn.clone())
+            .collect();
+        user_dns.sort();
+        user_dns.dedup();
+        user_dns
+    };
+
+    let results = future::join_all(
+        user_dns
+            .iter()
+            .map(|user_dn| vkldap::vk_ldap_check_user(user_dn.clone())),
+    )
+    .await;
+
+    let mut qualified: HashMap<String, bool> = HashMap::new();
+    for (user_dn, res) in user_dns.into_iter().zip(results) {
+        match res {
+            Ok(is_qualified) => {
+                qualified.insert(user_dn, is_qualified);
+            }
+            // The directory could not tell whether the user still qualifies,
+            // so the sessions are kept until the next iteration.
+            Err(err) => error!("failed to revalidate user {user_dn}: {err}"),
+        }
+    }
+
+    let grace_period =
+        Duration::from_millis(SESSION_MANAGER.grace_period_ms.load(Ordering::Relaxed));
+    let now = Instant::now();
+
+    let mut expired = Vec::new();
+    {
+        let mut sessions = SESSIONS.lock().unwrap();
+        for (client_id, session) in sessions.iter_mut() {
+            let Some(user_dn) = &session.user_dn else {
+                continue;
+            };
+
+            match qualified.get(user_dn) {
+                Some(true) => session.revalidation_failed_since = None,
+                Some(false) => {
+                    let failed_since = *session.revalidation_failed_since.get_or_insert(now);
+                    if now.duration_since(failed_since) >= grace_period {
+                        info!(
+                            "user {} no longer passes the LDAP revalidation, closing client {client_id}",
+                            session.username
+                        );
+                        expired.push(*client_id);
+                    }
+                }
+                None => (),
+            }
+        }
+
+        for client_id in &expired {
+            sessions.remove(client_id);
+        }
+    }
+
+    if !expired.is_empty() {
+        close_clients(&expired).await;
+    }
+}
+
+async fn expire_sessions(max_lifetime: Duration) {
+    let mut expired = Vec::new();
+    {
+        let mut sessions = SESSIONS.lock().unwrap();
+        sessions.retain(|client_id, session| {
+            if session.authenticated_at.elapsed() < max_lifetime {
+                return true;
+            }
+            info!(
+                "session of user {} exceeded the maximum lifetime, closing client {client_id}",
+                session.username
+            );
+            expired.push(*client_id);
+            false
+        });
+    }
+
+    if !expired.is_empty() {
+        close_clients(&expired).await;
+    }
+}
+
+async fn session_manager_loop() {
+    debug!("initiating session manager loop");
+
+    let mut last_revalidation = Instant::now();
+
+    loop {
+        let interval = Duration::from_millis(SESSION_MANAGER.interval_ms.load(Ordering::Relaxed));
+        let max_lifetime =
+            Duration::from_millis(SESSION_MANAGER.max_lifetime_ms.load(Ordering::Relaxed));
+
+        // A zero value disables the respective check. The session lifetimes are
+        // checked every second.
+        let next_revalidation =
+            (!interval.is_zero()).then(|| interval.saturating_sub(last_revalidation.elapsed()));
+        let next_expiration = (!max_lifetime.is_zero()).then_some(Duration::from_secs(1));
+        let sleep_time = next_revalidation.into_iter().chain(next_expiration).min();
+
+        let interrupted = SESSION_MANAGER.background_loop.sleep(sleep_time).await;
+
+        if SESSION_MANAGER.background_loop.should_stop() {
+            debug!("exiting session manager loop");
+            break;
+        }
+
+        if interrupted {
+            continue;
+        }
+
+        if !max_lifetime.is_zero() {
+            expire_sessions(max_lifetime).await;
+        }
+
+        if !interval.is_zero() && last_revalidation.elapsed() >= interval {
+            revalidation_iteration().await;
+            last_revalidation = Instant::now();
+        }
+    }
+}
+
+struct SessionManager {
+    background_loop: BackgroundLoop,
+    interval_ms: AtomicU64,
+    grace_period_ms: AtomicU64,
+    max_lifetime_ms: AtomicU64,
+}
+
+impl SessionManager {
+    fn new() -> SessionManager {
+        SessionManager {
+            background_loop: BackgroundLoop::new("session manager"),
+            interval_ms: AtomicU64::new(0),
+            grace_period_ms: AtomicU64::new(0),
+            max_lifetime_ms: AtomicU64::new(0),
+        }
+    }
+}
+
+lazy_static! {
+    static ref SESSION_MANAGER: SessionManager = SessionManager::new();
+}
+
+pub fn start_session_manager() {
+    SESSION_MANAGER
+        .background_loop
+        .start(session_manager_loop());
+}
+
+pub fn shutdown_session_manager() {
+    if let Err(err) = SESSION_MANAGER.background_loop.shutdown() {
+        error!("{err}");
+    }
+
+    SESSIONS.lock().unwrap().clear();
+}
+
+pub fn set_revalidation_interval(interval: Duration) {
+    SESSION_MANAGER
+        .interval_ms
+        .store(interval.as_millis() as u64, Ordering::Relaxed);
+    SESSION_MANAGER.background_loop.notify_settings_changed();
+}
+
+pub fn set_revalidation_grace_period(grace_period: Duration) {
+    SESSION_MANAGER
+        .grace_period_ms
+        .store(grace_period.as_millis() as u64, Ordering::Relaxed);
+}
+
+pub fn set_max_session_lifetime(max_lifetime: Duration) {
+    SESSION_MANAGER
+        .max_lifetime_ms
+        .store(max_lifetime.as_millis() as u64, Ordering::Relaxed);
+    SESSION_MANAGER.background_loop.notify_settings_changed();
+}
@@ -0,0 +1,57 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use lazy_static::lazy_static;
+
+use crate::vkldap::errors::VkLdapFailureReason;
+
+static AUTH_SUCCESSES: AtomicU64 = AtomicU64::new(0);
+static PASSWORD_EXPIRATION_WARNINGS: AtomicU64 = AtomicU64::new(0);
+static GRACE_LOGINS: AtomicU64 = AtomicU64::new(0);
+
+lazy_static! {
+    // The number of failed authentications, indexed by the failure reason.
+    static ref AUTH_FAILURES: Mutex<BTreeMap<&'static str, u64>> = Mutex::new(BTreeMap::new());
+}
+
+pub fn record_auth_success() {
+    AUTH_SUCCESSES.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn record_auth_failure(reason: VkLdapFailureReason) {
+    *AUTH_FAILURES
+        .lock()
+        .unwrap()
+        .entry(reason.as_str())
+        .or_insert(0) += 1;
+}
+
+pub fn record_password_expiration_warning() {
+    PASSWORD_EXPIRATION_WARNINGS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn record_grace_login() {
+    GRACE_LOGINS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn get_auth_successes() -> u64 {
+    AUTH_SUCCESSES.load(Ordering::Relaxed)
+}
+
+pub fn get_auth_failures() -> Vec<(&'static str, u64)> {
+    AUTH_FAILURES
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(reason, count)| (*reason, *count))
+        .collect()
+}
+
+pub fn get_password_expiration_warnings() -> u64 {
+    PASSWORD_EXPIRATION_WARNINGS.load(Ordering::Relaxed)
+}
+
+pub fn get_grace_logins() -> u64 {
+    GRACE_LOGINS.load(Ordering::Relaxed)
+}
@@ -1,25 +1,53 @@
 use std::collections::VecDeque;
-use std::fs;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::time::Duration;
 
-use ldap3::exop::WhoAmI;
-use ldap3::{Ldap, LdapConnAsync, LdapConnSettings, SearchEntry};
-use log::debug;
-use native_tls::{Certificate, Identity, TlsConnector};
+use ldap3::exop::{PasswordModify, WhoAmI};
+use ldap3::{
+    Ldap, LdapConnAsync, LdapConnSettings, LdapError, Scope, SearchEntry, SearchOptions,
+    SearchResult, ldap_escape,
+};
+use log::{debug, warn};
 use tokio::sync::{Mutex, MutexGuard, Notify};
 use url::Url;
 
-use crate::{handle_io_error, handle_ldap_error, handle_tls_error};
+use crate::configs::{LdapBindMechanism, LdapHealthCheck};
+use crate::handle_ldap_error;
 
-use super::Result;
-use super::errors::VkLdapError;
-use super::server::VkLdapServer;
+use super::context;
+use super::errors::{VkLdapError, VkLdapFailureReason};
+use super::passwd_file::VkBindPasswords;
+use super::ppolicy::{PasswordPolicyRequest, PasswordPolicyResponse};
+use super::server::{VkLdapServer, VkLdapServerStatus};
 use super::settings::{VkConnectionSettings, VkLdapSettings};
+use super::tls::VkTlsMaterial;
+use super::{Result, VkConnectionPoolStats, VkLdapSearchEntry, VkLdapSearchRequest};
+
+// The pause between the replacement of two connections when rolling a pool to
+// a new TLS material.
+const TLS_ROLL_DELAY: Duration = Duration::from_millis(100);
+
+// The bounds of the backoff between the attempts to re-create a connection
+// that could not be replaced.
+const RECONNECT_MIN_BACKOFF: Duration = Duration::from_millis(100);
+const RECONNECT_MAX_BACKOFF: Duration = Duration::from_secs(5);
 
 struct ConnectionQueue {
     queue: VecDeque<VkLdapConnection>,
     epoch: u64,
     size: usize,
+    // The connections of the current epoch that are in use.
+    borrowed: usize,
+    // The connections being replaced, whose first connection attempt is still
+    // running.
+    replacing: usize,
+    // The connections that could not be replaced, and are being re-created in
+    // the background.
+    reconnecting: usize,
+    // The settings of the current connections, used to replace the broken
+    // ones.
+    settings: Option<VkConnectionSettings>,
 }
 
 impl ConnectionQueue {
@@ -28,6 +56,10 @@ impl ConnectionQueue {
             queue: VecDeque::new(),
             epoch: 0,
             size: 0,
+            borrowed: 0,
+            replacing: 0,
+            reconnecting: 0,
+            settings: None,
         }
     }
 
@@ -47,9 +79,13 @@ impl ConnectionQueue {
 
         self.epoch += 1;
         self.size = settings.connection_pool_size;
+        self.borrowed = 0;
+        self.replacing = 0;
+        self.reconnecting = 0;
+        self.settings = Some(settings.clone());
 
         for _ in 0..self.size {
-            match VkLdapConnection::new(&settings, server).await {
+            match VkLdapConnection::new(settings, server).await {
                 Ok(conn) => self.queue.push_front(conn),
                 Err(err) => {
                     self.close_connections().await;
@@ -65,12 +101,23 @@ impl ConnectionQueue {
         self.queue.is_empty()
     }
 
+    /// Whether no connection is in use or about to be put back in the queue,
+    /// i.e., the idle connections are all the connections of the pool that
+    /// are currently connected.
     fn has_all_connections(&self) -> bool {
-        self.queue.len() == self.size
+        self.borrowed == 0 && self.replacing == 0
+    }
+
+    /// Whether an empty queue will never get a connection back until the
+    /// server is reachable again, because every connection of the pool failed
+    /// to connect.
+    fn is_unavailable(&self) -> bool {
+        self.is_empty() && self.has_all_connections()
     }
 
     fn take(&mut self) -> (VkLdapConnection, u64) {
         assert!(!self.is_empty());
+        self.borrowed += 1;
         (self.queue.pop_back().unwrap(), self.epoch)
     }
 
@@ -87,6 +134,14 @@ pub(super) struct VkConnectionPool {
     queue: Mutex<ConnectionQueue>,
     signal: Notify,
     server: VkLdapServer,
+    // Copies of the queue state, readable without waiting for the queue lock,
+    // which is held while the connections are being re-created.
+    size: AtomicUsize,
+    idle: AtomicUsize,
+    borrowed: AtomicUsize,
+    reconnecting: AtomicUsize,
+    epoch: AtomicU64,
+    waiters: AtomicUsize,
 }
 
 pub(super) struct VkLdapPoolConnection {
@@ -112,6 +167,25 @@ macro_rules! notify_wait {
     }};
 }
 
+/// Counts a task waiting for a pool connection. The count is decremented on
+/// drop, so that tasks cancelled while waiting are not counted anymore.
+struct WaiterGuard<'a> {
+    waiters: &'a AtomicUsize,
+}
+
+impl<'a> WaiterGuard<'a> {
+    fn new(waiters: &'a AtomicUsize) -> WaiterGuard<'a> {
+        waiters.fetch_add(1, Ordering::Relaxed);
+        WaiterGuard { waiters }
+    }
+}
+
+impl Drop for WaiterGuard<'_> {
+    fn drop(&mut self) {
+        self.waiters.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
 impl VkConnectionPool {
     pub async fn new(
         server: VkLdapServer,
@@ -121,6 +195,12 @@ impl VkConnectionPool {
         let res = c_queue.reset_connections(&server, settings).await;
         (
             VkConnectionPool {
+                size: AtomicUsize::new(c_queue.size),
+                idle: AtomicUsize::new(c_queue.queue.len()),
+                borrowed: AtomicUsize::new(0),
+                reconnecting: AtomicUsize::new(0),
+                epoch: AtomicU64::new(c_queue.epoch),
+                waiters: AtomicUsize::new(0),
                 queue: Mutex::new(c_queue),
                 signal: Notify::new(),
                 server,
@@ -129,42 +209,230 @@ impl VkConnectionPool {
         )
     }
 
+    fn update_stats(&self, queue: &ConnectionQueue) {
+        self.size.store(queue.size, Ordering::Relaxed);
+        self.idle.store(queue.queue.len(), Ordering::Relaxed);
+        self.borrowed.store(queue.borrowed, Ordering::Relaxed);
+        self.reconnecting
+            .store(queue.reconnecting, Ordering::Relaxed);
+        self.epoch.store(queue.epoch, Ordering::Relaxed);
+    }
+
+    pub fn get_stats(&self) -> VkConnectionPoolStats {
+        VkConnectionPoolStats {
+            size: self.size.load(Ordering::Relaxed),
+            idle: self.idle.load(Ordering::Relaxed),
+            borrowed: self.borrowed.load(Ordering::Relaxed),
+            reconnecting: self.reconnecting.load(Ordering::Relaxed),
+            waiters: self.waiters.load(Ordering::Relaxed),
+            epoch: self.epoch.load(Ordering::Relaxed),
+        }
+    }
+
     pub async fn refresh_connections(&self, settings: &VkConnectionSettings) -> Result<()> {
         let mut queue = self.queue.lock().await;
 
-        queue.reset_connections(&self.server, settings).await?;
+        let res = queue.reset_connections(&self.server, settings).await;
+        self.update_stats(&queue);
 
+        // The waiters either get one of the new connections, or fail if none
+        // could be created.
         self.signal.notify_waiters();
 
+        res
+    }
+
+    /// Replaces the connections created with an older TLS material, one at a
+    /// time, so that the pool keeps its capacity and the server does not get
+    /// a burst of new connections. A new connection is created before waiting
+    /// for an outdated one to be idle, and then replaces it.
+    pub async fn roll_connections(&self, settings: &VkConnectionSettings) -> Result<()> {
+        let generation = settings
+            .tls_material
+            .as_ref()
+            .map_or(0, |material| material.get_generation());
+
+        let (epoch, size) = {
+            let queue = self.queue.lock().await;
+            (queue.epoch, queue.size)
+        };
+
+        for _ in 0..size {
+            let mut new_conn = VkLdapConnection::new(settings, &self.server).await?;
+
+            let mut queue = self.queue.lock().await;
+            if queue.epoch == epoch {
+                queue.settings = Some(settings.clone());
+            }
+            let old_conn = loop {
+                // The pool was re-created in the meantime, which already used
+                // the new material.
+                if queue.epoch != epoch {
+                    break None;
+                }
+
+                let position = queue
+                    .queue
+                    .iter()
+                    .position(|conn| conn.tls_generation < generation);
+                if let Some(position) = position {
+                    break queue.queue.remove(position);
+                }
+
+                // Every idle connection is up to date, so the rolling is done
+                // once no connection is borrowed.
+                if queue.has_all_connections() {
+                    break None;
+                }
+
+                queue = notify_wait!(self.signal, queue);
+            };
+
+            let Some(mut old_conn) = old_conn else {
+                drop(queue);
+                new_conn.close().await;
+                return Ok(());
+            };
+
+            queue.put(new_conn);
+            self.update_stats(&queue);
+            drop(queue);
+            self.signal.notify_waiters();
+
+            old_conn.close().await;
+
+            tokio::time::sleep(TLS_ROLL_DELAY).await;
+        }
+
         Ok(())
     }
 
-    pub async fn take_connection(&self) -> VkLdapPoolConnection {
+    /// Takes an idle connection, waiting for one to be returned if there is
+    /// none. Fails if the pool has no connection to return, because every
+    /// connection failed to connect to the server.
+    pub async fn take_connection(&self) -> Result<VkLdapPoolConnection> {
         let mut queue = self.queue.lock().await;
 
-        while queue.is_empty() {
-            queue = notify_wait!(self.signal, queue);
+        if queue.is_empty() {
+            let _waiter = WaiterGuard::new(&self.waiters);
+            while queue.is_empty() {
+                if queue.is_unavailable() {
+                    let url = self.server.get_url_ref().to_string();
+                    return Err(VkLdapError::NoPoolConnectionAvailable(url));
+                }
+                queue = notify_wait!(self.signal, queue);
+            }
         }
 
         let (conn, epoch) = queue.take();
-        VkLdapPoolConnection {
+        self.update_stats(&queue);
+        Ok(VkLdapPoolConnection {
             conn,
             server: self.server.clone(),
             from_epoch: epoch,
-        }
+        })
     }
 
     pub async fn return_connection(&self, mut pool_conn: VkLdapPoolConnection) {
         let mut queue = self.queue.lock().await;
 
         if queue.get_epoch() == pool_conn.from_epoch {
+            queue.borrowed -= 1;
             queue.put(pool_conn.conn);
+            self.update_stats(&queue);
             self.signal.notify_waiters();
         } else {
             pool_conn.conn.close().await;
         }
     }
 
+    /// Closes a connection that cannot be reused, and puts a new connection
+    /// in its place. If the new connection cannot be created, the server is
+    /// marked unhealthy, and the connection is re-created with a backoff until
+    /// it succeeds or the pool is refreshed, so that the pool keeps its size
+    /// after a short outage.
+    pub async fn replace_connection(&self, mut pool_conn: VkLdapPoolConnection) {
+        let epoch = pool_conn.from_epoch;
+        {
+            let mut queue = self.queue.lock().await;
+            if queue.get_epoch() != epoch {
+                drop(queue);
+                pool_conn.conn.close().await;
+                return;
+            }
+            queue.borrowed -= 1;
+            queue.replacing += 1;
+            self.update_stats(&queue);
+        }
+        pool_conn.conn.close().await;
+
+        let mut backoff = RECONNECT_MIN_BACKOFF;
+        let mut reconnecting = false;
+        loop {
+            let settings = {
+                let queue = self.queue.lock().await;
+                if queue.get_epoch() != epoch {
+                    return;
+                }
+                queue.settings.clone()
+            };
+
+            let res = match &settings {
+                Some(settings) => VkLdapConnection::new(settings, &self.server).await,
+                None => Err(VkLdapError::NoServerConfigured),
+            };
+
+            let mut queue = self.queue.lock().await;
+            if queue.get_epoch() != epoch {
+                drop(queue);
+                if let Ok(mut conn) = res {
+                    conn.close().await;
+                }
+                return;
+            }
+
+            match res {
+                Ok(conn) => {
+                    if reconnecting {
+                        queue.reconnecting -= 1;
+                    } else {
+                        queue.replacing -= 1;
+                    }
+                    queue.put(conn);
+                    self.update_stats(&queue);
+                    self.signal.notify_waiters();
+                    return;
+                }
+                Err(err) if !reconnecting => {
+                    reconnecting = true;
+                    queue.replacing -= 1;
+                    queue.reconnecting += 1;
+                    self.update_stats(&queue);
+                    drop(queue);
+
+                    // The waiters may have to fail over if this was the last
+                    // connection of the pool.
+                    self.signal.notify_waiters();
+
+                    let url = self.server.get_url_ref();
+                    warn!("failed to replace interrupted connection to {url}, retrying: {err}");
+                    let status = VkLdapServerStatus::UNHEALTHY(err.to_string());
+                    context::update_server_status(&self.server, status, None).await;
+                }
+                Err(err) => {
+                    drop(queue);
+                    debug!(
+                        "failed to re-create connection to {}: {err}",
+                        self.server.get_url_ref()
+                    );
+                }
+            }
+
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(RECONNECT_MAX_BACKOFF);
+        }
+    }
+
     pub async fn shutdown(&self) {
         let mut queue = self.queue.lock().await;
 
@@ -172,12 +440,168 @@ impl VkConnectionPool {
             queue = notify_wait!(self.signal, queue);
         }
 
+        // Stops re-creating the connections that could not be replaced.
+        queue.epoch += 1;
         queue.close_connections().await
     }
 }
 
+/// Holds a connection taken from a pool and gives it back when dropped.
+///
+/// If the guard is dropped while an LDAP operation is still running, which
+/// happens when a hedged request loses the race, when the authentication
+/// deadline expires, or when the client disconnects, the connection is closed
+/// and replaced instead of being returned to the pool. A bind cannot be
+/// abandoned, so the connection could otherwise be reused with the identity of
+/// the interrupted bind.
+pub(super) struct VkPoolConnectionGuard {
+    pool: Arc<VkConnectionPool>,
+    pool_conn: Option<VkLdapPoolConnection>,
+    in_flight: bool,
+}
+
+impl VkPoolConnectionGuard {
+    pub async fn take(pool: Arc<VkConnectionPool>) -> Result<VkPoolConnectionGuard> {
+        let pool_conn = pool.take_connection().await?;
+        Ok(VkPoolConnectionGuard {
+            pool,
+            pool_conn: Some(pool_conn),
+            in_flight: false,
+        })
+    }
+
+    pub async fn run<F, R>(&mut self, ldap_op: F) -> R
+    where
+        F: AsyncFnOnce(&mut VkLdapConnection) -> R,
+    {
+        let pool_conn = self
+            .pool_conn
+            .as_mut()
+            .expect("the guard should hold a connection");
+
+        self.in_flight = true;
+        let res = ldap_op(&mut pool_conn.conn).await;
+        self.in_flight = false;
+        res
+    }
+}
+
+impl Drop for VkPoolConnectionGuard {
+    fn drop(&mut self) {
+        if let Some(pool_conn) = self.pool_conn.take() {
+            let pool = Arc::clone(&self.pool);
+            let in_flight = self.in_flight;
+            tokio::spawn(async move {
+                if in_flight {
+                    pool.replace_connection(pool_conn).await;
+                } else {
+                    pool.return_connection(pool_conn).await;
+                }
+            });
+        }
+    }
+}
+
+const ACCOUNT_STATUS_ATTRIBUTES: [&str; 4] = [
+    "userAccountControl",
+    "msDS-User-Account-Control-Computed",
+    "pwdAccountLockedTime",
+    "nsAccountLock",
+];
+
+// Flags of the Active Directory userAccountControl attribute. Active Directory
+// keeps the lockout and password expiration flags current only in the
+// constructed msDS-User-Account-Control-Computed attribute.
+const UAC_ACCOUNTDISABLE: u32 = 0x0002;
+const UAC_LOCKOUT: u32 = 0x0010;
+const UAC_PASSWORD_EXPIRED: u32 = 0x0080_0000;
+
+// The pwdAccountLockedTime value of the accounts locked by an administrator,
+// which stay locked until the attribute is removed.
+const PPOLICY_PERMANENT_LOCK: &str = "000001010000Z";
+
+/// Checks the account status attributes of a user entry before binding, so
+/// that unusable accounts are rejected with the precise reason. Active
+/// Directory uses `userAccountControl` and `msDS-User-Account-Control-Computed`,
+/// OpenLDAP's ppolicy overlay uses `pwdAccountLockedTime`, and 389-DS uses
+/// `nsAccountLock`.
+fn check_account_status(entry: &SearchEntry) -> Result<()> {
+    let first_value = |attribute: &str| {
+        entry
+            .attrs
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(attribute))
+            .and_then(|(_, values)| values.first())
+    };
+    let flag_checks = [
+        (
+            "userAccountControl",
+            UAC_ACCOUNTDISABLE,
+            VkLdapFailureReason::AccountDisabled,
+        ),
+        (
+            "msDS-User-Account-Control-Computed",
+            UAC_LOCKOUT,
+            VkLdapFailureReason::AccountLocked,
+        ),
+        (
+            "msDS-User-Account-Control-Computed",
+            UAC_PASSWORD_EXPIRED,
+            VkLdapFailureReason::PasswordExpired,
+        ),
+    ];
+
+    for (attribute, flag, reason) in flag_checks {
+        if let Some(value) =
+            first_value(attribute).filter(|value| value.parse::<u32>().unwrap_or(0) & flag != 0)
+        {
+            return Err(VkLdapError::AccountStatusError(
+                reason,
+                format!("{attribute}={value}"),
+            ));
+        }
+    }
+
+    // A temporary lockout of the ppolicy overlay ends after pwdLockoutDuration
+    // without removing the attribute, so only the permanent lock is checked
+    // here, and the bind reports the temporary ones.
+    if let Some(locked_time) =
+        first_value("pwdAccountLockedTime").filter(|value| *value == PPOLICY_PERMANENT_LOCK)
+    {
+        return Err(VkLdapError::AccountStatusError(
+            VkLdapFailureReason::AccountLocked,
+            format!("pwdAccountLockedTime={locked_time}"),
+        ));
+    }
+
+    if let Some(lock) = first_value("nsAccountLock") {
+        if lock.eq_ignore_ascii_case("true") {
+            return Err(VkLdapError::AccountStatusError(
+                VkLdapFailureReason::AccountDisabled,
+                format!("nsAccountLock={lock}"),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Builds the filter of the user search. The username is escaped as a filter
+/// value (RFC 4515), so that characters like `*` or `)` cannot change the
+/// filter.
+fn user_search_filter(filter: &str, attribute: &str, username: &str) -> String {
+    let username = ldap_escape(username);
+    format!("(&({filter})({attribute}={username}))")
+}
+
 pub(super) struct VkLdapConnection {
     ldap_handler: Ldap,
+    // The host of the server URL, which names the Kerberos service principal
+    // of the server.
+    #[cfg(feature = "gssapi")]
+    server_host: String,
+    // The generation of the TLS material used to create the connection.
+    tls_generation: u64,
 }
 
 impl VkLdapConnection {
@@ -185,14 +609,172 @@ impl VkLdapConnection {
         let url = server.get_url_ref();
         debug!("creating LDAP connection to {url}");
 
-        let ldap_handler = Self::create_ldap_connection(&settings, url).await?;
-        Ok(VkLdapConnection { ldap_handler })
+        let ldap_handler = Self::create_ldap_connection(settings, url).await?;
+        let tls_generation = settings
+            .tls_material
+            .as_ref()
+            .map_or(0, |material| material.get_generation());
+        Ok(VkLdapConnection {
+            ldap_handler,
+            #[cfg(feature = "gssapi")]
+            server_host: url.host_str().unwrap_or_default().to_string(),
+            tls_generation,
+        })
+    }
+
+    /// Fails with a connection error if the connection was already closed,
+    /// e.g., by the server.
+    pub fn check_open(&mut self) -> Result<()> {
+        if self.ldap_handler.is_closed() {
+            return Err(VkLdapError::LdapConnectionError(LdapError::EndOfStream));
+        }
+        Ok(())
     }
 
-    pub async fn ping(&mut self) -> Result<()> {
+    pub async fn ping(&mut self, settings: &VkLdapSettings) -> Result<()> {
+        let timeout = settings.health_check_timeout;
+
+        match settings.effective_health_check() {
+            LdapHealthCheck::WhoAmI => {
+                handle_ldap_error!(
+                    self.ldap_handler
+                        .with_timeout(timeout)
+                        .extended(WhoAmI)
+                        .await,
+                    VkLdapError::LdapServerPingError
+                );
+            }
+            LdapHealthCheck::RootDse => {
+                handle_ldap_error!(
+                    self.ldap_handler
+                        .with_timeout(timeout)
+                        .search("", Scope::Base, "(objectClass=*)", vec!["1.1"])
+                        .await,
+                    VkLdapError::LdapServerPingError
+                );
+            }
+            LdapHealthCheck::ServiceBind => {
+                self.service_bind(settings, timeout).await?;
+            }
+            LdapHealthCheck::Search => {
+                self.service_bind(settings, timeout).await?;
+
+                let base = match &settings.health_check_search_base {
+                    Some(base) => base.as_str(),
+                    None => settings.search_base.as_deref().unwrap_or(""),
+                };
+                let filter = match &settings.health_check_search_filter {
+                    Some(filter) => format!("({filter})"),
+                    None => "(objectClass=*)".to_string(),
+                };
+
+                debug!("running health check search with base='{base}' filter='{filter}'");
+                handle_ldap_error!(
+                    self.ldap_handler
+                        .with_timeout(timeout)
+                        .search(base, Scope::Base, filter.as_str(), vec!["1.1"])
+                        .await,
+                    VkLdapError::LdapServerPingError
+                );
+            }
+        }
+        Ok(())
+    }
+
+    async fn service_bind(&mut self, settings: &VkLdapSettings, timeout: Duration) -> Result<()> {
+        match settings.search_bind_mechanism {
+            LdapBindMechanism::Simple => (),
+            LdapBindMechanism::External => {
+                // The service account is the identity of the client certificate
+                // presented in the TLS handshake.
+                debug!("running ldap admin SASL EXTERNAL bind");
+                handle_ldap_error!(
+                    self.ldap_handler
+                        .with_timeout(timeout)
+                        .sasl_external_bind()
+                        .await,
+                    VkLdapError::LdapAdminBindError
+                );
+                return Ok(());
+            }
+            LdapBindMechanism::Gssapi => return self.gssapi_bind(timeout).await,
+        }
+
+        let Some(bind_dn) = &settings.search_bind_dn else {
+            return Ok(());
+        };
+
+        let passwords = match &settings.search_bind_passwd_file {
+            Some(passwd_file) => passwd_file.passwords()?,
+            None => match &settings.search_bind_passwd {
+                Some(bind_passwd) => VkBindPasswords {
+                    primary: bind_passwd.clone(),
+                    secondary: None,
+                },
+                None => return Ok(()),
+            },
+        };
+
+        let res = self
+            .admin_simple_bind(bind_dn, &passwords.primary, timeout)
+            .await;
+
+        // During a password rotation, the directory may still expect the
+        // secondary password.
+        match (res, &passwords.secondary) {
+            (
+                Err(VkLdapError::LdapAdminBindError(LdapError::LdapResult { result })),
+                Some(secondary),
+            ) if result.rc == 49 => {
+                warn!(
+                    "the primary password of '{bind_dn}' was rejected, binding with the secondary \
+                     password of ldap.search_bind_passwd_file"
+                );
+                self.admin_simple_bind(bind_dn, secondary, timeout).await
+            }
+            (res, _) => res,
+        }
+    }
+
+    /// Binds with the Kerberos ticket of the credential cache, which is kept
+    /// valid by the Kerberos renewer when a keytab is set.
+    #[cfg(feature = "gssapi")]
+    async fn gssapi_bind(&mut self, timeout: Duration) -> Result<()> {
+        debug!(
+            "running ldap admin SASL GSSAPI bind with service 'ldap/{}'",
+            self.server_host
+        );
+        // The GSSAPI context is created when the bind is first polled, from
+        // this thread, so it uses the credential cache of the module.
+        super::kerberos::use_kerberos_ccache();
         handle_ldap_error!(
-            self.ldap_handler.extended(WhoAmI).await,
-            VkLdapError::LdapServerPingError
+            self.ldap_handler
+                .with_timeout(timeout)
+                .sasl_gssapi_bind(&self.server_host)
+                .await,
+            VkLdapError::LdapAdminBindError
+        );
+        Ok(())
+    }
+
+    #[cfg(not(feature = "gssapi"))]
+    async fn gssapi_bind(&mut self, _timeout: Duration) -> Result<()> {
+        Err(VkLdapError::GSSAPINotSupported)
+    }
+
+    async fn admin_simple_bind(
+        &mut self,
+        bind_dn: &str,
+        bind_passwd: &str,
+        timeout: Duration,
+    ) -> Result<()> {
+        debug!("running ldap admin bind with DN='{bind_dn}'");
+        handle_ldap_error!(
+            self.ldap_handler
+                .with_timeout(timeout)
+                .simple_bind(bind_dn, bind_passwd)
+                .await,
+            VkLdapError::LdapAdminBindError
         );
         Ok(())
     }
@@ -203,73 +785,122 @@ impl VkLdapConnection {
     ) -> Result<Ldap> {
         let mut ldap_conn_settings = LdapConnSettings::new();
 
-        let use_starttls = settings.use_starttls;
+        // The connections through a Unix socket are local, so the TLS settings
+        // do not apply to them.
+        let use_starttls = settings.use_starttls && server_url.scheme() != "ldapi";
         let requires_tls = server_url.scheme() == "ldaps" || use_starttls;
 
+        let mut material = None;
         if requires_tls {
-            let mut tls_builder = &mut TlsConnector::builder();
-
-            if let Some(path) = &settings.ca_cert_path {
-                let ca_cert_bytes =
-                    handle_io_error!(fs::read(path), "failed to read CA cert file".to_string());
-                let ca_cert = handle_tls_error!(
-                    Certificate::from_pem(&ca_cert_bytes),
-                    "failed to load CA certificate".to_string()
-                );
-                tls_builder = tls_builder.add_root_certificate(ca_cert);
-            }
+            let tls_material = match &settings.tls_material {
+                Some(material) => Arc::clone(material),
+                None => Arc::new(VkTlsMaterial::load(settings, &[], 0)?),
+            };
 
-            if let Some(cert_path) = &settings.client_cert_path {
-                match &settings.client_key_path {
-                    None => return Err(VkLdapError::NoTLSKeyPathSet),
-                    Some(key_path) => {
-                        let cert_bytes = handle_io_error!(
-                            fs::read(cert_path),
-                            "failed to read client certificate file".to_string()
-                        );
-                        let key_bytes = handle_io_error!(
-                            fs::read(key_path),
-                            "failed to read client key file".to_string()
-                        );
-                        let client_cert = handle_tls_error!(
-                            Identity::from_pkcs8(&cert_bytes, &key_bytes),
-                            "failed to load client certificate".to_string()
-                        );
-                        tls_builder = tls_builder.identity(client_cert);
-                    }
-                }
-            }
-
-            let tls_connector = handle_tls_error!(
-                tls_builder.build(),
-                "failed to setup TLS connection".to_string()
-            );
-
-            ldap_conn_settings = ldap_conn_settings.set_connector(tls_connector);
-            ldap_conn_settings = ldap_conn_settings.set_starttls(settings.use_starttls);
+            ldap_conn_settings = tls_material
+                .configure(settings, server_url, ldap_conn_settings)
+                .await?;
+            ldap_conn_settings = ldap_conn_settings.set_starttls(use_starttls);
+            material = Some(tls_material);
         }
 
         ldap_conn_settings = ldap_conn_settings.set_conn_timeout(settings.timeout_connection);
 
-        match LdapConnAsync::from_url_with_settings(ldap_conn_settings, &server_url).await {
-            Ok((conn, handler)) => {
-                ldap3::drive!(conn);
-                Ok(handler)
+        let mut handler =
+            match LdapConnAsync::from_url_with_settings(ldap_conn_settings, server_url).await {
+                Ok((conn, handler)) => {
+                    ldap3::drive!(conn);
+                    handler
+                }
+                Err(err) => {
+                    return Err(match &material {
+                        Some(material) => material.connection_error(err),
+                        None => VkLdapError::LdapConnectionError(err),
+                    });
+                }
+            };
+
+        if let Some(material) = material {
+            if let Err(err) = material
+                .verify_peer(settings, server_url, &mut handler)
+                .await
+            {
+                let _ = handler.unbind().await;
+                return Err(err);
             }
-            Err(err) => Err(VkLdapError::LdapConnectionError(err)),
         }
+
+        Ok(handler)
+    }
+
+    /// Binds as the user, requesting the password policy control. Returns the
+    /// password policy warnings sent by the server, if any.
+    pub async fn bind(
+        &mut self,
+        user_dn: &str,
+        password: &str,
+        timeout: Duration,
+    ) -> Result<PasswordPolicyResponse> {
+        self.user_bind(user_dn, password, timeout, false).await
+    }
+
+    /// Binds as the user to change its password. Unlike `bind`, it accepts the
+    /// bind of a user whose password must be changed, which the server only
+    /// allows to change the password. A failed bind is always an error, e.g.,
+    /// the one of Active Directory with the 773 sub-code, since the connection
+    /// is then not authenticated as the user.
+    pub async fn bind_to_change_password(
+        &mut self,
+        user_dn: &str,
+        password: &str,
+        timeout: Duration,
+    ) -> Result<()> {
+        self.user_bind(user_dn, password, timeout, true)
+            .await
+            .map(|_| ())
     }
 
-    pub async fn bind(&mut self, user_dn: &str, password: &str, timeout: Duration) -> Result<()> {
+    async fn user_bind(
+        &mut self,
+        user_dn: &str,
+        password: &str,
+        timeout: Duration,
+        allow_password_change_only: bool,
+    ) -> Result<PasswordPolicyResponse> {
         debug!("running ldap bind with DN='{user_dn}'");
-        handle_ldap_error!(
-            self.ldap_handler
-                .with_timeout(timeout)
-                .simple_bind(user_dn, password)
-                .await,
-            VkLdapError::LdapBindError
-        );
-        Ok(())
+        let res = self
+            .ldap_handler
+            .with_controls(PasswordPolicyRequest)
+            .with_timeout(timeout)
+            .simple_bind(user_dn, password)
+            .await;
+
+        let policy = match &res {
+            Ok(result) => PasswordPolicyResponse::from_controls(&result.ctrls),
+            Err(_) => PasswordPolicyResponse::default(),
+        };
+
+        // The password policy error is more precise than the result code, and
+        // the server accepts the bind of a user whose password must be changed,
+        // but only allows that user to change the password.
+        if let Some(error) = &policy.error {
+            if let Some(reason) = error.failure_reason() {
+                let bound = matches!(&res, Ok(result) if result.rc == 0);
+                if allow_password_change_only
+                    && bound
+                    && reason == VkLdapFailureReason::PasswordMustChange
+                {
+                    return Ok(policy);
+                }
+                return Err(VkLdapError::AccountStatusError(
+                    reason,
+                    format!("the password policy control reported {error}"),
+                ));
+            }
+        }
+
+        handle_ldap_error!(res, VkLdapError::from_bind_error);
+        Ok(policy)
     }
 
     pub async fn search(
@@ -278,35 +909,24 @@ impl VkLdapConnection {
         username: &str,
         timeout: Duration,
     ) -> Result<String> {
-        if let Some(bind_dn) = &settings.search_bind_dn {
-            if let Some(bind_passwd) = &settings.search_bind_passwd {
-                debug!("running ldap admin bind with DN='{bind_dn}'");
-                handle_ldap_error!(
-                    self.ldap_handler
-                        .with_timeout(timeout)
-                        .simple_bind(&bind_dn, &bind_passwd)
-                        .await,
-                    VkLdapError::LdapAdminBindError
-                );
-            }
-        }
+        self.service_bind(settings, timeout).await?;
 
         let mut base = "";
         if let Some(sbase) = &settings.search_base {
-            base = &sbase;
+            base = sbase;
         }
 
         let mut filter = "objectClass=*";
         if let Some(sfilter) = &settings.search_filter {
-            filter = &sfilter;
+            filter = sfilter;
         }
 
         let mut attribute = "uid";
         if let Some(sattribute) = &settings.search_attribute {
-            attribute = &sattribute;
+            attribute = sattribute;
         }
 
-        let search_filter = format!("(&({filter})({attribute}={username}))");
+        let search_filter = user_search_filter(filter, attribute, username);
         let scope = settings.search_scope;
         let dn_attribute = &settings.search_dn_attribute;
 
@@ -314,6 +934,11 @@ impl VkLdapConnection {
             "running ldap search with filter='{search_filter}' scope='{:?}' attribute='{dn_attribute}'",
             scope
         );
+        let mut attributes = vec![dn_attribute.as_str()];
+        if settings.search_account_status_check {
+            attributes.extend(ACCOUNT_STATUS_ATTRIBUTES);
+        }
+
         let (rs, _res) = handle_ldap_error!(
             self.ldap_handler
                 .with_timeout(timeout)
@@ -321,13 +946,13 @@ impl VkLdapConnection {
                     base,
                     settings.search_scope,
                     search_filter.as_str(),
-                    vec![dn_attribute],
+                    attributes,
                 )
                 .await,
             VkLdapError::LdapSearchError
         );
 
-        if rs.len() == 0 {
+        if rs.is_empty() {
             return Err(VkLdapError::NoLdapEntryFound(search_filter));
         }
 
@@ -345,10 +970,225 @@ impl VkLdapConnection {
             return Err(VkLdapError::InvalidDNAttribute(dn_attribute.clone()));
         }
 
+        if settings.search_account_status_check {
+            check_account_status(&sentry)?;
+        }
+
         Ok(sentry.attrs[dn_attribute][0].clone())
     }
 
+    /// Runs an arbitrary search bound as the service account. When the size
+    /// limit is exceeded, the entries returned until then are kept.
+    pub async fn search_entries(
+        &mut self,
+        settings: &VkLdapSettings,
+        request: &VkLdapSearchRequest,
+        timeout: Duration,
+    ) -> Result<Vec<VkLdapSearchEntry>> {
+        self.service_bind(settings, timeout).await?;
+
+        let scope: Scope = request.scope.clone().into();
+        debug!(
+            "running ldap search with base='{}' filter='{}' scope='{:?}'",
+            request.base, request.filter, scope
+        );
+        let res = self
+            .ldap_handler
+            .with_search_options(SearchOptions::new().sizelimit(request.size_limit))
+            .with_timeout(timeout)
+            .search(
+                request.base.as_str(),
+                scope,
+                request.filter.as_str(),
+                request
+                    .attributes
+                    .iter()
+                    .map(String::as_str)
+                    .collect::<Vec<&str>>(),
+            )
+            .await;
+
+        // The sizeLimitExceeded result code comes with the entries found until
+        // the limit was reached.
+        let rs = match res {
+            Ok(SearchResult(rs, result)) if result.rc == 4 => rs,
+            res => {
+                let (rs, _res) = handle_ldap_error!(res, VkLdapError::LdapSearchError);
+                rs
+            }
+        };
+
+        let entries = rs
+            .into_iter()
+            .filter(|entry| !entry.is_ref() && !entry.is_intermediate())
+            .map(|entry| {
+                let sentry = SearchEntry::construct(entry);
+                let mut attributes: Vec<(String, Vec<Vec<u8>>)> = sentry
+                    .attrs
+                    .into_iter()
+                    .map(|(name, values)| {
+                        (name, values.into_iter().map(String::into_bytes).collect())
+                    })
+                    .chain(sentry.bin_attrs)
+                    .collect();
+                attributes.sort_by(|(a, _), (b, _)| a.cmp(b));
+                VkLdapSearchEntry {
+                    dn: sentry.dn,
+                    attributes,
+                }
+            })
+            .collect();
+
+        Ok(entries)
+    }
+
+    /// Checks that the user entry still exists, matches the revalidation
+    /// filter, which can be used to exclude users that are no longer members
+    /// of a group, and that its account status attributes do not report a
+    /// disabled or locked account.
+    pub async fn check_user(
+        &mut self,
+        settings: &VkLdapSettings,
+        user_dn: &str,
+        timeout: Duration,
+    ) -> Result<()> {
+        self.service_bind(settings, timeout).await?;
+
+        let filter = match &settings.revalidation_filter {
+            Some(filter) => format!("({filter})"),
+            None => "(objectClass=*)".to_string(),
+        };
+
+        debug!("running ldap user check with base='{user_dn}' filter='{filter}'");
+        let res = self
+            .ldap_handler
+            .with_timeout(timeout)
+            .search(
+                user_dn,
+                Scope::Base,
+                filter.as_str(),
+                ACCOUNT_STATUS_ATTRIBUTES.to_vec(),
+            )
+            .await;
+
+        // A user entry that no longer exists is reported with the noSuchObject
+        // result code.
+        if let Ok(SearchResult(_, result)) = &res {
+            if result.rc == 32 {
+                return Err(VkLdapError::NoLdapEntryFound(filter));
+            }
+        }
+
+        let (rs, _res) = handle_ldap_error!(res, VkLdapError::LdapSearchError);
+        let Some(entry) = rs.into_iter().next() else {
+            return Err(VkLdapError::NoLdapEntryFound(filter));
+        };
+
+        // The account may have been disabled or locked since the client
+        // authenticated.
+        check_account_status(&SearchEntry::construct(entry))
+    }
+
+    /// Changes the password of the user bound to this connection with the
+    /// Password Modify extended operation (RFC 3062).
+    pub async fn modify_password(
+        &mut self,
+        user_dn: &str,
+        old_password: &str,
+        new_password: &str,
+        timeout: Duration,
+    ) -> Result<()> {
+        debug!("running ldap password modify operation for DN='{user_dn}'");
+        handle_ldap_error!(
+            self.ldap_handler
+                .with_timeout(timeout)
+                .extended(PasswordModify {
+                    user_id: Some(user_dn),
+                    old_pass: Some(old_password),
+                    new_pass: Some(new_password),
+                })
+                .await,
+            VkLdapError::LdapPasswordModifyError
+        );
+        Ok(())
+    }
+
     pub async fn close(&mut self) {
         let _ = self.ldap_handler.unbind().await;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn entry(attribute: &str, value: &str) -> SearchEntry {
+        SearchEntry {
+            dn: "cn=user,dc=valkey,dc=io".to_string(),
+            attrs: HashMap::from([(attribute.to_string(), vec![value.to_string()])]),
+            bin_attrs: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_check_account_status_ppolicy_lock() {
+        let err = check_account_status(&entry("pwdAccountLockedTime", "000001010000Z"));
+        assert!(matches!(
+            err,
+            Err(VkLdapError::AccountStatusError(
+                VkLdapFailureReason::AccountLocked,
+                _
+            ))
+        ));
+
+        // Temporary lockouts are left to the bind, since they may have ended.
+        assert!(check_account_status(&entry("pwdAccountLockedTime", "20250602101542Z")).is_ok());
+    }
+
+    #[test]
+    fn test_user_search_filter_escapes_username() {
+        assert_eq!(
+            user_search_filter("objectClass=*", "uid", "user1"),
+            "(&(objectClass=*)(uid=user1))"
+        );
+        assert_eq!(
+            user_search_filter("objectClass=*", "uid", "*)(uid=admin\\"),
+            "(&(objectClass=*)(uid=\\2a\\29\\28uid=admin\\5c))"
+        );
+    }
+
+    #[test]
+    fn test_check_account_status_ad_computed_flags() {
+        let err = check_account_status(&entry("msDS-User-Account-Control-Computed", "16"));
+        assert!(matches!(
+            err,
+            Err(VkLdapError::AccountStatusError(
+                VkLdapFailureReason::AccountLocked,
+                _
+            ))
+        ));
+
+        let err = check_account_status(&entry("msDS-User-Account-Control-Computed", "8388608"));
+        assert!(matches!(
+            err,
+            Err(VkLdapError::AccountStatusError(
+                VkLdapFailureReason::PasswordExpired,
+                _
+            ))
+        ));
+
+        let err = check_account_status(&entry("userAccountControl", "514"));
+        assert!(matches!(
+            err,
+            Err(VkLdapError::AccountStatusError(
+                VkLdapFailureReason::AccountDisabled,
+                _
+            ))
+        ));
+
+        // Active Directory never keeps the lockout flag of userAccountControl
+        // current, so it is not trusted.
+        assert!(check_account_status(&entry("userAccountControl", "528")).is_ok());
+    }
+}
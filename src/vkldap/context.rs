@@ -1,23 +1,37 @@
 use lazy_static::lazy_static;
-use std::{sync::Arc, time::Duration};
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
 
-use log::{debug, info};
+use futures::stream::{FuturesUnordered, StreamExt};
+use log::{debug, error, info, warn};
 use tokio::sync::Mutex;
 use url::Url;
 
 use super::{
-    Result,
-    connection::{VkConnectionPool, VkLdapConnection, VkLdapPoolConnection},
+    Result, VkLdapAuthInfo, VkLdapAuthResult, VkLdapSearchEntry, VkLdapSearchRequest,
+    VkLdapServerAction, VkLdapServerCounters, VkLdapServerDetails,
+    connection::{VkConnectionPool, VkLdapConnection, VkLdapPoolConnection, VkPoolConnectionGuard},
     errors::VkLdapError,
-    server::{VkLdapServer, VkLdapServerStatus},
+    latency::VkLatencyTracker,
+    server::{VkLdapServer, VkLdapServerOptions, VkLdapServerStatus},
     settings::{VkConnectionSettings, VkLdapSettings},
+    tls::VkTlsMaterial,
 };
 
 struct VkLdapContext {
     servers: Vec<VkLdapServer>,
     conn_pools: Vec<Arc<VkConnectionPool>>,
+    latencies: Vec<VkLatencyTracker>,
+    counters: Vec<VkLdapServerCounters>,
     ldap_settings: VkLdapSettings,
     connection_settings: VkConnectionSettings,
+    tls_material: Option<Arc<VkTlsMaterial>>,
+    tls_generation: u64,
 }
 
 impl VkLdapContext {
@@ -25,8 +39,12 @@ impl VkLdapContext {
         VkLdapContext {
             servers: Vec::new(),
             conn_pools: Vec::new(),
+            latencies: Vec::new(),
+            counters: Vec::new(),
             ldap_settings: VkLdapSettings::default(),
             connection_settings: VkConnectionSettings::default(),
+            tls_material: None,
+            tls_generation: 0,
         }
     }
 
@@ -34,11 +52,43 @@ impl VkLdapContext {
         self.ldap_settings.clone()
     }
 
-    fn get_connection_settings(&self) -> VkConnectionSettings {
-        self.connection_settings.clone()
+    fn get_server_connection_settings(&self, server: &VkLdapServer) -> VkConnectionSettings {
+        let mut settings = self.connection_settings.for_server(server);
+        settings.tls_material = self.tls_material.clone();
+        settings
+    }
+
+    fn get_server_ca_cert_paths(&self) -> Vec<String> {
+        self.servers
+            .iter()
+            .filter_map(|s| s.get_options().ca_cert_path.clone())
+            .collect()
+    }
+
+    fn next_tls_generation(&mut self) -> u64 {
+        self.tls_generation += 1;
+        self.tls_generation
+    }
+
+    fn set_tls_material(&mut self, material: Option<VkTlsMaterial>) {
+        self.tls_material = material.map(Arc::new);
+    }
+
+    fn get_tls_material(&self) -> Option<Arc<VkTlsMaterial>> {
+        self.tls_material.clone()
     }
 
     fn refresh_ldap_settings(&mut self, settings: VkLdapSettings) {
+        let falls_back = settings.effective_health_check() != settings.health_check;
+        let fell_back =
+            self.ldap_settings.effective_health_check() != self.ldap_settings.health_check;
+        if falls_back && !fell_back {
+            warn!(
+                "the search bind user has no credentials to bind with, the health checks run \
+                 the whoami probe instead"
+            );
+        }
+
         self.ldap_settings = settings
     }
 
@@ -55,17 +105,95 @@ impl VkLdapContext {
         }
 
         self.conn_pools.clear();
+        self.latencies.clear();
+        self.counters.clear();
         pools
     }
 
-    fn new_server(&self, server_url: Url) -> VkLdapServer {
+    fn new_server(&self, server_url: Url, options: VkLdapServerOptions) -> VkLdapServer {
         let server_id = self.servers.len();
-        VkLdapServer::new(server_url, server_id, VkLdapServerStatus::HEALTHY)
+        VkLdapServer::new(server_url, server_id, VkLdapServerStatus::HEALTHY, options)
     }
 
     fn add_server(&mut self, server: VkLdapServer, pool: VkConnectionPool) {
         self.servers.push(server);
         self.conn_pools.push(Arc::new(pool));
+        self.latencies.push(VkLatencyTracker::new());
+        self.counters.push(VkLdapServerCounters::default());
+    }
+
+    fn is_current_server(&self, server: &VkLdapServer) -> bool {
+        match self.servers.get(server.get_id()) {
+            Some(current) => current.get_url_ref() == server.get_url_ref(),
+            None => false,
+        }
+    }
+
+    fn record_latency(&mut self, server: &VkLdapServer, latency: Duration) {
+        if self.is_current_server(server) {
+            self.latencies[server.get_id()].record(latency);
+        }
+    }
+
+    fn record_operation(&mut self, server: &VkLdapServer, connection_error: bool) {
+        if self.is_current_server(server) {
+            let counters = &mut self.counters[server.get_id()];
+            counters.operations += 1;
+            if connection_error {
+                counters.connection_errors += 1;
+            }
+        }
+    }
+
+    fn get_servers_details(&self) -> Vec<VkLdapServerDetails> {
+        self.servers
+            .iter()
+            .map(|server| VkLdapServerDetails {
+                server: server.clone(),
+                pool: self.conn_pools[server.get_id()].get_stats(),
+                counters: self.counters[server.get_id()].clone(),
+            })
+            .collect()
+    }
+
+    fn find_server_by_name(&self, name: &str) -> Result<VkLdapServer> {
+        self.servers
+            .iter()
+            .find(|s| {
+                s.get_id().to_string() == name
+                    || s.get_url_ref().as_str() == name
+                    || s.get_host_string() == name
+            })
+            .cloned()
+            .ok_or_else(|| VkLdapError::UnknownServer(name.to_string()))
+    }
+
+    fn set_server_marked_down(&mut self, server: &VkLdapServer, marked_down: bool) {
+        if self.is_current_server(server) {
+            let server = &mut self.servers[server.get_id()];
+            let url = server.get_url_ref();
+            if marked_down {
+                info!("server {url} marked down by an administrator");
+            } else {
+                info!("server {url} marked up by an administrator");
+            }
+            server.set_marked_down(marked_down);
+        }
+    }
+
+    /// Returns the hedging delay of the operations on the server: the latency
+    /// percentile when it is set and enough latencies were recorded, and the
+    /// fixed delay otherwise. Either of them enables hedging.
+    fn get_hedge_delay(&self, server: &VkLdapServer) -> Option<Duration> {
+        if let Some(percentile) = self.ldap_settings.hedge_delay_percentile {
+            if self.is_current_server(server) {
+                if let Some(latency) = self.latencies[server.get_id()].percentile(percentile) {
+                    return Some(latency);
+                }
+            }
+        }
+
+        self.ldap_settings.hedge_delay
     }
 
     fn get_connection_pool(&self, server: &VkLdapServer) -> Arc<VkConnectionPool> {
@@ -84,14 +212,11 @@ impl VkLdapContext {
         status: VkLdapServerStatus,
         ping_time: Option<Duration>,
     ) {
-        if server.get_id() >= self.servers.len() {
-            return ();
+        if !self.is_current_server(server) {
+            return;
         }
 
         let server = &mut self.servers[server.get_id()];
-        if server.get_url_ref() != server.get_url_ref() {
-            return ();
-        }
 
         if server.get_status() != status {
             let pre_status = server.get_status();
@@ -110,27 +235,76 @@ impl VkLdapContext {
             return Err(VkLdapError::NoServerConfigured);
         }
 
-        for server in self.servers.iter() {
-            if server.is_healthy() {
-                return Ok(server.clone());
+        // Degraded servers are only used when there is no healthy server left.
+        self.select_server(VkLdapServer::is_healthy)
+            .or_else(|| self.select_server(VkLdapServer::is_available))
+            .ok_or(VkLdapError::NoHealthyServerAvailable)
+    }
+
+    fn find_hedge_server(&self, excluded: &[usize]) -> Option<VkLdapServer> {
+        let is_candidate = |s: &VkLdapServer| !excluded.contains(&s.get_id());
+
+        self.select_server(|s| s.is_healthy() && is_candidate(s))
+            .or_else(|| self.select_server(|s| s.is_available() && is_candidate(s)))
+    }
+
+    fn select_server<P>(&self, is_eligible: P) -> Option<VkLdapServer>
+    where
+        P: Fn(&VkLdapServer) -> bool,
+    {
+        let priority = self
+            .servers
+            .iter()
+            .filter(|s| is_eligible(s))
+            .map(|s| s.get_options().priority)
+            .min()?;
+
+        let candidates: Vec<&VkLdapServer> = self
+            .servers
+            .iter()
+            .filter(|s| is_eligible(s) && s.get_options().priority == priority)
+            .collect();
+
+        let weights: Vec<u32> = candidates
+            .iter()
+            .map(|s| s.get_options().weight.unwrap_or(0))
+            .collect();
+        Some(candidates[pick_weighted_index(&weights)].clone())
+    }
+}
+
+/// Picks an index at random proportionally to its weight. Entries with weight
+/// `0` are never picked when any other entry has a weight, and the first entry
+/// is picked when none of them has a weight, so that the list order is kept.
+/// Server lists mixing servers with and without a weight are rejected, so the
+/// entries with weight `0` here are either all of them or explicitly drained.
+fn pick_weighted_index(weights: &[u32]) -> usize {
+    let total_weight: u64 = weights.iter().map(|w| *w as u64).sum();
+    if total_weight > 0 {
+        let mut pick = rand::random_range(0..total_weight);
+        for (idx, weight) in weights.iter().enumerate() {
+            let weight = *weight as u64;
+            if pick < weight {
+                return idx;
             }
+            pick -= weight;
         }
-
-        Err(VkLdapError::NoHealthyServerAvailable)
     }
+
+    0
 }
 
 lazy_static! {
     static ref VK_LDAP_CONTEXT: Mutex<VkLdapContext> = Mutex::new(VkLdapContext::new());
 }
 
-pub(super) async fn add_server(server_url: Url) {
+pub(super) async fn add_server(server_url: Url, options: VkLdapServerOptions) {
     let mut server;
     let settings;
     {
         let ldap_ctx = VK_LDAP_CONTEXT.lock().await;
-        server = ldap_ctx.new_server(server_url);
-        settings = ldap_ctx.get_connection_settings();
+        server = ldap_ctx.new_server(server_url, options);
+        settings = ldap_ctx.get_server_connection_settings(&server);
     }
 
     let (pool, res) = VkConnectionPool::new(server.clone(), &settings).await;
@@ -155,17 +329,93 @@ pub async fn refresh_ldap_settings(settings: VkLdapSettings) {
     VK_LDAP_CONTEXT.lock().await.refresh_ldap_settings(settings);
 }
 
+/// Loads the TLS files of the current settings. When the files cannot be
+/// loaded, the connections read them again when they are created, and fail
+/// with the respective error if they require TLS.
+async fn load_tls_material() -> Result<VkTlsMaterial> {
+    let (settings, ca_paths, generation) = {
+        let mut ldap_ctx = VK_LDAP_CONTEXT.lock().await;
+        (
+            ldap_ctx.connection_settings.clone(),
+            ldap_ctx.get_server_ca_cert_paths(),
+            ldap_ctx.next_tls_generation(),
+        )
+    };
+
+    VkTlsMaterial::load(&settings, &ca_paths, generation)
+}
+
 pub async fn refresh_connection_settings(settings: VkConnectionSettings) {
     VK_LDAP_CONTEXT
         .lock()
         .await
         .refresh_connection_settings(settings);
 
+    let material = match load_tls_material().await {
+        Ok(material) => Some(material),
+        Err(err) => {
+            debug!("failed to load the TLS files: {err}");
+            None
+        }
+    };
+    VK_LDAP_CONTEXT.lock().await.set_tls_material(material);
+
     let servers = VK_LDAP_CONTEXT.lock().await.get_current_servers();
 
     for server in servers {
-        refresh_pool_connections(&server).await
+        let _ = refresh_pool_connections(&server).await;
+    }
+}
+
+/// Reloads the TLS files, if they changed on disk or `force` is set, and then
+/// gradually replaces the connections of every server. The current files are
+/// kept when the new ones fail to load. Returns whether the files were
+/// reloaded.
+pub(super) async fn reload_tls_material(force: bool) -> Result<bool> {
+    let current = VK_LDAP_CONTEXT.lock().await.get_tls_material();
+    if let Some(current) = &current {
+        if !force && !current.has_changed_on_disk() {
+            return Ok(false);
+        }
+    }
+
+    let material = load_tls_material().await?;
+    let generation = material.get_generation();
+
+    let servers = {
+        let mut ldap_ctx = VK_LDAP_CONTEXT.lock().await;
+        ldap_ctx.set_tls_material(Some(material));
+        ldap_ctx.get_current_servers()
+    };
+    info!("loaded the TLS files (generation {generation}), rolling the LDAP connections");
+
+    for server in servers {
+        let (pool, settings) = {
+            let ldap_ctx = VK_LDAP_CONTEXT.lock().await;
+            if !ldap_ctx.is_current_server(&server) {
+                continue;
+            }
+            (
+                ldap_ctx.get_connection_pool(&server),
+                ldap_ctx.get_server_connection_settings(&server),
+            )
+        };
+
+        tokio::spawn(async move {
+            if let Err(err) = pool.roll_connections(&settings).await {
+                let url = server.get_url_ref();
+                error!(
+                    "failed to roll the connections of server {url} to the new TLS files: {err}"
+                );
+            }
+        });
     }
+
+    Ok(true)
+}
+
+pub(super) async fn get_ldap_settings() -> VkLdapSettings {
+    VK_LDAP_CONTEXT.lock().await.get_ldap_settings()
 }
 
 pub(super) async fn get_servers_health_status() -> Vec<VkLdapServer> {
@@ -173,11 +423,14 @@ pub(super) async fn get_servers_health_status() -> Vec<VkLdapServer> {
 }
 
 pub(super) async fn get_connection(server: &VkLdapServer) -> Result<VkLdapConnection> {
-    let settings = VK_LDAP_CONTEXT.lock().await.get_connection_settings();
-    VkLdapConnection::new(&settings, &server).await
+    let settings = VK_LDAP_CONTEXT
+        .lock()
+        .await
+        .get_server_connection_settings(server);
+    VkLdapConnection::new(&settings, server).await
 }
 
-pub(super) async fn get_pool_connection(server: &VkLdapServer) -> VkLdapPoolConnection {
+pub(super) async fn get_pool_connection(server: &VkLdapServer) -> Result<VkLdapPoolConnection> {
     let pool = VK_LDAP_CONTEXT.lock().await.get_connection_pool(server);
     pool.take_connection().await
 }
@@ -201,98 +454,432 @@ pub(super) async fn update_server_status(
         .update_server_status(server, status, ping_time)
 }
 
-pub(super) async fn refresh_pool_connections(server: &VkLdapServer) {
+pub(super) async fn refresh_pool_connections(server: &VkLdapServer) -> Result<()> {
     let pool;
     let settings;
     {
         let ldap_ctx = VK_LDAP_CONTEXT.lock().await;
         pool = ldap_ctx.get_connection_pool(server);
-        settings = ldap_ctx.get_connection_settings();
+        settings = ldap_ctx.get_server_connection_settings(server);
     }
 
     match pool.refresh_connections(&settings).await {
-        Ok(_) => update_server_status(server, VkLdapServerStatus::HEALTHY, None).await,
+        Ok(_) => {
+            update_server_status(server, VkLdapServerStatus::HEALTHY, None).await;
+            Ok(())
+        }
         Err(err) => {
-            update_server_status(server, VkLdapServerStatus::UNHEALTHY(err.to_string()), None).await
+            let status = VkLdapServerStatus::UNHEALTHY(err.to_string());
+            update_server_status(server, status, None).await;
+            Err(err)
+        }
+    }
+}
+
+pub(super) async fn get_servers_details() -> Vec<VkLdapServerDetails> {
+    VK_LDAP_CONTEXT.lock().await.get_servers_details()
+}
+
+pub(super) async fn run_server_action(name: String, action: VkLdapServerAction) -> Result<()> {
+    let server = VK_LDAP_CONTEXT.lock().await.find_server_by_name(&name)?;
+
+    match action {
+        VkLdapServerAction::MarkDown => {
+            VK_LDAP_CONTEXT
+                .lock()
+                .await
+                .set_server_marked_down(&server, true);
+            Ok(())
+        }
+        VkLdapServerAction::MarkUp => {
+            VK_LDAP_CONTEXT
+                .lock()
+                .await
+                .set_server_marked_down(&server, false);
+            refresh_pool_connections(&server).await
+        }
+        VkLdapServerAction::RefreshPool => refresh_pool_connections(&server).await,
+    }
+}
+
+async fn run_ldap_op_on_server<F, T>(
+    ldap_op: &F,
+    server: VkLdapServer,
+    pool: Arc<VkConnectionPool>,
+) -> (VkLdapServer, VkLdapAuthInfo, Result<T>)
+where
+    F: AsyncFn(&mut VkLdapConnection, &mut VkLdapAuthInfo) -> Result<T>,
+{
+    let mut info = VkLdapAuthInfo {
+        server: Some(server.get_url_ref().to_string()),
+        ..Default::default()
+    };
+    let mut guard = match VkPoolConnectionGuard::take(pool).await {
+        Ok(guard) => guard,
+        Err(err) => return (server, info, Err(err)),
+    };
+
+    let sample = VkLatencySample::new(&server);
+    let op_res = guard
+        .run(async |conn: &mut VkLdapConnection| ldap_op(conn, &mut info).await)
+        .await;
+
+    let connection_error = matches!(op_res, Err(VkLdapError::LdapConnectionError(_)));
+    sample.finish(connection_error).await;
+
+    (server, info, op_res)
+}
+
+/// Records an operation on a server and its latency. An operation that is
+/// dropped before it completes, because it lost a hedge or was cancelled,
+/// still records the time it ran for, so that the slow operations are not
+/// left out of the latency percentile used as the hedging delay.
+struct VkLatencySample {
+    server: Option<VkLdapServer>,
+    start: Instant,
+}
+
+impl VkLatencySample {
+    fn new(server: &VkLdapServer) -> VkLatencySample {
+        VkLatencySample {
+            server: Some(server.clone()),
+            start: Instant::now(),
+        }
+    }
+
+    async fn finish(mut self, connection_error: bool) {
+        let latency = self.start.elapsed();
+        if let Some(server) = self.server.take() {
+            let mut ldap_ctx = VK_LDAP_CONTEXT.lock().await;
+            ldap_ctx.record_operation(&server, connection_error);
+            if !connection_error {
+                ldap_ctx.record_latency(&server, latency);
+            }
         }
     }
 }
 
-async fn run_ldap_op_with_failover<F>(ldap_op: F) -> Result<()>
+impl Drop for VkLatencySample {
+    fn drop(&mut self) {
+        if let Some(server) = self.server.take() {
+            let latency = self.start.elapsed();
+            tokio::spawn(async move {
+                VK_LDAP_CONTEXT
+                    .lock()
+                    .await
+                    .record_latency(&server, latency);
+            });
+        }
+    }
+}
+
+/// Runs the LDAP operation on an available server, and fails over to the next
+/// one on connection errors. When `hedge` is set, the operation is also started
+/// on another server if it takes longer than the hedging delay, so it must be
+/// safe to run twice.
+async fn run_ldap_op_with_failover<F, T>(hedge: bool, ldap_op: F) -> (VkLdapAuthInfo, Result<T>)
 where
-    F: AsyncFn(&mut VkLdapConnection) -> Result<()>,
+    F: AsyncFn(&mut VkLdapConnection, &mut VkLdapAuthInfo) -> Result<T>,
 {
     loop {
         let server;
         let pool;
+        let hedge_delay;
         {
             let ldap_ctx = VK_LDAP_CONTEXT.lock().await;
-            server = ldap_ctx.find_server()?;
+            server = match ldap_ctx.find_server() {
+                Ok(server) => server,
+                Err(err) => return (VkLdapAuthInfo::default(), Err(err)),
+            };
             pool = ldap_ctx.get_connection_pool(&server);
+            hedge_delay = ldap_ctx.get_hedge_delay(&server);
         }
 
-        let mut pool_conn = pool.take_connection().await;
+        let mut tried = vec![server.get_id()];
+        let mut attempts = FuturesUnordered::new();
+        attempts.push(run_ldap_op_on_server(&ldap_op, server, pool));
+
+        let hedge_timer = tokio::time::sleep(hedge_delay.unwrap_or_default());
+        tokio::pin!(hedge_timer);
+        let mut hedged = !hedge || hedge_delay.is_none();
+
+        // The first attempt that does not fail with a connection error wins. The
+        // remaining attempts are dropped, which closes and replaces their
+        // connections.
+        while !attempts.is_empty() {
+            tokio::select! {
+                Some((server, info, op_res)) = attempts.next() => {
+                    if let Err(
+                        err @ (VkLdapError::LdapConnectionError(_)
+                        | VkLdapError::NoPoolConnectionAvailable(_)),
+                    ) = &op_res
+                    {
+                        let status = VkLdapServerStatus::UNHEALTHY(err.to_string());
+                        update_server_status(&server, status, None).await;
+
+                        debug!(
+                            "got connection error during ldap operation, failing over to other available server..."
+                        );
+                        continue;
+                    }
+
+                    return (info, op_res);
+                }
+                _ = &mut hedge_timer, if !hedged => {
+                    hedged = true;
+
+                    let hedge_server;
+                    let hedge_pool;
+                    {
+                        let ldap_ctx = VK_LDAP_CONTEXT.lock().await;
+                        match ldap_ctx.find_hedge_server(&tried) {
+                            Some(server) => {
+                                hedge_pool = ldap_ctx.get_connection_pool(&server);
+                                hedge_server = server;
+                            }
+                            None => continue,
+                        }
+                    }
+
+                    debug!(
+                        "ldap operation is taking too long, hedging request to server {}",
+                        hedge_server.get_url_ref()
+                    );
+                    tried.push(hedge_server.get_id());
+                    attempts.push(run_ldap_op_on_server(&ldap_op, hedge_server, hedge_pool));
+                }
+            }
+        }
+    }
+}
 
-        let op_res = ldap_op(&mut pool_conn.conn).await;
+/// Runs the LDAP operation on an available server, for the operations that
+/// must not be sent twice. It fails over to the next server on connection
+/// errors only until the operation sets `sent`, right before sending the
+/// request that cannot be repeated, and is never hedged.
+async fn run_ldap_op_with_failover_until_sent<F, T>(
+    sent: Arc<AtomicBool>,
+    ldap_op: F,
+) -> (VkLdapAuthInfo, Result<T>)
+where
+    F: AsyncFn(&mut VkLdapConnection, &mut VkLdapAuthInfo) -> Result<T>,
+{
+    loop {
+        let server;
+        let pool;
+        {
+            let ldap_ctx = VK_LDAP_CONTEXT.lock().await;
+            server = match ldap_ctx.find_server() {
+                Ok(server) => server,
+                Err(err) => return (VkLdapAuthInfo::default(), Err(err)),
+            };
+            pool = ldap_ctx.get_connection_pool(&server);
+        }
 
-        tokio::spawn(async move { pool.return_connection(pool_conn).await });
+        sent.store(false, Ordering::Relaxed);
+        let (server, info, op_res) = run_ldap_op_on_server(&ldap_op, server, pool).await;
 
-        if let Err(err) = &op_res {
-            if let VkLdapError::LdapConnectionError(_) = err {
-                let err_msg = err.to_string();
-                update_server_status(&server, VkLdapServerStatus::UNHEALTHY(err_msg), None).await;
+        if let Err(
+            err @ (VkLdapError::LdapConnectionError(_) | VkLdapError::NoPoolConnectionAvailable(_)),
+        ) = &op_res
+        {
+            let status = VkLdapServerStatus::UNHEALTHY(err.to_string());
+            update_server_status(&server, status, None).await;
 
+            if !sent.load(Ordering::Relaxed) {
                 debug!(
-                    "got connection error during ldap operation, failing over to other available server..."
+                    "got connection error before sending the ldap operation, failing over to other available server..."
                 );
                 continue;
             }
         }
 
-        return op_res;
+        return (info, op_res);
     }
 }
 
-pub(super) async fn ldap_bind(username: String, password: String) -> Result<()> {
+/// Runs the LDAP operation, i.e., the future of `run_ldap_op_with_failover` or
+/// `run_ldap_op_with_failover_until_sent`, bounded by the total authentication deadline, which
+/// covers waiting for pool connections and every attempt.
+async fn run_ldap_op_with_deadline<O>(timeout: Option<Duration>, ldap_op: O) -> VkLdapAuthResult
+where
+    O: Future<Output = (VkLdapAuthInfo, Result<()>)>,
+{
+    let now = Instant::now();
+
+    let (mut info, result) = match timeout {
+        None => ldap_op.await,
+        Some(timeout) => match tokio::time::timeout(timeout, ldap_op).await {
+            Ok(res) => res,
+            Err(_) => {
+                debug!("ldap operation did not complete within the authentication deadline");
+                (
+                    VkLdapAuthInfo::default(),
+                    Err(VkLdapError::AuthTimeout(timeout)),
+                )
+            }
+        },
+    };
+
+    info.latency = now.elapsed();
+    VkLdapAuthResult { result, info }
+}
+
+pub(super) async fn ldap_bind(username: String, password: String) -> VkLdapAuthResult {
     let settings = VK_LDAP_CONTEXT.lock().await.get_ldap_settings();
+    let timeout_auth_total = settings.timeout_auth_total;
+    let hedge_binds = settings.hedge_binds;
 
     let prefix = settings.bind_db_prefix;
     let suffix = settings.bind_db_suffix;
     let user_dn = format!("{prefix}{username}{suffix}");
 
-    run_ldap_op_with_failover(async move |conn| {
-        conn.bind(
-            user_dn.as_str(),
-            password.as_str(),
-            settings.timeout_ldap_operation,
-        )
-        .await
-    })
-    .await
+    let ldap_op = run_ldap_op_with_failover(hedge_binds, async move |conn, info| {
+        info.user_dn = Some(user_dn.clone());
+        let policy = conn
+            .bind(
+                user_dn.as_str(),
+                password.as_str(),
+                settings.timeout_ldap_operation,
+            )
+            .await?;
+        info.password_expires_in = policy.time_before_expiration;
+        info.grace_logins_remaining = policy.grace_logins_remaining;
+        Ok(())
+    });
+    run_ldap_op_with_deadline(timeout_auth_total, ldap_op).await
 }
 
-pub(super) async fn ldap_search_and_bind(username: String, password: String) -> Result<()> {
+pub(super) async fn ldap_search_and_bind(username: String, password: String) -> VkLdapAuthResult {
     let settings = VK_LDAP_CONTEXT.lock().await.get_ldap_settings();
+    let timeout_auth_total = settings.timeout_auth_total;
+    let hedge_binds = settings.hedge_binds;
 
-    run_ldap_op_with_failover(async move |conn| {
-        let search_res = conn
+    let ldap_op = run_ldap_op_with_failover(hedge_binds, async move |conn, info| {
+        let user_dn = conn
             .search(
                 &settings,
                 username.as_str(),
                 settings.timeout_ldap_operation,
             )
-            .await;
-        match search_res {
-            Ok(user_dn) => {
-                conn.bind(
-                    user_dn.as_str(),
-                    password.as_str(),
-                    settings.timeout_ldap_operation,
-                )
-                .await
-            }
-            Err(err) => Err(err),
-        }
+            .await?;
+        info.user_dn = Some(user_dn.clone());
+        let policy = conn
+            .bind(
+                user_dn.as_str(),
+                password.as_str(),
+                settings.timeout_ldap_operation,
+            )
+            .await?;
+        info.password_expires_in = policy.time_before_expiration;
+        info.grace_logins_remaining = policy.grace_logins_remaining;
+        Ok(())
+    });
+    run_ldap_op_with_deadline(timeout_auth_total, ldap_op).await
+}
+
+/// Changes the password of a user. The operation is never hedged, and only
+/// fails over to another server until the bind is sent, since the password
+/// could otherwise be changed twice, and the second change would fail with the
+/// old password.
+pub(super) async fn ldap_modify_password(
+    username: String,
+    old_password: String,
+    new_password: String,
+    use_bind_mode: bool,
+) -> VkLdapAuthResult {
+    let settings = VK_LDAP_CONTEXT.lock().await.get_ldap_settings();
+    let timeout_auth_total = settings.timeout_auth_total;
+
+    let sent = Arc::new(AtomicBool::new(false));
+    let op_sent = Arc::clone(&sent);
+    let ldap_op = run_ldap_op_with_failover_until_sent(sent, async move |conn, info| {
+        let user_dn = if use_bind_mode {
+            let prefix = &settings.bind_db_prefix;
+            let suffix = &settings.bind_db_suffix;
+            format!("{prefix}{username}{suffix}")
+        } else {
+            conn.search(
+                &settings,
+                username.as_str(),
+                settings.timeout_ldap_operation,
+            )
+            .await?
+        };
+        info.user_dn = Some(user_dn.clone());
+
+        // A pooled connection that was closed by the server fails before
+        // anything is sent, so the change can still run on another server.
+        conn.check_open()?;
+        op_sent.store(true, Ordering::Relaxed);
+
+        conn.bind_to_change_password(
+            user_dn.as_str(),
+            old_password.as_str(),
+            settings.timeout_ldap_operation,
+        )
+        .await?;
+
+        conn.modify_password(
+            user_dn.as_str(),
+            old_password.as_str(),
+            new_password.as_str(),
+            settings.timeout_ldap_operation,
+        )
+        .await
+    });
+
+    run_ldap_op_with_deadline(timeout_auth_total, ldap_op).await
+}
+
+pub(super) async fn ldap_search(request: VkLdapSearchRequest) -> Result<Vec<VkLdapSearchEntry>> {
+    let settings = VK_LDAP_CONTEXT.lock().await.get_ldap_settings();
+
+    let (_, res) = run_ldap_op_with_failover(true, async move |conn, _| {
+        conn.search_entries(&settings, &request, settings.timeout_ldap_operation)
+            .await
+    })
+    .await;
+
+    res
+}
+
+pub(super) async fn ldap_check_user(user_dn: String) -> Result<bool> {
+    let settings = VK_LDAP_CONTEXT.lock().await.get_ldap_settings();
+
+    let dn = user_dn.clone();
+    let (_, res) = run_ldap_op_with_failover(true, async move |conn, _| {
+        conn.check_user(&settings, &dn, settings.timeout_ldap_operation)
+            .await
     })
-    .await
+    .await;
+
+    match res {
+        Ok(_) => Ok(true),
+        Err(VkLdapError::NoLdapEntryFound(_)) => Ok(false),
+        Err(err @ VkLdapError::AccountStatusError(..)) => {
+            info!("LDAP user {user_dn} no longer qualifies: {err}");
+            Ok(false)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pick_weighted_index() {
+        assert_eq!(pick_weighted_index(&[0, 0, 0]), 0);
+        assert_eq!(pick_weighted_index(&[0, 5]), 1);
+
+        // Servers with an explicit weight of 0 are skipped when others have one.
+        let mut picks = [0; 3];
+        for _ in 0..4000 {
+            picks[pick_weighted_index(&[0, 3, 1])] += 1;
+        }
+        assert_eq!(picks[0], 0);
+        assert!(picks[1] > 2 * picks[2], "unexpected distribution {picks:?}");
+    }
 }
@@ -1,27 +1,113 @@
+use std::time::Duration;
+
 use ldap3::LdapError;
 use valkey_module::ValkeyError;
 
 pub enum VkLdapError {
     IOError(String, std::io::Error),
     NoTLSKeyPathSet,
-    TLSError(String, native_tls::Error),
+    TLSError(String, String),
+    TLSPinMismatch(String, String),
+    PasswordFileError(String, String),
+    GSSAPINotSupported,
     LdapBindError(LdapError),
+    LdapAccountError(VkLdapFailureReason, LdapError),
+    AccountStatusError(VkLdapFailureReason, String),
     LdapAdminBindError(LdapError),
     LdapSearchError(LdapError),
+    LdapPasswordModifyError(LdapError),
     LdapConnectionError(LdapError),
     LdapServerPingError(LdapError),
     NoLdapEntryFound(String),
     MultipleEntryFound(String),
     InvalidDNAttribute(String),
     NoServerConfigured,
+    UnknownServer(String),
     NoHealthyServerAvailable,
-    FailedToStopFailuredDetectorThread,
+    NoPoolConnectionAvailable(String),
+    AuthTimeout(Duration),
+    FailedToStopBackgroundLoop(&'static str),
     FailedToShutdownJobScheduler,
     FailedToSendJobToScheduler(String),
 }
 
 unsafe impl Send for VkLdapError {}
 
+/// Coarse classification of an authentication failure, used when reporting
+/// failures outside of the server logs.
+#[derive(Clone, Copy, PartialEq)]
+pub enum VkLdapFailureReason {
+    InvalidCredentials,
+    UserNotFound,
+    MultipleEntries,
+    DirectoryUnavailable,
+    AccountLocked,
+    AccountDisabled,
+    AccountExpired,
+    PasswordExpired,
+    PasswordMustChange,
+    LogonRestricted,
+    BindRejected,
+}
+
+impl VkLdapFailureReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::InvalidCredentials => "invalid-credentials",
+            Self::UserNotFound => "user-not-found",
+            Self::MultipleEntries => "multiple-entries",
+            Self::DirectoryUnavailable => "directory-unavailable",
+            Self::AccountLocked => "account-locked",
+            Self::AccountDisabled => "account-disabled",
+            Self::AccountExpired => "account-expired",
+            Self::PasswordExpired => "password-expired",
+            Self::PasswordMustChange => "password-must-change",
+            Self::LogonRestricted => "logon-restricted",
+            Self::BindRejected => "bind-rejected",
+        }
+    }
+
+    /// Whether the directory recognized the account and refused it because it
+    /// is locked, disabled or expired, so that no other password may be tried.
+    pub fn is_definitive(&self) -> bool {
+        matches!(
+            self,
+            Self::AccountLocked
+                | Self::AccountDisabled
+                | Self::AccountExpired
+                | Self::PasswordExpired
+        )
+    }
+
+    /// Parses the sub-code that Active Directory adds to the diagnostic message
+    /// of a failed bind, e.g., "80090308: LdapErr: DSID-0C09044E, comment:
+    /// AcceptSecurityContext error, data 533, v4563".
+    fn from_ad_diagnostic_message(text: &str) -> Option<VkLdapFailureReason> {
+        let start = text.find("data ")? + "data ".len();
+        let code = text[start..]
+            .split(|c: char| !c.is_ascii_hexdigit())
+            .next()?;
+
+        match code.to_ascii_lowercase().as_str() {
+            "525" => Some(Self::UserNotFound),
+            "52e" => Some(Self::InvalidCredentials),
+            "530" | "531" => Some(Self::LogonRestricted),
+            "532" => Some(Self::PasswordExpired),
+            "533" => Some(Self::AccountDisabled),
+            "701" => Some(Self::AccountExpired),
+            "773" => Some(Self::PasswordMustChange),
+            "775" => Some(Self::AccountLocked),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for VkLdapFailureReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
 fn ldap_error_to_string(ldap_err: &LdapError) -> String {
     let msg = ldap_err.to_string();
     // When using Active Directory LDAP API, some error messages might containing a
@@ -40,10 +126,26 @@ impl std::fmt::Display for VkLdapError {
             ),
             VkLdapError::IOError(msg, ioerr) => write!(f, "{msg}: {ioerr}"),
             VkLdapError::TLSError(msg, tlserr) => write!(f, "{msg}: {tlserr}"),
+            VkLdapError::PasswordFileError(msg, err) => write!(f, "{msg}: {err}"),
+            VkLdapError::GSSAPINotSupported => write!(
+                f,
+                "GSSAPI binds are not supported, the module was built without the gssapi feature"
+            ),
+            VkLdapError::TLSPinMismatch(url, pin) => write!(
+                f,
+                "the public key of server {url} (sha256//{pin}) does not match any of the pinned keys"
+            ),
             VkLdapError::LdapBindError(ldaperr) => {
                 let ldaperr = ldap_error_to_string(ldaperr);
                 write!(f, "error in bind operation: {ldaperr}")
             }
+            VkLdapError::LdapAccountError(reason, ldaperr) => {
+                let ldaperr = ldap_error_to_string(ldaperr);
+                write!(f, "error in bind operation ({reason}): {ldaperr}")
+            }
+            VkLdapError::AccountStatusError(reason, detail) => {
+                write!(f, "the user account is not usable ({reason}): {detail}")
+            }
             VkLdapError::LdapAdminBindError(ldaperr) => {
                 let ldaperr = ldap_error_to_string(ldaperr);
                 write!(f, "error in binding admin user: {ldaperr}")
@@ -52,16 +154,25 @@ impl std::fmt::Display for VkLdapError {
                 let ldaperr = ldap_error_to_string(ldaperr);
                 write!(f, "failed to search ldap user: {ldaperr}")
             }
+            VkLdapError::LdapPasswordModifyError(ldaperr) => match ldaperr {
+                // The diagnostic message of the directory explains which
+                // password policy the new password violates.
+                LdapError::LdapResult { result } if !result.text.is_empty() => {
+                    let text = result.text.replace('\0', "");
+                    write!(f, "failed to change the password: {text}")
+                }
+                _ => {
+                    let ldaperr = ldap_error_to_string(ldaperr);
+                    write!(f, "failed to change the password: {ldaperr}")
+                }
+            },
             VkLdapError::LdapConnectionError(ldaperr) => {
                 let ldaperr = ldap_error_to_string(ldaperr);
                 write!(f, "LDAP connection failure: {ldaperr}")
             }
             VkLdapError::LdapServerPingError(ldaperr) => {
                 let ldaperr = ldap_error_to_string(ldaperr);
-                write!(
-                    f,
-                    "failed to run WhoAmI command on the ldap server: {ldaperr}"
-                )
+                write!(f, "health check probe failed on the ldap server: {ldaperr}")
             }
             VkLdapError::NoLdapEntryFound(filter) => {
                 write!(f, "search filter '{filter}' returned no entries")
@@ -79,14 +190,25 @@ impl std::fmt::Display for VkLdapError {
                 f,
                 "no server set in configuration. Please set ldap.servers config option"
             ),
+            VkLdapError::UnknownServer(server) => {
+                write!(f, "no server '{server}' in the ldap.servers config")
+            }
             VkLdapError::NoHealthyServerAvailable => write!(
                 f,
                 "all servers set in configuration are unhealthy. Please check the logs for more information"
             ),
-            VkLdapError::FailedToStopFailuredDetectorThread => write!(
+            VkLdapError::NoPoolConnectionAvailable(url) => write!(
                 f,
-                "failed to wait for the failure detector thread to finish"
+                "no connection to server {url} is available, the connections are being re-created"
             ),
+            VkLdapError::AuthTimeout(timeout) => write!(
+                f,
+                "authentication did not complete within {}ms",
+                timeout.as_millis()
+            ),
+            VkLdapError::FailedToStopBackgroundLoop(name) => {
+                write!(f, "failed to wait for the {name} to finish")
+            }
             VkLdapError::FailedToShutdownJobScheduler => write!(
                 f,
                 "failed to shutdown job scheduler. Please check the logs for more information"
@@ -100,21 +222,57 @@ impl std::fmt::Display for VkLdapError {
 
 impl From<&VkLdapError> for ValkeyError {
     fn from(err: &VkLdapError) -> Self {
-        err.into()
+        ValkeyError::String(err.to_string())
     }
 }
 
 impl VkLdapError {
+    /// Builds the error of a failed user bind. Active Directory reports every
+    /// unusable account as invalid credentials, and only a sub-code in the
+    /// diagnostic message tells the real reason.
+    pub(super) fn from_bind_error(err: LdapError) -> VkLdapError {
+        if let LdapError::LdapResult { result } = &err {
+            let text = result.text.replace('\0', "");
+            if result.rc == 49 {
+                match VkLdapFailureReason::from_ad_diagnostic_message(&text) {
+                    None | Some(VkLdapFailureReason::InvalidCredentials) => (),
+                    Some(reason) => return VkLdapError::LdapAccountError(reason, err),
+                }
+            }
+        }
+        VkLdapError::LdapBindError(err)
+    }
+
+    pub fn failure_reason(&self) -> VkLdapFailureReason {
+        match self {
+            VkLdapError::LdapAccountError(reason, _) => *reason,
+            VkLdapError::AccountStatusError(reason, _) => *reason,
+            VkLdapError::LdapBindError(LdapError::LdapResult { result }) => match result.rc {
+                49 => VkLdapFailureReason::InvalidCredentials,
+                // Some directories reject the binds of locked accounts with a
+                // constraint violation or as unwilling to perform, but so they
+                // do for other reasons, e.g., a policy of the server. Lockouts
+                // are only reported from the password policy control, the
+                // Active Directory sub-code or the account status attributes.
+                19 | 53 => VkLdapFailureReason::BindRejected,
+                _ => VkLdapFailureReason::DirectoryUnavailable,
+            },
+            VkLdapError::NoLdapEntryFound(_) => VkLdapFailureReason::UserNotFound,
+            VkLdapError::MultipleEntryFound(_) => VkLdapFailureReason::MultipleEntries,
+            _ => VkLdapFailureReason::DirectoryUnavailable,
+        }
+    }
+
     pub(super) fn is_ldap_connection_error(err: &LdapError) -> bool {
-        match err {
+        !matches!(
+            err,
             LdapError::LdapResult { .. }
-            | LdapError::FilterParsing
-            | LdapError::DecodingUTF8
-            | LdapError::InvalidScopeString(_)
-            | LdapError::AddNoValues
-            | LdapError::AdapterInit(_) => false,
-            _ => true,
-        }
+                | LdapError::FilterParsing
+                | LdapError::DecodingUTF8
+                | LdapError::InvalidScopeString(_)
+                | LdapError::AddNoValues
+                | LdapError::AdapterInit(_)
+        )
     }
 }
 
@@ -133,7 +291,7 @@ macro_rules! handle_tls_error {
     ($expr:expr, $errmsg:expr) => {
         match $expr {
             Ok(res) => res,
-            Err(err) => return Err(VkLdapError::TLSError($errmsg, err)),
+            Err(err) => return Err(VkLdapError::TLSError($errmsg, err.to_string())),
         }
     };
 }
@@ -1,51 +1,63 @@
 use lazy_static::lazy_static;
 
-use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use std::sync::{Arc, Mutex};
-use std::thread;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::{Duration, Instant};
 
 use futures::future;
-use log::{debug, error};
+use log::debug;
 
+use super::Result;
 use super::context;
-use super::errors::VkLdapError;
+use super::scheduler::BackgroundLoop;
 use super::server::{VkLdapServer, VkLdapServerStatus};
-use super::{Result, scheduler};
 
 async fn check_server_health(server: VkLdapServer) {
-    if server.is_healthy() {
-        let mut pool_conn = context::get_pool_connection(&server).await;
-
-        let now = Instant::now();
-        let res = pool_conn.conn.ping().await;
-        let ping_time = now.elapsed();
-
-        context::return_pool_connection(pool_conn).await;
-
-        if let Err(err) = res {
-            context::update_server_status(
-                &server,
-                VkLdapServerStatus::UNHEALTHY(err.to_string()),
-                None,
-            )
-            .await;
-        } else {
-            context::update_server_status(
-                &server,
-                super::server::VkLdapServerStatus::HEALTHY,
-                Some(ping_time),
-            )
-            .await;
+    let settings = context::get_ldap_settings().await;
+
+    if server.is_available() {
+        let res = match context::get_pool_connection(&server).await {
+            Ok(mut pool_conn) => {
+                let now = Instant::now();
+                let res = pool_conn.conn.ping(&settings).await;
+                let ping_time = now.elapsed();
+
+                context::return_pool_connection(pool_conn).await;
+                res.map(|_| ping_time)
+            }
+            Err(err) => Err(err),
+        };
+
+        match res {
+            Ok(ping_time) => {
+                let status = match settings.health_check_slow_threshold {
+                    Some(threshold) if ping_time > threshold => {
+                        VkLdapServerStatus::DEGRADED(format!(
+                            "ping time of {}ms is above the threshold of {}ms",
+                            ping_time.as_millis(),
+                            threshold.as_millis()
+                        ))
+                    }
+                    _ => VkLdapServerStatus::HEALTHY,
+                };
+                context::update_server_status(&server, status, Some(ping_time)).await;
+            }
+            Err(err) => {
+                context::update_server_status(
+                    &server,
+                    VkLdapServerStatus::UNHEALTHY(err.to_string()),
+                    None,
+                )
+                .await;
+            }
         }
     } else {
         let conn_res = context::get_connection(&server).await;
 
         match conn_res {
             Ok(mut conn) => {
-                let res = conn.ping().await;
-                if let Ok(_) = res {
-                    context::refresh_pool_connections(&server).await;
+                let res = conn.ping(&settings).await;
+                if res.is_ok() {
+                    let _ = context::refresh_pool_connections(&server).await;
                 }
             }
             Err(err) => {
@@ -65,85 +77,66 @@ async fn failure_detector_iteration() {
 
     let mut futures = Vec::new();
 
-    for server in servers {
+    // Servers marked down by an administrator are left alone until they are
+    // marked up again.
+    for server in servers.into_iter().filter(|s| !s.is_marked_down()) {
         futures.push(check_server_health(server));
     }
 
     future::join_all(futures).await;
 }
 
-struct FailureDetector {
-    thread: Mutex<Option<thread::JoinHandle<()>>>,
-    stop: AtomicBool,
-    interval: AtomicU64,
-}
-
-impl FailureDetector {
-    fn new() -> FailureDetector {
-        FailureDetector {
-            thread: Mutex::new(None),
-            stop: AtomicBool::new(false),
-            interval: AtomicU64::new(1),
-        }
-    }
-
-    fn start(&self) {
-        self.stop.store(false, Ordering::Release);
-        let mut thread = self.thread.lock().unwrap();
+async fn failure_detector_loop() {
+    debug!("initiating failure detector loop");
 
-        *thread = Some(thread::spawn(move || {
-            debug!("initiating failure detector thread");
+    loop {
+        let interval = Duration::from_millis(FAILURE_DETECTOR.interval_ms.load(Ordering::Relaxed));
+        let interrupted = FAILURE_DETECTOR.background_loop.sleep(Some(interval)).await;
 
-            loop {
-                std::thread::sleep(Duration::from_secs(
-                    FAILURE_DETECTOR.interval.load(Ordering::Relaxed),
-                ));
+        if FAILURE_DETECTOR.background_loop.should_stop() {
+            debug!("exiting failure detector loop");
+            break;
+        }
 
-                if let Err(err) = scheduler::submit_sync_task(failure_detector_iteration()) {
-                    error!("failed to run failure detector iteration: {err}");
-                }
+        if interrupted {
+            continue;
+        }
 
-                if FAILURE_DETECTOR.should_stop() {
-                    debug!("exiting failure detector loop");
-                    return ();
-                }
-            }
-        }))
+        failure_detector_iteration().await;
     }
+}
 
-    fn shutdown(&self) -> Result<()> {
-        self.stop.store(true, Ordering::Release);
-
-        let mut thread = self.thread.lock().unwrap();
-        let handler_opt = (*thread).take();
+struct FailureDetector {
+    background_loop: BackgroundLoop,
+    interval_ms: AtomicU64,
+}
 
-        if let Some(handler) = handler_opt {
-            match handler.join() {
-                Ok(_) => Ok(()),
-                Err(_) => Err(VkLdapError::FailedToStopFailuredDetectorThread),
-            }
-        } else {
-            panic!("failure detector thread should have been initialized");
+impl FailureDetector {
+    fn new() -> FailureDetector {
+        FailureDetector {
+            background_loop: BackgroundLoop::new("failure detector"),
+            interval_ms: AtomicU64::new(1000),
         }
     }
-
-    fn should_stop(&self) -> bool {
-        self.stop.load(Ordering::Acquire)
-    }
 }
 
 lazy_static! {
-    static ref FAILURE_DETECTOR: Arc<FailureDetector> = Arc::new(FailureDetector::new());
+    static ref FAILURE_DETECTOR: FailureDetector = FailureDetector::new();
 }
 
-pub fn start_failure_detector_thread() {
-    FAILURE_DETECTOR.start();
+pub fn start_failure_detector() {
+    FAILURE_DETECTOR
+        .background_loop
+        .start(failure_detector_loop());
 }
 
-pub fn shutdown_failure_detector_thread() -> Result<()> {
-    FAILURE_DETECTOR.shutdown()
+pub fn shutdown_failure_detector() -> Result<()> {
+    FAILURE_DETECTOR.background_loop.shutdown()
 }
 
-pub fn set_failure_detector_interval(interval: u64) {
-    FAILURE_DETECTOR.interval.store(interval, Ordering::Relaxed);
+pub fn set_failure_detector_interval(interval: Duration) {
+    FAILURE_DETECTOR
+        .interval_ms
+        .store(interval.as_millis() as u64, Ordering::Relaxed);
+    FAILURE_DETECTOR.background_loop.notify_settings_changed();
 }
@@ -0,0 +1,378 @@
+use lazy_static::lazy_static;
+
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use log::{debug, error, info};
+
+use super::scheduler::BackgroundLoop;
+
+// The prefix of the credential cache of the module, followed by the principal.
+// A memory cache is private to the process, so the tickets are never written to
+// disk, and a new cache is used when the principal changes.
+const KERBEROS_CCACHE_PREFIX: &str = "MEMORY:valkey-ldap-";
+
+// The minimum delay between two renewals, so that tickets with a very short
+// lifetime do not make the renewer spin.
+const MIN_RENEW_DELAY: Duration = Duration::from_secs(30);
+
+// The delay before retrying a failed renewal.
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+
+// The bindings of the credential store extensions of the MIT Kerberos GSSAPI
+// library, which are not exposed by the GSSAPI crates. The library is linked
+// by the GSSAPI support of ldap3.
+#[allow(non_camel_case_types)]
+mod ffi {
+    use std::os::raw::{c_char, c_int, c_void};
+
+    pub type OM_uint32 = u32;
+    pub type gss_name_t = *mut c_void;
+    pub type gss_cred_id_t = *mut c_void;
+
+    #[repr(C)]
+    pub struct gss_OID_desc {
+        pub length: OM_uint32,
+        pub elements: *mut c_void,
+    }
+
+    #[repr(C)]
+    pub struct gss_OID_set_desc {
+        pub count: usize,
+        pub elements: *mut gss_OID_desc,
+    }
+
+    #[repr(C)]
+    pub struct gss_buffer_desc {
+        pub length: usize,
+        pub value: *mut c_void,
+    }
+
+    #[repr(C)]
+    pub struct gss_key_value_element_desc {
+        pub key: *const c_char,
+        pub value: *const c_char,
+    }
+
+    #[repr(C)]
+    pub struct gss_key_value_set_desc {
+        pub count: OM_uint32,
+        pub elements: *mut gss_key_value_element_desc,
+    }
+
+    pub const GSS_C_INITIATE: c_int = 1;
+    pub const GSS_C_INDEFINITE: OM_uint32 = 0xffffffff;
+    pub const GSS_C_GSS_CODE: c_int = 1;
+    pub const GSS_C_MECH_CODE: c_int = 2;
+
+    unsafe extern "C" {
+        pub static GSS_KRB5_NT_PRINCIPAL_NAME: *mut gss_OID_desc;
+        pub static gss_mech_krb5: *mut gss_OID_desc;
+
+        pub fn gss_import_name(
+            minor_status: *mut OM_uint32,
+            input_name_buffer: *mut gss_buffer_desc,
+            input_name_type: *mut gss_OID_desc,
+            output_name: *mut gss_name_t,
+        ) -> OM_uint32;
+
+        pub fn gss_release_name(minor_status: *mut OM_uint32, name: *mut gss_name_t) -> OM_uint32;
+
+        pub fn gss_acquire_cred_from(
+            minor_status: *mut OM_uint32,
+            desired_name: gss_name_t,
+            time_req: OM_uint32,
+            desired_mechs: *mut gss_OID_set_desc,
+            cred_usage: c_int,
+            cred_store: *const gss_key_value_set_desc,
+            output_cred_handle: *mut gss_cred_id_t,
+            actual_mechs: *mut *mut gss_OID_set_desc,
+            time_rec: *mut OM_uint32,
+        ) -> OM_uint32;
+
+        pub fn gss_release_cred(
+            minor_status: *mut OM_uint32,
+            cred_handle: *mut gss_cred_id_t,
+        ) -> OM_uint32;
+
+        pub fn gss_krb5_ccache_name(
+            minor_status: *mut OM_uint32,
+            name: *const c_char,
+            out_name: *mut *const c_char,
+        ) -> OM_uint32;
+
+        pub fn gss_display_status(
+            minor_status: *mut OM_uint32,
+            status_value: OM_uint32,
+            status_type: c_int,
+            mech_type: *mut gss_OID_desc,
+            message_context: *mut OM_uint32,
+            status_string: *mut gss_buffer_desc,
+        ) -> OM_uint32;
+
+        pub fn gss_release_buffer(
+            minor_status: *mut OM_uint32,
+            buffer: *mut gss_buffer_desc,
+        ) -> OM_uint32;
+    }
+}
+
+#[derive(Clone, PartialEq)]
+struct VkKerberosSettings {
+    keytab: String,
+    principal: Option<String>,
+}
+
+impl VkKerberosSettings {
+    fn ccache_name(&self) -> String {
+        format!(
+            "{KERBEROS_CCACHE_PREFIX}{}",
+            self.principal.as_deref().unwrap_or("default")
+        )
+    }
+}
+
+fn is_gss_error(major: ffi::OM_uint32) -> bool {
+    major & 0xffff0000 != 0
+}
+
+/// Returns the messages of a GSSAPI status code.
+fn gss_status_messages(status: ffi::OM_uint32, status_type: c_int) -> Vec<String> {
+    let mut messages = Vec::new();
+    let mut message_context = 0;
+    loop {
+        let mut minor = 0;
+        let mut buffer = ffi::gss_buffer_desc {
+            length: 0,
+            value: ptr::null_mut(),
+        };
+        let major = unsafe {
+            ffi::gss_display_status(
+                &mut minor,
+                status,
+                status_type,
+                ffi::gss_mech_krb5,
+                &mut message_context,
+                &mut buffer,
+            )
+        };
+        if is_gss_error(major) {
+            break;
+        }
+        if !buffer.value.is_null() {
+            let bytes =
+                unsafe { std::slice::from_raw_parts(buffer.value as *const u8, buffer.length) };
+            messages.push(String::from_utf8_lossy(bytes).into_owned());
+            unsafe { ffi::gss_release_buffer(&mut minor, &mut buffer) };
+        }
+        if message_context == 0 {
+            break;
+        }
+    }
+    messages
+}
+
+fn gss_error(major: ffi::OM_uint32, minor: ffi::OM_uint32) -> String {
+    let mut messages = gss_status_messages(major, ffi::GSS_C_GSS_CODE);
+    if minor != 0 {
+        messages.extend(gss_status_messages(minor, ffi::GSS_C_MECH_CODE));
+    }
+    messages.join(": ")
+}
+
+/// Acquires the initiator credentials of the principal from the credential
+/// cache of the module, which makes the Kerberos library get a new ticket from
+/// the KDC with the keys of the client keytab when the ticket in the cache is
+/// missing or is halfway to its expiration. The keytab and the cache are passed
+/// in the credential store, so the environment of the process is never used.
+/// Returns the remaining lifetime of the ticket.
+fn acquire_ticket(settings: &VkKerberosSettings) -> Result<Duration, String> {
+    let keytab = CString::new(settings.keytab.as_str()).map_err(|err| err.to_string())?;
+    let ccache = CString::new(settings.ccache_name()).map_err(|err| err.to_string())?;
+    let mut store_elements = [
+        ffi::gss_key_value_element_desc {
+            key: c"client_keytab".as_ptr(),
+            value: keytab.as_ptr(),
+        },
+        ffi::gss_key_value_element_desc {
+            key: c"ccache".as_ptr(),
+            value: ccache.as_ptr(),
+        },
+    ];
+    let cred_store = ffi::gss_key_value_set_desc {
+        count: store_elements.len() as ffi::OM_uint32,
+        elements: store_elements.as_mut_ptr(),
+    };
+
+    let mut minor = 0;
+    let mut name: ffi::gss_name_t = ptr::null_mut();
+    if let Some(principal) = &settings.principal {
+        let mut buffer = ffi::gss_buffer_desc {
+            length: principal.len(),
+            value: principal.as_ptr() as *mut c_void,
+        };
+        let major = unsafe {
+            ffi::gss_import_name(
+                &mut minor,
+                &mut buffer,
+                ffi::GSS_KRB5_NT_PRINCIPAL_NAME,
+                &mut name,
+            )
+        };
+        if is_gss_error(major) {
+            return Err(gss_error(major, minor));
+        }
+    }
+
+    let mut mechs = ffi::gss_OID_set_desc {
+        count: 1,
+        elements: unsafe { ffi::gss_mech_krb5 },
+    };
+    let mut cred: ffi::gss_cred_id_t = ptr::null_mut();
+    let mut lifetime = 0;
+    let major = unsafe {
+        ffi::gss_acquire_cred_from(
+            &mut minor,
+            name,
+            ffi::GSS_C_INDEFINITE,
+            &mut mechs,
+            ffi::GSS_C_INITIATE,
+            &cred_store,
+            &mut cred,
+            ptr::null_mut(),
+            &mut lifetime,
+        )
+    };
+    let res = if is_gss_error(major) {
+        Err(gss_error(major, minor))
+    } else {
+        Ok(Duration::from_secs(lifetime as u64))
+    };
+
+    let mut release_minor = 0;
+    unsafe {
+        if !cred.is_null() {
+            ffi::gss_release_cred(&mut release_minor, &mut cred);
+        }
+        if !name.is_null() {
+            ffi::gss_release_name(&mut release_minor, &mut name);
+        }
+    }
+    res
+}
+
+/// Makes the GSSAPI binds started from the calling thread use the credential
+/// cache of the module. The Kerberos library keeps the name of the default
+/// cache per thread, so it must be called right before starting the bind, from
+/// the same thread. Without a keytab, the default cache of the process is used.
+pub fn use_kerberos_ccache() {
+    let ccache = KERBEROS_RENEWER
+        .settings
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|settings| CString::new(settings.ccache_name()).ok());
+
+    let name: *const c_char = ccache.as_ref().map_or(ptr::null(), |name| name.as_ptr());
+    let mut minor = 0;
+    // The library copies the name, so it does not need to outlive the call.
+    let major = unsafe { ffi::gss_krb5_ccache_name(&mut minor, name, ptr::null_mut()) };
+    if is_gss_error(major) {
+        let ccache = ccache.as_deref().map(CStr::to_string_lossy);
+        error!(
+            "failed to set the kerberos credential cache {}: {}",
+            ccache.unwrap_or_default(),
+            gss_error(major, minor)
+        );
+    }
+}
+
+async fn kerberos_renewer_loop() {
+    debug!("initiating kerberos renewer loop");
+
+    loop {
+        let settings = KERBEROS_RENEWER.settings.lock().unwrap().clone();
+
+        // Without a keytab there is nothing to renew until the settings change.
+        let delay = match settings {
+            Some(settings) => {
+                let principal = settings.principal.clone().unwrap_or_default();
+                let res = tokio::task::spawn_blocking(move || acquire_ticket(&settings)).await;
+                match res {
+                    Ok(Ok(lifetime)) => {
+                        debug!("kerberos ticket of '{principal}' expires in {lifetime:?}");
+                        Some((lifetime / 2).max(MIN_RENEW_DELAY))
+                    }
+                    Ok(Err(err)) => {
+                        error!("failed to acquire the kerberos ticket of '{principal}': {err}");
+                        Some(RETRY_DELAY)
+                    }
+                    Err(err) => {
+                        error!("failed to run the kerberos ticket renewal: {err}");
+                        Some(RETRY_DELAY)
+                    }
+                }
+            }
+            None => None,
+        };
+
+        KERBEROS_RENEWER.background_loop.sleep(delay).await;
+
+        if KERBEROS_RENEWER.background_loop.should_stop() {
+            debug!("exiting kerberos renewer loop");
+            break;
+        }
+    }
+}
+
+struct KerberosRenewer {
+    background_loop: BackgroundLoop,
+    settings: Mutex<Option<VkKerberosSettings>>,
+}
+
+impl KerberosRenewer {
+    fn new() -> KerberosRenewer {
+        KerberosRenewer {
+            background_loop: BackgroundLoop::new("kerberos renewer"),
+            settings: Mutex::new(None),
+        }
+    }
+}
+
+lazy_static! {
+    static ref KERBEROS_RENEWER: KerberosRenewer = KerberosRenewer::new();
+}
+
+pub fn start_kerberos_renewer() {
+    KERBEROS_RENEWER
+        .background_loop
+        .start(kerberos_renewer_loop());
+}
+
+pub fn shutdown_kerberos_renewer() {
+    if let Err(err) = KERBEROS_RENEWER.background_loop.shutdown() {
+        error!("{err}");
+    }
+}
+
+/// Sets the keytab and the principal used to get the tickets of the GSSAPI
+/// binds. Without a keytab, the default credential cache of the process is
+/// used, and its tickets must be renewed outside of the module.
+pub fn set_kerberos_credentials(keytab: Option<String>, principal: Option<String>) {
+    let settings = keytab.map(|keytab| VkKerberosSettings { keytab, principal });
+
+    let mut current = KERBEROS_RENEWER.settings.lock().unwrap();
+    if *current == settings {
+        return;
+    }
+
+    if let Some(settings) = &settings {
+        info!("using the kerberos client keytab {}", settings.keytab);
+    }
+
+    *current = settings;
+    KERBEROS_RENEWER.background_loop.notify_settings_changed();
+}
@@ -0,0 +1,40 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+const LATENCY_WINDOW_SIZE: usize = 256;
+const MIN_LATENCY_SAMPLES: usize = 20;
+
+/// Keeps the latencies of the most recent LDAP operations run against a server.
+pub(super) struct VkLatencyTracker {
+    samples: VecDeque<Duration>,
+}
+
+impl VkLatencyTracker {
+    pub fn new() -> VkLatencyTracker {
+        VkLatencyTracker {
+            samples: VecDeque::with_capacity(LATENCY_WINDOW_SIZE),
+        }
+    }
+
+    pub fn record(&mut self, latency: Duration) {
+        if self.samples.len() == LATENCY_WINDOW_SIZE {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(latency);
+    }
+
+    /// Returns the given percentile of the recorded latencies, or `None` if there
+    /// are not enough samples to compute a meaningful value.
+    pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+        if self.samples.len() < MIN_LATENCY_SAMPLES {
+            return None;
+        }
+
+        let mut sorted: Vec<Duration> = self.samples.iter().copied().collect();
+        sorted.sort_unstable();
+
+        let rank = (percentile / 100.0 * sorted.len() as f64).ceil() as usize;
+        let idx = rank.clamp(1, sorted.len()) - 1;
+        Some(sorted[idx])
+    }
+}
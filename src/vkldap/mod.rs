@@ -2,22 +2,108 @@ mod connection;
 mod context;
 pub mod errors;
 pub mod failure_detector;
+#[cfg(feature = "gssapi")]
+pub mod kerberos;
+mod latency;
+pub mod passwd_file;
+mod ppolicy;
 pub mod scheduler;
 pub mod server;
 pub mod settings;
+mod tls;
+pub mod tls_reloader;
 
+use crate::configs::LdapSearchScope;
 use errors::VkLdapError;
 use log::error;
-use scheduler::CallbackTrait;
-use server::VkLdapServer;
+use scheduler::{CallbackTrait, TaskHandle};
+use server::{VkLdapServer, VkLdapServerOptions};
 use settings::{VkConnectionSettings, VkLdapSettings};
+use std::time::Duration;
 use url::Url;
 
 type Result<T> = std::result::Result<T, VkLdapError>;
 
+/// Details about how an authentication request was processed.
+#[derive(Clone, Debug, Default)]
+pub struct VkLdapAuthInfo {
+    /// The DN used in the user bind, if it was resolved.
+    pub user_dn: Option<String>,
+    /// The URL of the server that handled the request.
+    pub server: Option<String>,
+    /// The total time spent processing the request.
+    pub latency: Duration,
+    /// The time until the password expires, reported by the password policy
+    /// control.
+    pub password_expires_in: Option<Duration>,
+    /// The number of grace logins left after the password expired, reported by
+    /// the password policy control.
+    pub grace_logins_remaining: Option<u64>,
+}
+
+/// An arbitrary search run with the service account, used to troubleshoot
+/// the search settings.
+pub struct VkLdapSearchRequest {
+    pub base: String,
+    pub scope: LdapSearchScope,
+    pub filter: String,
+    pub attributes: Vec<String>,
+    /// The maximum number of entries returned, or zero for no limit.
+    pub size_limit: i32,
+}
+
+pub struct VkLdapSearchEntry {
+    pub dn: String,
+    /// The attributes of the entry, sorted by name.
+    pub attributes: Vec<(String, Vec<Vec<u8>>)>,
+}
+
+/// The state of the connection pool of a server.
+pub struct VkConnectionPoolStats {
+    pub size: usize,
+    pub idle: usize,
+    pub borrowed: usize,
+    /// The number of connections that could not be replaced, and are being
+    /// re-created in the background.
+    pub reconnecting: usize,
+    /// The number of operations waiting for a connection.
+    pub waiters: usize,
+    /// Incremented every time the connections of the pool are re-created.
+    pub epoch: u64,
+}
+
+/// The counters of the LDAP operations run on a server.
+#[derive(Clone, Default)]
+pub struct VkLdapServerCounters {
+    pub operations: u64,
+    pub connection_errors: u64,
+}
+
+pub struct VkLdapServerDetails {
+    pub server: VkLdapServer,
+    pub pool: VkConnectionPoolStats,
+    pub counters: VkLdapServerCounters,
+}
+
+/// The administrative actions that can be run on a server.
+pub enum VkLdapServerAction {
+    /// Stops using the server for new operations, letting the running ones
+    /// finish, and stops the health checks on the server.
+    MarkDown,
+    /// Uses the server again, after re-creating its connections.
+    MarkUp,
+    /// Re-creates the connections of the server.
+    RefreshPool,
+}
+
+pub struct VkLdapAuthResult {
+    pub result: Result<()>,
+    pub info: VkLdapAuthInfo,
+}
+
 pub fn refresh_ldap_settings(settings: VkLdapSettings) {
     if !scheduler::is_scheduler_ready() {
-        return ();
+        return;
     }
 
     let res = scheduler::submit_sync_task(context::refresh_ldap_settings(settings));
@@ -28,7 +114,7 @@ pub fn refresh_ldap_settings(settings: VkLdapSettings) {
 
 pub fn refresh_connection_settings(settings: VkConnectionSettings) {
     if !scheduler::is_scheduler_ready() {
-        return ();
+        return;
     }
 
     let res = scheduler::submit_sync_task(context::refresh_connection_settings(settings));
@@ -44,11 +130,11 @@ pub fn clear_server_list() -> Result<()> {
     scheduler::submit_sync_task(context::clear_server_list())
 }
 
-pub fn add_server(server_url: Url) -> Result<()> {
+pub fn add_server(server_url: Url, options: VkLdapServerOptions) -> Result<()> {
     if !scheduler::is_scheduler_ready() {
         return Ok(());
     }
-    scheduler::submit_sync_task(context::add_server(server_url))
+    scheduler::submit_sync_task(context::add_server(server_url, options))
 }
 
 pub fn get_servers_health_status() -> Result<Vec<VkLdapServer>> {
@@ -59,13 +145,85 @@ pub fn get_servers_health_status() -> Result<Vec<VkLdapServer>> {
     scheduler::submit_sync_task(context::get_servers_health_status())
 }
 
-pub fn vk_ldap_bind<C, T>(username: String, password: String, callback: C, data: T) -> Result<()>
+pub fn get_servers_details() -> Result<Vec<VkLdapServerDetails>> {
+    if !scheduler::is_scheduler_ready() {
+        return Ok(Vec::new());
+    }
+
+    scheduler::submit_sync_task(context::get_servers_details())
+}
+
+/// Runs an administrative action on the server, identified by its index in
+/// the server list, its URL, or its host.
+pub fn vk_ldap_server_action<C, T>(
+    server: String,
+    action: VkLdapServerAction,
+    callback: C,
+    data: T,
+) -> Result<TaskHandle>
 where
     T: 'static + Send,
     C: CallbackTrait<T, Result<()>>,
 {
     if !scheduler::is_scheduler_ready() {
-        return Ok(());
+        return Ok(TaskHandle::default());
+    }
+
+    scheduler::submit_async_task(context::run_server_action(server, action), callback, data)
+}
+
+/// Sets the keytab and the principal of the GSSAPI binds of the search bind
+/// user. Without the `gssapi` feature, the configs are rejected when set, so
+/// there is nothing to set.
+pub fn set_kerberos_credentials(keytab: Option<String>, principal: Option<String>) {
+    #[cfg(feature = "gssapi")]
+    kerberos::set_kerberos_credentials(keytab, principal);
+
+    #[cfg(not(feature = "gssapi"))]
+    let _ = (keytab, principal);
+}
+
+pub fn get_tls_pin_mismatches() -> u64 {
+    tls::get_pin_mismatches()
+}
+
+/// Checks that the TLS backend can load the client certificate or key file at
+/// `path`.
+pub fn check_tls_client_file(path: &str) -> Result<()> {
+    tls::check_client_file(path)
+}
+
+/// Checks that the TLS backend supports encrypted client keys.
+pub fn check_tls_key_passphrase(passphrase: &str) -> Result<()> {
+    tls::check_key_passphrase(passphrase)
+}
+
+/// Reloads the TLS files from disk, even if they did not change, and rolls the
+/// connections of every server to the new files.
+pub fn vk_ldap_reload_tls<C, T>(callback: C, data: T) -> Result<TaskHandle>
+where
+    T: 'static + Send,
+    C: CallbackTrait<T, Result<bool>>,
+{
+    if !scheduler::is_scheduler_ready() {
+        return Ok(TaskHandle::default());
+    }
+
+    scheduler::submit_async_task(context::reload_tls_material(true), callback, data)
+}
+
+pub fn vk_ldap_bind<C, T>(
+    username: String,
+    password: String,
+    callback: C,
+    data: T,
+) -> Result<TaskHandle>
+where
+    T: 'static + Send,
+    C: CallbackTrait<T, VkLdapAuthResult>,
+{
+    if !scheduler::is_scheduler_ready() {
+        return Ok(TaskHandle::default());
     }
 
     scheduler::submit_async_task(context::ldap_bind(username, password), callback, data)
@@ -76,13 +234,13 @@ pub fn vk_ldap_search_and_bind<C, T>(
     password: String,
     callback: C,
     data: T,
-) -> Result<()>
+) -> Result<TaskHandle>
 where
     T: 'static + Send,
-    C: CallbackTrait<T, Result<()>>,
+    C: CallbackTrait<T, VkLdapAuthResult>,
 {
     if !scheduler::is_scheduler_ready() {
-        return Ok(());
+        return Ok(TaskHandle::default());
     }
 
     scheduler::submit_async_task(
@@ -91,3 +249,50 @@ where
         data,
     )
 }
+
+/// Changes the password of the user. The user DN is resolved in the same way
+/// as in the authentication, depending on the bind mode.
+pub fn vk_ldap_modify_password<C, T>(
+    username: String,
+    old_password: String,
+    new_password: String,
+    use_bind_mode: bool,
+    callback: C,
+    data: T,
+) -> Result<TaskHandle>
+where
+    T: 'static + Send,
+    C: CallbackTrait<T, VkLdapAuthResult>,
+{
+    if !scheduler::is_scheduler_ready() {
+        return Ok(TaskHandle::default());
+    }
+
+    scheduler::submit_async_task(
+        context::ldap_modify_password(username, old_password, new_password, use_bind_mode),
+        callback,
+        data,
+    )
+}
+
+pub fn vk_ldap_search<C, T>(
+    request: VkLdapSearchRequest,
+    callback: C,
+    data: T,
+) -> Result<TaskHandle>
+where
+    T: 'static + Send,
+    C: CallbackTrait<T, Result<Vec<VkLdapSearchEntry>>>,
+{
+    if !scheduler::is_scheduler_ready() {
+        return Ok(TaskHandle::default());
+    }
+
+    scheduler::submit_async_task(context::ldap_search(request), callback, data)
+}
+
+/// Checks whether the user entry still exists and matches the revalidation
+/// filter. It must be awaited from a task running in the scheduler.
+pub async fn vk_ldap_check_user(user_dn: String) -> Result<bool> {
+    context::ldap_check_user(user_dn).await
+}
@@ -0,0 +1,128 @@
+use std::fs;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use log::{error, info};
+
+use crate::handle_io_error;
+
+use super::Result;
+use super::errors::VkLdapError;
+
+/// The passwords of the search bind user. The secondary password is accepted
+/// while the password is rotated.
+#[derive(Clone)]
+pub struct VkBindPasswords {
+    pub primary: String,
+    pub secondary: Option<String>,
+}
+
+struct VkPasswordFileState {
+    modified: Option<SystemTime>,
+    len: u64,
+    passwords: Option<VkBindPasswords>,
+}
+
+/// A file holding the password of the search bind user in its first line, and
+/// optionally a secondary password in its second line. The file is read again
+/// when it changes on disk.
+pub struct VkPasswordFile {
+    path: String,
+    state: Mutex<VkPasswordFileState>,
+}
+
+/// Parses the contents of a password file. Only the line endings are removed,
+/// as leading and trailing spaces can be part of the password.
+pub fn parse_passwords(contents: &str) -> std::result::Result<VkBindPasswords, String> {
+    let mut lines = contents.lines();
+    let primary = match lines.next() {
+        Some(line) if !line.is_empty() => line.to_string(),
+        _ => return Err("the first line of the file must hold the password".to_string()),
+    };
+    let secondary = lines
+        .next()
+        .filter(|line| !line.is_empty())
+        .map(str::to_string);
+
+    if lines.any(|line| !line.is_empty()) {
+        return Err("the file must hold at most two passwords".to_string());
+    }
+
+    Ok(VkBindPasswords { primary, secondary })
+}
+
+fn read_passwords(path: &str) -> Result<VkBindPasswords> {
+    let contents = handle_io_error!(
+        fs::read_to_string(path),
+        format!("failed to read the search bind password file {path}")
+    );
+    parse_passwords(&contents).map_err(|err| {
+        VkLdapError::PasswordFileError(format!("invalid search bind password file {path}"), err)
+    })
+}
+
+impl VkPasswordFile {
+    pub fn new(path: String) -> VkPasswordFile {
+        VkPasswordFile {
+            path,
+            state: Mutex::new(VkPasswordFileState {
+                modified: None,
+                len: 0,
+                passwords: None,
+            }),
+        }
+    }
+
+    /// Returns the passwords of the file, reading it again if its size or
+    /// modification time changed. When the changed file cannot be read, the
+    /// previous passwords are kept.
+    pub fn passwords(&self) -> Result<VkBindPasswords> {
+        let mut state = self.state.lock().unwrap();
+
+        let metadata = fs::metadata(&self.path).ok();
+        let modified = metadata.as_ref().and_then(|m| m.modified().ok());
+        let len = metadata.as_ref().map_or(0, |m| m.len());
+
+        let changed = modified != state.modified || len != state.len;
+        match (&state.passwords, changed) {
+            (Some(passwords), false) => return Ok(passwords.clone()),
+            (None, _) => {
+                let passwords = read_passwords(&self.path)?;
+                state.passwords = Some(passwords);
+            }
+            (Some(_), true) => match read_passwords(&self.path) {
+                Ok(passwords) => {
+                    info!("reloaded the search bind password file {}", self.path);
+                    state.passwords = Some(passwords);
+                }
+                Err(err) => {
+                    error!("{err}, keeping the current passwords");
+                }
+            },
+        }
+        state.modified = modified;
+        state.len = len;
+
+        Ok(state.passwords.clone().expect("passwords were loaded"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_passwords() {
+        let passwords = parse_passwords("new secret\r\n").ok().unwrap();
+        assert_eq!(passwords.primary, "new secret");
+        assert_eq!(passwords.secondary, None);
+
+        let passwords = parse_passwords("new\nold\n\n").ok().unwrap();
+        assert_eq!(passwords.primary, "new");
+        assert_eq!(passwords.secondary, Some("old".to_string()));
+
+        assert!(parse_passwords("").is_err());
+        assert!(parse_passwords("\nold\n").is_err());
+        assert!(parse_passwords("a\nb\nc\n").is_err());
+    }
+}
@@ -0,0 +1,187 @@
+use std::time::Duration;
+
+use ldap3::controls::{Control, RawControl};
+
+use super::errors::VkLdapFailureReason;
+
+// Password policy control from draft-behera-ldap-password-policy, supported by
+// the ppolicy overlay of OpenLDAP and by 389-DS.
+const PPOLICY_OID: &str = "1.3.6.1.4.1.42.2.27.8.5.1";
+
+pub(super) struct PasswordPolicyRequest;
+
+impl From<PasswordPolicyRequest> for RawControl {
+    fn from(_: PasswordPolicyRequest) -> RawControl {
+        RawControl {
+            ctype: PPOLICY_OID.to_string(),
+            crit: false,
+            val: None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub(super) enum PasswordPolicyError {
+    PasswordExpired,
+    AccountLocked,
+    ChangeAfterReset,
+    Other(u64),
+}
+
+impl PasswordPolicyError {
+    fn from_code(code: u64) -> PasswordPolicyError {
+        match code {
+            0 => PasswordPolicyError::PasswordExpired,
+            1 => PasswordPolicyError::AccountLocked,
+            2 => PasswordPolicyError::ChangeAfterReset,
+            code => PasswordPolicyError::Other(code),
+        }
+    }
+
+    pub fn failure_reason(&self) -> Option<VkLdapFailureReason> {
+        match self {
+            PasswordPolicyError::PasswordExpired => Some(VkLdapFailureReason::PasswordExpired),
+            PasswordPolicyError::AccountLocked => Some(VkLdapFailureReason::AccountLocked),
+            PasswordPolicyError::ChangeAfterReset => Some(VkLdapFailureReason::PasswordMustChange),
+            PasswordPolicyError::Other(_) => None,
+        }
+    }
+}
+
+impl std::fmt::Display for PasswordPolicyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PasswordPolicyError::PasswordExpired => write!(f, "passwordExpired"),
+            PasswordPolicyError::AccountLocked => write!(f, "accountLocked"),
+            PasswordPolicyError::ChangeAfterReset => write!(f, "changeAfterReset"),
+            PasswordPolicyError::Other(code) => write!(f, "error code {code}"),
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub(super) struct PasswordPolicyResponse {
+    pub time_before_expiration: Option<Duration>,
+    pub grace_logins_remaining: Option<u64>,
+    pub error: Option<PasswordPolicyError>,
+}
+
+impl PasswordPolicyResponse {
+    /// Finds and parses the password policy control in the controls of a bind
+    /// response. Returns the default (empty) response when the server did not
+    /// send the control, or when its value is malformed.
+    pub fn from_controls(ctrls: &[Control]) -> PasswordPolicyResponse {
+        ctrls
+            .iter()
+            .find(|Control(_, raw)| raw.ctype == PPOLICY_OID)
+            .and_then(|Control(_, raw)| raw.val.as_deref())
+            .and_then(Self::parse)
+            .unwrap_or_default()
+    }
+
+    // PasswordPolicyResponseValue ::= SEQUENCE {
+    //     warning [0] CHOICE {
+    //         timeBeforeExpiration [0] INTEGER (0 .. maxInt),
+    //         graceAuthNsRemaining [1] INTEGER (0 .. maxInt) } OPTIONAL,
+    //     error   [1] ENUMERATED { ... } OPTIONAL }
+    fn parse(value: &[u8]) -> Option<PasswordPolicyResponse> {
+        let (tag, content, _) = read_tlv(value)?;
+        if tag != 0x30 {
+            return None;
+        }
+
+        let mut response = PasswordPolicyResponse::default();
+        let mut rest = content;
+        while !rest.is_empty() {
+            let (tag, content, next) = read_tlv(rest)?;
+            match tag {
+                0xa0 => {
+                    let (warning_tag, warning, _) = read_tlv(content)?;
+                    let value = read_uint(warning)?;
+                    match warning_tag {
+                        0x80 => response.time_before_expiration = Some(Duration::from_secs(value)),
+                        0x81 => response.grace_logins_remaining = Some(value),
+                        _ => return None,
+                    }
+                }
+                0x81 => response.error = Some(PasswordPolicyError::from_code(read_uint(content)?)),
+                _ => return None,
+            }
+            rest = next;
+        }
+
+        Some(response)
+    }
+}
+
+/// Reads a BER encoded element, and returns its tag, its content, and the
+/// remaining bytes after the element.
+fn read_tlv(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let tag = *data.first()?;
+    let first_len = *data.get(1)? as usize;
+
+    let (len, header_len) = if first_len & 0x80 == 0 {
+        (first_len, 2)
+    } else {
+        let num_bytes = first_len & 0x7f;
+        if num_bytes == 0 || num_bytes > 4 {
+            return None;
+        }
+        let len_bytes = data.get(2..2 + num_bytes)?;
+        let len = len_bytes
+            .iter()
+            .fold(0usize, |len, byte| (len << 8) | *byte as usize);
+        (len, 2 + num_bytes)
+    };
+
+    let content = data.get(header_len..header_len + len)?;
+    Some((tag, content, &data[header_len + len..]))
+}
+
+fn read_uint(content: &[u8]) -> Option<u64> {
+    if content.is_empty() || content.len() > 8 {
+        return None;
+    }
+    Some(
+        content
+            .iter()
+            .fold(0u64, |value, byte| (value << 8) | *byte as u64),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_password_policy_response() {
+        let expiring = [0x30, 0x06, 0xa0, 0x04, 0x80, 0x02, 0x0e, 0x10];
+        assert_eq!(
+            PasswordPolicyResponse::parse(&expiring),
+            Some(PasswordPolicyResponse {
+                time_before_expiration: Some(Duration::from_secs(3600)),
+                ..Default::default()
+            })
+        );
+
+        let grace = [0x30, 0x05, 0xa0, 0x03, 0x81, 0x01, 0x02];
+        assert_eq!(
+            PasswordPolicyResponse::parse(&grace),
+            Some(PasswordPolicyResponse {
+                grace_logins_remaining: Some(2),
+                ..Default::default()
+            })
+        );
+
+        let locked = [0x30, 0x03, 0x81, 0x01, 0x01];
+        assert_eq!(
+            PasswordPolicyResponse::parse(&locked),
+            Some(PasswordPolicyResponse {
+                error: Some(PasswordPolicyError::AccountLocked),
+                ..Default::default()
+            })
+        );
+
+        assert_eq!(PasswordPolicyResponse::parse(&[0x30, 0x05, 0x81]), None);
+    }
+}
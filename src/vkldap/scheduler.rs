@@ -1,13 +1,19 @@
 use std::{
     any::Any,
-    sync::{Arc, Condvar, Mutex, RwLock, mpsc},
+    sync::{
+        Arc, Condvar, Mutex, RwLock,
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
     thread,
+    time::Duration,
 };
 
 use futures::future::BoxFuture;
 use lazy_static::lazy_static;
 use log::{debug, error};
 use tokio::runtime;
+use tokio::sync::oneshot;
 
 use super::{Result, errors::VkLdapError};
 
@@ -18,8 +24,8 @@ lazy_static! {
 pub trait TaskTrait<R>: Future<Output = R> + 'static + Send {}
 impl<R, TT: Future<Output = R> + Send + 'static> TaskTrait<R> for TT {}
 
-pub trait CallbackTrait<T: Send, R>: Fn(Option<T>, R) -> () + 'static + Send {}
-impl<T: Send, R, CT: Fn(Option<T>, R) -> () + 'static + Send> CallbackTrait<T, R> for CT {}
+pub trait CallbackTrait<T: Send, R>: Fn(Option<T>, R) + 'static + Send {}
+impl<T: Send, R, CT: Fn(Option<T>, R) + 'static + Send> CallbackTrait<T, R> for CT {}
 
 async fn coerce_future_output<F, R: 'static>(f: F) -> Box<dyn Any>
 where
@@ -60,6 +66,7 @@ struct Task {
     task: BoxFuture<'static, Box<dyn Any>>,
     callback: Box<dyn CallbackTrait<Box<dyn Any + Send>, Box<dyn Any>>>,
     data: Option<Box<dyn Any + Send>>,
+    cancel_rx: Option<oneshot::Receiver<()>>,
 }
 
 impl Task {
@@ -77,6 +84,35 @@ impl Task {
                 Some(data) => Some(Box::new(data)),
                 None => None,
             },
+            cancel_rx: None,
+        }
+    }
+
+    fn cancellable(&mut self) -> TaskHandle {
+        let (cancel_tx, cancel_rx) = oneshot::channel();
+        self.cancel_rx = Some(cancel_rx);
+        TaskHandle {
+            cancel_tx: Some(cancel_tx),
+        }
+    }
+}
+
+/// Handle to an async task submitted to the scheduler.
+///
+/// Dropping the handle does not cancel the task. When the task is cancelled,
+/// its future is dropped and the callback is not called, but the callback data
+/// is still released.
+#[derive(Default)]
+pub struct TaskHandle {
+    cancel_tx: Option<oneshot::Sender<()>>,
+}
+
+impl TaskHandle {
+    pub fn cancel(mut self) {
+        if let Some(cancel_tx) = self.cancel_tx.take() {
+            // The task might have already finished, in which case there is
+            // nothing to cancel.
+            let _ = cancel_tx.send(());
         }
     }
 }
@@ -140,7 +176,7 @@ impl Scheduler {
 
         self.state = Some(SchedulerState {
             thread_handler: handler,
-            job_tx: job_tx,
+            job_tx,
         });
     }
 
@@ -167,12 +203,9 @@ fn scheduler_loop(job_rx: mpsc::Receiver<Job>) {
     loop {
         match job_rx.recv() {
             Ok(job) => match job {
-                Job::Shutdown => return (),
+                Job::Shutdown => return,
                 Job::Task(task) => {
-                    tokio::spawn(async move {
-                        let res = task.task.await;
-                        (task.callback)(task.data, res);
-                    });
+                    tokio::spawn(run_task(task));
                 }
             },
             Err(err) => {
@@ -182,6 +215,28 @@ fn scheduler_loop(job_rx: mpsc::Receiver<Job>) {
     }
 }
 
+async fn run_task(task: Task) {
+    let Task {
+        task,
+        callback,
+        data,
+        cancel_rx,
+    } = task;
+
+    match cancel_rx {
+        Some(mut cancel_rx) => {
+            tokio::select! {
+                res = task => callback(data, res),
+                Ok(_) = &mut cancel_rx => debug!("async task was cancelled"),
+            }
+        }
+        None => {
+            let res = task.await;
+            callback(data, res);
+        }
+    }
+}
+
 pub fn start_job_scheduler() {
     SCHEDULER.write().unwrap().initialize();
 }
@@ -247,18 +302,168 @@ where
     Ok(notify.wait())
 }
 
-pub fn submit_async_task<F, C, R, T>(task: F, callback: C, data: T) -> Result<()>
+pub fn submit_async_task<F, C, R, T>(task: F, callback: C, data: T) -> Result<TaskHandle>
 where
     F: TaskTrait<R>,
     C: CallbackTrait<T, R>,
     R: 'static,
     T: 'static + Send,
 {
-    let payload = Task::new(task, callback, Some(data));
+    let mut payload = Task::new(task, callback, Some(data));
+    let handle = payload.cancellable();
 
     SCHEDULER
         .read()
         .unwrap()
         .get_sender()
-        .send(Job::Task(payload))
+        .send(Job::Task(payload))?;
+
+    Ok(handle)
+}
+
+/// A loop that runs as an async task of the scheduler until it is shut down,
+/// such as the failure detector and the session manager.
+///
+/// The loop waits between iterations with `sleep`, which is interrupted when a
+/// stop is requested or the settings of the loop change. Both signals store a
+/// permit when nobody is waiting, so a stop request or a settings change that
+/// happens during an iteration is not lost.
+pub struct BackgroundLoop {
+    name: &'static str,
+    done: Mutex<Option<mpsc::Receiver<()>>>,
+    stop: AtomicBool,
+    stop_signal: tokio::sync::Notify,
+    settings_changed: tokio::sync::Notify,
+}
+
+impl BackgroundLoop {
+    pub fn new(name: &'static str) -> BackgroundLoop {
+        BackgroundLoop {
+            name,
+            done: Mutex::new(None),
+            stop: AtomicBool::new(false),
+            stop_signal: tokio::sync::Notify::new(),
+            settings_changed: tokio::sync::Notify::new(),
+        }
+    }
+
+    pub fn start<F>(&self, task: F)
+    where
+        F: TaskTrait<()>,
+    {
+        self.stop.store(false, Ordering::Release);
+        let mut done = self.done.lock().unwrap();
+
+        let (done_tx, done_rx) = mpsc::channel();
+        let res = submit_async_task(
+            async move {
+                task.await;
+                let _ = done_tx.send(());
+            },
+            |_: Option<()>, _: ()| {},
+            (),
+        );
+
+        match res {
+            Ok(_) => *done = Some(done_rx),
+            Err(err) => error!("failed to start the {}: {err}", self.name),
+        }
+    }
+
+    pub fn shutdown(&self) -> Result<()> {
+        self.stop.store(true, Ordering::Release);
+        self.stop_signal.notify_one();
+
+        let done_opt = self.done.lock().unwrap().take();
+        match done_opt {
+            Some(done_rx) => done_rx
+                .recv()
+                .map_err(|_| VkLdapError::FailedToStopBackgroundLoop(self.name)),
+            None => Ok(()),
+        }
+    }
+
+    pub fn should_stop(&self) -> bool {
+        self.stop.load(Ordering::Acquire)
+    }
+
+    pub fn notify_settings_changed(&self) {
+        self.settings_changed.notify_one();
+    }
+
+    /// Sleeps for `duration`, or until interrupted when no duration is given.
+    /// Returns whether the sleep was interrupted.
+    pub async fn sleep(&self, duration: Option<Duration>) -> bool {
+        tokio::select! {
+            _ = tokio::time::sleep(duration.unwrap_or_default()), if duration.is_some() => false,
+            _ = self.settings_changed.notified() => true,
+            _ = self.stop_signal.notified() => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicU32;
+    use std::time::Instant;
+
+    use super::*;
+
+    #[test]
+    fn test_background_loop_sleep_signals() {
+        let rt = runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let background_loop = BackgroundLoop::new("test loop");
+
+        rt.block_on(async {
+            assert!(!background_loop.sleep(Some(Duration::from_millis(1))).await);
+
+            // A settings change that happens before the sleep is not lost.
+            background_loop.notify_settings_changed();
+            assert!(background_loop.sleep(None).await);
+            assert!(!background_loop.sleep(Some(Duration::from_millis(1))).await);
+        });
+
+        // Shutting down a loop that was never started only flags the stop.
+        assert!(background_loop.shutdown().is_ok());
+        assert!(background_loop.should_stop());
+        rt.block_on(async {
+            assert!(background_loop.sleep(None).await);
+        });
+    }
+
+    #[test]
+    fn test_background_loop_shutdown() {
+        start_job_scheduler();
+
+        let background_loop = Arc::new(BackgroundLoop::new("test loop"));
+        let iterations = Arc::new(AtomicU32::new(0));
+        let task_loop = Arc::clone(&background_loop);
+        let task_iterations = Arc::clone(&iterations);
+        background_loop.start(async move {
+            while !task_loop.should_stop() {
+                task_iterations.fetch_add(1, Ordering::Relaxed);
+                task_loop.sleep(Some(Duration::from_secs(60))).await;
+            }
+        });
+
+        // A settings change wakes the loop up for another iteration.
+        while iterations.load(Ordering::Relaxed) == 0 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        background_loop.notify_settings_changed();
+        while iterations.load(Ordering::Relaxed) == 1 {
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        // The shutdown interrupts the sleep and waits for the loop to end.
+        let start = Instant::now();
+        assert!(background_loop.shutdown().is_ok());
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert_eq!(iterations.load(Ordering::Relaxed), 2);
+
+        assert!(stop_job_scheduler().is_ok());
+    }
 }
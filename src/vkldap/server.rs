@@ -1,10 +1,12 @@
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
+use percent_encoding::percent_decode_str;
 use url::Url;
 
 #[derive(Clone)]
 pub enum VkLdapServerStatus {
     HEALTHY,
+    DEGRADED(String),
     UNHEALTHY(String),
 }
 
@@ -12,6 +14,7 @@ impl std::fmt::Display for VkLdapServerStatus {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::HEALTHY => write!(f, "HEALTHY"),
+            Self::DEGRADED(msg) => write!(f, "DEGRADED: [{msg}]"),
             Self::UNHEALTHY(msg) => write!(f, "UNHEALTHY: [{msg}]"),
         }
     }
@@ -23,21 +26,62 @@ impl PartialEq for VkLdapServerStatus {
     }
 }
 
+/// Per-server overrides set as query parameters in the server URL.
+#[derive(Clone, Default)]
+pub struct VkLdapServerOptions {
+    pub use_starttls: Option<bool>,
+    pub ca_cert_path: Option<String>,
+    pub tls_server_name: Option<String>,
+    pub tls_pinned_keys: Option<Vec<String>>,
+    pub connection_pool_size: Option<usize>,
+    pub timeout_connection: Option<Duration>,
+    pub weight: Option<u32>,
+    pub priority: u32,
+}
+
+/// Returns the first priority that has both servers with and without a
+/// weight. The servers without a weight would never be picked while a
+/// weighted one is available, so such server lists are rejected.
+pub fn find_mixed_weight_priority(options: &[&VkLdapServerOptions]) -> Option<u32> {
+    options.iter().find_map(|server| {
+        options
+            .iter()
+            .any(|other| {
+                other.priority == server.priority
+                    && other.weight.is_some() != server.weight.is_some()
+            })
+            .then_some(server.priority)
+    })
+}
+
 #[derive(Clone)]
 pub struct VkLdapServer {
     url: Url,
     id: usize,
     status: VkLdapServerStatus,
     ping_time: Option<Duration>,
+    options: VkLdapServerOptions,
+    marked_down: bool,
+    last_error: Option<String>,
+    last_transition: SystemTime,
 }
 
 impl VkLdapServer {
-    pub(super) fn new(url: Url, id: usize, status: VkLdapServerStatus) -> VkLdapServer {
+    pub(super) fn new(
+        url: Url,
+        id: usize,
+        status: VkLdapServerStatus,
+        options: VkLdapServerOptions,
+    ) -> VkLdapServer {
         VkLdapServer {
             url,
             id,
             status,
             ping_time: None,
+            options,
+            marked_down: false,
+            last_error: None,
+            last_transition: SystemTime::now(),
         }
     }
 
@@ -45,22 +89,63 @@ impl VkLdapServer {
         &self.url
     }
 
-    pub(super) fn get_id(&self) -> usize {
+    pub fn get_id(&self) -> usize {
         self.id
     }
 
     pub(super) fn is_healthy(&self) -> bool {
-        self.status == VkLdapServerStatus::HEALTHY
+        !self.marked_down && self.status == VkLdapServerStatus::HEALTHY
+    }
+
+    pub(super) fn is_available(&self) -> bool {
+        if self.marked_down {
+            return false;
+        }
+
+        match self.status {
+            VkLdapServerStatus::HEALTHY | VkLdapServerStatus::DEGRADED(_) => true,
+            VkLdapServerStatus::UNHEALTHY(_) => false,
+        }
     }
 
     pub fn get_status(&self) -> VkLdapServerStatus {
-        return self.status.clone();
+        self.status.clone()
     }
 
     pub(super) fn set_status(&mut self, status: VkLdapServerStatus) {
+        if self.status != status {
+            self.last_transition = SystemTime::now();
+        }
+        match &status {
+            VkLdapServerStatus::HEALTHY => (),
+            VkLdapServerStatus::DEGRADED(msg) | VkLdapServerStatus::UNHEALTHY(msg) => {
+                self.last_error = Some(msg.clone())
+            }
+        }
         self.status = status
     }
 
+    /// Whether the server was marked down by an administrator. Servers marked
+    /// down are not used for new operations, nor probed by the failure detector.
+    pub fn is_marked_down(&self) -> bool {
+        self.marked_down
+    }
+
+    pub(super) fn set_marked_down(&mut self, marked_down: bool) {
+        if self.marked_down != marked_down {
+            self.last_transition = SystemTime::now();
+        }
+        self.marked_down = marked_down
+    }
+
+    pub fn get_last_error(&self) -> Option<&str> {
+        self.last_error.as_deref()
+    }
+
+    pub fn get_last_transition(&self) -> SystemTime {
+        self.last_transition
+    }
+
     pub(super) fn set_ping_time(&mut self, ping_time: Option<Duration>) {
         self.ping_time = ping_time
     }
@@ -69,10 +154,62 @@ impl VkLdapServer {
         self.ping_time
     }
 
+    pub fn get_options(&self) -> &VkLdapServerOptions {
+        &self.options
+    }
+
+    /// Whether the server is reached through a Unix socket, with an ldapi URL.
+    pub fn is_unix_socket(&self) -> bool {
+        self.url.scheme() == "ldapi"
+    }
+
+    /// Returns the host of the server URL, or the path of the socket of the
+    /// servers reached through a Unix socket.
     pub fn get_host_string(&self) -> String {
+        if self.is_unix_socket() {
+            let host = self.url.host_str().unwrap_or_default();
+            return percent_decode_str(host).decode_utf8_lossy().to_string();
+        }
+
         match self.url.host() {
             Some(host) => host.to_string(),
             None => self.url.to_string(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(weight: Option<u32>, priority: u32) -> VkLdapServerOptions {
+        VkLdapServerOptions {
+            weight,
+            priority,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_find_mixed_weight_priority() {
+        let unweighted = options(None, 0);
+        let weighted = options(Some(5), 0);
+        let drained = options(Some(0), 0);
+        let backup = options(None, 1);
+
+        assert_eq!(
+            find_mixed_weight_priority(&[&unweighted, &unweighted]),
+            None
+        );
+        assert_eq!(find_mixed_weight_priority(&[&weighted, &drained]), None);
+        assert_eq!(find_mixed_weight_priority(&[&weighted, &backup]), None);
+        assert_eq!(
+            find_mixed_weight_priority(&[&weighted, &unweighted]),
+            Some(0)
+        );
+        assert_eq!(
+            find_mixed_weight_priority(&[&backup, &drained, &unweighted]),
+            Some(0)
+        );
+    }
+}
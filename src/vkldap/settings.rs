@@ -1,8 +1,13 @@
+use std::sync::Arc;
 use std::time::Duration;
 
 use ldap3::Scope;
 
-use crate::configs::LdapSearchScope;
+use crate::configs::{LdapBindMechanism, LdapHealthCheck, LdapSearchScope, LdapTlsVersion};
+
+use super::passwd_file::VkPasswordFile;
+use super::server::VkLdapServer;
+use super::tls::VkTlsMaterial;
 
 impl From<LdapSearchScope> for Scope {
     fn from(value: LdapSearchScope) -> Self {
@@ -24,11 +29,29 @@ pub struct VkLdapSettings {
     pub search_attribute: Option<String>,
     pub search_bind_dn: Option<String>,
     pub search_bind_passwd: Option<String>,
+    /// Takes precedence over `search_bind_passwd` when set.
+    pub search_bind_passwd_file: Option<Arc<VkPasswordFile>>,
+    pub search_bind_mechanism: LdapBindMechanism,
     pub search_dn_attribute: String,
+    pub search_account_status_check: bool,
     pub timeout_ldap_operation: Duration,
+    pub timeout_auth_total: Option<Duration>,
+    pub health_check: LdapHealthCheck,
+    pub health_check_timeout: Duration,
+    pub health_check_slow_threshold: Option<Duration>,
+    pub health_check_search_base: Option<String>,
+    pub health_check_search_filter: Option<String>,
+    pub hedge_delay: Option<Duration>,
+    pub hedge_delay_percentile: Option<f64>,
+    /// Whether binds are hedged too. A hedged bind is sent to two servers, so
+    /// a wrong password counts twice towards the lockout threshold of the
+    /// directory.
+    pub hedge_binds: bool,
+    pub revalidation_filter: Option<String>,
 }
 
 impl VkLdapSettings {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         bind_db_prefix: String,
         bind_db_suffix: String,
@@ -38,8 +61,21 @@ impl VkLdapSettings {
         search_attribute: Option<String>,
         search_bind_dn: Option<String>,
         search_bind_passwd: Option<String>,
+        search_bind_passwd_file: Option<Arc<VkPasswordFile>>,
+        search_bind_mechanism: LdapBindMechanism,
         search_dn_attribute: String,
+        search_account_status_check: bool,
         timeout_ldap_operation: Duration,
+        timeout_auth_total: Option<Duration>,
+        health_check: LdapHealthCheck,
+        health_check_timeout: Duration,
+        health_check_slow_threshold: Option<Duration>,
+        health_check_search_base: Option<String>,
+        health_check_search_filter: Option<String>,
+        hedge_delay: Option<Duration>,
+        hedge_delay_percentile: Option<f64>,
+        hedge_binds: bool,
+        revalidation_filter: Option<String>,
     ) -> Self {
         Self {
             bind_db_prefix,
@@ -50,8 +86,47 @@ impl VkLdapSettings {
             search_attribute,
             search_bind_dn,
             search_bind_passwd,
+            search_bind_passwd_file,
+            search_bind_mechanism,
             search_dn_attribute,
+            search_account_status_check,
             timeout_ldap_operation,
+            timeout_auth_total,
+            health_check,
+            health_check_timeout,
+            health_check_slow_threshold,
+            health_check_search_base,
+            health_check_search_filter,
+            hedge_delay,
+            hedge_delay_percentile,
+            hedge_binds,
+            revalidation_filter,
+        }
+    }
+
+    /// Whether the search bind user has credentials to bind with. The simple
+    /// bind needs both the DN and a password.
+    pub fn has_search_bind_credentials(&self) -> bool {
+        match self.search_bind_mechanism {
+            LdapBindMechanism::Simple => {
+                self.search_bind_dn.is_some()
+                    && (self.search_bind_passwd_file.is_some() || self.search_bind_passwd.is_some())
+            }
+            LdapBindMechanism::External | LdapBindMechanism::Gssapi => true,
+        }
+    }
+
+    /// The probe run by the failure detector. The `service-bind` and `search`
+    /// probes fall back to `whoami` when the search bind user has no
+    /// credentials, since they could not bind.
+    pub fn effective_health_check(&self) -> LdapHealthCheck {
+        match self.health_check {
+            LdapHealthCheck::ServiceBind | LdapHealthCheck::Search
+                if !self.has_search_bind_credentials() =>
+            {
+                LdapHealthCheck::WhoAmI
+            }
+            _ => self.health_check.clone(),
         }
     }
 }
@@ -67,28 +142,76 @@ impl Default for VkLdapSettings {
             search_attribute: Default::default(),
             search_bind_dn: Default::default(),
             search_bind_passwd: Default::default(),
+            search_bind_passwd_file: Default::default(),
+            search_bind_mechanism: LdapBindMechanism::Simple,
             search_dn_attribute: Default::default(),
+            search_account_status_check: Default::default(),
             timeout_ldap_operation: Default::default(),
+            timeout_auth_total: Default::default(),
+            health_check: LdapHealthCheck::WhoAmI,
+            health_check_timeout: Default::default(),
+            health_check_slow_threshold: Default::default(),
+            health_check_search_base: Default::default(),
+            health_check_search_filter: Default::default(),
+            hedge_delay: Default::default(),
+            hedge_delay_percentile: Default::default(),
+            hedge_binds: Default::default(),
+            revalidation_filter: Default::default(),
         }
     }
 }
 
+/// How the TLS connections verify the LDAP servers.
 #[derive(Clone)]
+pub struct VkTlsPolicy {
+    pub ca_cert_dir: Option<String>,
+    pub min_version: LdapTlsVersion,
+    pub use_system_ca: bool,
+    /// The name to verify the server certificates against, instead of the
+    /// host of the server URL.
+    pub server_name: Option<String>,
+    /// The base64 encoded SHA-256 digests of the accepted server public keys.
+    /// When empty, any public key is accepted.
+    pub pinned_keys: Vec<String>,
+    pub skip_verify: bool,
+}
+
+impl Default for VkTlsPolicy {
+    fn default() -> Self {
+        Self {
+            ca_cert_dir: Default::default(),
+            min_version: LdapTlsVersion::Tls12,
+            use_system_ca: true,
+            server_name: Default::default(),
+            pinned_keys: Default::default(),
+            skip_verify: Default::default(),
+        }
+    }
+}
+
+#[derive(Clone, Default)]
 pub struct VkConnectionSettings {
     pub use_starttls: bool,
     pub ca_cert_path: Option<String>,
     pub client_cert_path: Option<String>,
     pub client_key_path: Option<String>,
+    pub client_key_passphrase: Option<String>,
+    pub tls_policy: VkTlsPolicy,
     pub connection_pool_size: usize,
     pub timeout_connection: Duration,
+    /// The loaded TLS files, set by the LDAP context.
+    pub(super) tls_material: Option<Arc<VkTlsMaterial>>,
 }
 
 impl VkConnectionSettings {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         use_starttls: bool,
         ca_cert_path: Option<String>,
         client_cert_path: Option<String>,
         client_key_path: Option<String>,
+        client_key_passphrase: Option<String>,
+        tls_policy: VkTlsPolicy,
         connection_pool_size: usize,
         timeout_connection: Duration,
     ) -> Self {
@@ -97,21 +220,38 @@ impl VkConnectionSettings {
             ca_cert_path,
             client_cert_path,
             client_key_path,
+            client_key_passphrase,
+            tls_policy,
             connection_pool_size,
             timeout_connection,
+            tls_material: None,
         }
     }
-}
 
-impl Default for VkConnectionSettings {
-    fn default() -> Self {
-        Self {
-            use_starttls: Default::default(),
-            ca_cert_path: Default::default(),
-            client_cert_path: Default::default(),
-            client_key_path: Default::default(),
-            connection_pool_size: 0,
-            timeout_connection: Default::default(),
+    /// Returns a copy of these settings with the overrides of `server` applied.
+    pub(super) fn for_server(&self, server: &VkLdapServer) -> VkConnectionSettings {
+        let options = server.get_options();
+        let mut settings = self.clone();
+
+        if let Some(use_starttls) = options.use_starttls {
+            settings.use_starttls = use_starttls;
         }
+        if let Some(ca_cert_path) = &options.ca_cert_path {
+            settings.ca_cert_path = Some(ca_cert_path.clone());
+        }
+        if let Some(server_name) = &options.tls_server_name {
+            settings.tls_policy.server_name = Some(server_name.clone());
+        }
+        if let Some(pinned_keys) = &options.tls_pinned_keys {
+            settings.tls_policy.pinned_keys = pinned_keys.clone();
+        }
+        if let Some(pool_size) = options.connection_pool_size {
+            settings.connection_pool_size = pool_size;
+        }
+        if let Some(timeout) = options.timeout_connection {
+            settings.timeout_connection = timeout;
+        }
+
+        settings
     }
 }
@@ -0,0 +1,260 @@
+#[cfg(all(feature = "tls-native", feature = "tls-rustls"))]
+compile_error!("the \"tls-native\" and \"tls-rustls\" features cannot be enabled at the same time");
+
+#[cfg(not(any(feature = "tls-native", feature = "tls-rustls")))]
+compile_error!("either the \"tls-native\" or the \"tls-rustls\" feature must be enabled");
+
+#[cfg(feature = "tls-native")]
+mod native;
+#[cfg(feature = "tls-rustls")]
+mod rustls;
+
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use ldap3::{Ldap, LdapConnSettings, LdapError};
+use log::{debug, error, warn};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::handle_io_error;
+
+use super::Result;
+use super::errors::VkLdapError;
+use super::settings::{VkConnectionSettings, VkTlsPolicy};
+
+#[cfg(feature = "tls-rustls")]
+use self::rustls::VkTlsBackend;
+#[cfg(feature = "tls-native")]
+use native::VkTlsBackend;
+
+static PIN_MISMATCHES: AtomicU64 = AtomicU64::new(0);
+
+/// The TLS files used to create the LDAP connections. The files are read and
+/// validated once, so that broken files are rejected before any connection is
+/// replaced, and so that new connections do not read the files again.
+pub(super) struct VkTlsMaterial {
+    generation: u64,
+    policy: VkTlsPolicy,
+    ca_dir_listing: Vec<String>,
+    // The contents of the files, to detect when they change on disk.
+    files: Vec<(String, Vec<u8>)>,
+    backend: VkTlsBackend,
+}
+
+fn read_file(path: &str, what: &str, files: &mut Vec<(String, Vec<u8>)>) -> Result<Vec<u8>> {
+    let bytes = handle_io_error!(fs::read(path), format!("failed to read {what} file"));
+    files.push((path.to_string(), bytes.clone()));
+    Ok(bytes)
+}
+
+/// Whether the client certificate file is a PKCS#12 bundle, which holds both
+/// the certificate and the key. Bundles are recognized by their extension, or
+/// by being DER encoded instead of PEM encoded.
+fn is_pkcs12_bundle(path: &str, bytes: &[u8]) -> bool {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase());
+
+    match extension.as_deref() {
+        Some("p12") | Some("pfx") => true,
+        _ => pem_label(bytes).is_none() && bytes.first() == Some(&0x30),
+    }
+}
+
+/// Returns the label of the first PEM block, e.g., "RSA PRIVATE KEY".
+fn pem_label(bytes: &[u8]) -> Option<&str> {
+    let text = std::str::from_utf8(bytes).ok()?;
+    let start = text.find("-----BEGIN ")? + "-----BEGIN ".len();
+    let len = text[start..].find("-----")?;
+    Some(&text[start..start + len])
+}
+
+/// Whether the PEM encoded key is encrypted with the legacy PEM encryption of
+/// the PKCS#1 and SEC1 formats, which is signaled by a header instead of the
+/// label.
+fn is_legacy_encrypted(bytes: &[u8]) -> bool {
+    std::str::from_utf8(bytes)
+        .map(|text| text.contains("Proc-Type: 4,ENCRYPTED"))
+        .unwrap_or(false)
+}
+
+/// Reads the PEM files in `dir` that hold certificates. Files that do not,
+/// like the CRLs or keys of a system CA directory, are skipped.
+fn read_ca_cert_dir(
+    dir: &str,
+    files: &mut Vec<(String, Vec<u8>)>,
+) -> Result<Vec<(String, Vec<u8>)>> {
+    let entries = handle_io_error!(
+        fs::read_dir(dir),
+        "failed to read CA cert directory".to_string()
+    );
+
+    let mut paths = Vec::new();
+    for entry in entries {
+        let entry = handle_io_error!(entry, "failed to read CA cert directory".to_string());
+        if entry.path().is_file() {
+            paths.push(entry.path().to_string_lossy().to_string());
+        }
+    }
+    paths.sort();
+
+    let mut ca_files = Vec::new();
+    for path in paths {
+        let bytes = read_file(&path, "CA cert", files)?;
+        if pem_label(&bytes) != Some("CERTIFICATE") {
+            debug!("skipping {path} in the CA cert directory, it is not a PEM certificate");
+            continue;
+        }
+        ca_files.push((path, bytes));
+    }
+
+    Ok(ca_files)
+}
+
+/// The names of the files in `dir`, to detect files added or removed.
+fn list_dir(dir: &str) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.file_name().to_string_lossy().to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names
+}
+
+/// Returns the pin of a public key: the base64 encoded SHA-256 digest of its
+/// DER encoded subject public key info.
+fn public_key_pin(spki: &[u8]) -> String {
+    BASE64.encode(Sha256::digest(spki))
+}
+
+/// Checks the public key pin of the server at `server_url` against the pinned
+/// keys, which must not be empty.
+fn check_public_key_pin(server_url: &str, pin: String, pinned_keys: &[String]) -> Result<()> {
+    if !pinned_keys.contains(&pin) {
+        PIN_MISMATCHES.fetch_add(1, Ordering::Relaxed);
+        error!("the public key of server {server_url} (sha256//{pin}) is not pinned");
+        return Err(VkLdapError::TLSPinMismatch(server_url.to_string(), pin));
+    }
+    Ok(())
+}
+
+pub(super) fn get_pin_mismatches() -> u64 {
+    PIN_MISMATCHES.load(Ordering::Relaxed)
+}
+
+/// Rejects, when the config is set, the client certificate or key files in a
+/// format that the TLS backend cannot load. Files that cannot be read are
+/// reported when the TLS material is loaded.
+pub(super) fn check_client_file(path: &str) -> Result<()> {
+    match fs::read(path) {
+        Ok(bytes) => VkTlsBackend::check_client_file(path, &bytes),
+        Err(_) => Ok(()),
+    }
+}
+
+/// Rejects, when the config is set, a passphrase that the TLS backend cannot
+/// use.
+pub(super) fn check_key_passphrase(passphrase: &str) -> Result<()> {
+    if passphrase.is_empty() {
+        return Ok(());
+    }
+    VkTlsBackend::check_key_passphrase()
+}
+
+impl VkTlsMaterial {
+    /// Loads the CA certificate and the client identity of the settings, and
+    /// the additional CA certificates set per server.
+    pub fn load(
+        settings: &VkConnectionSettings,
+        server_ca_paths: &[String],
+        generation: u64,
+    ) -> Result<VkTlsMaterial> {
+        let mut files = Vec::new();
+        let policy = settings.tls_policy.clone();
+
+        let (ca_dir_files, ca_dir_listing) = match &policy.ca_cert_dir {
+            Some(dir) => (read_ca_cert_dir(dir, &mut files)?, list_dir(dir)),
+            None => (Vec::new(), Vec::new()),
+        };
+
+        let backend = VkTlsBackend::load(settings, server_ca_paths, &ca_dir_files, &mut files)?;
+
+        if policy.skip_verify {
+            warn!(
+                "ldap.tls_skip_verify is enabled: the certificates of the LDAP servers are NOT \
+                 verified and the passwords can be intercepted. Never use it outside of a lab."
+            );
+        }
+
+        Ok(VkTlsMaterial {
+            generation,
+            policy,
+            ca_dir_listing,
+            files,
+            backend,
+        })
+    }
+
+    pub fn get_generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Whether any of the files differs from the loaded contents, or files
+    /// were added to or removed from the CA cert directory.
+    pub fn has_changed_on_disk(&self) -> bool {
+        if let Some(dir) = &self.policy.ca_cert_dir {
+            if list_dir(dir) != self.ca_dir_listing {
+                return true;
+            }
+        }
+
+        self.files
+            .iter()
+            .any(|(path, contents)| match fs::read(path) {
+                Ok(bytes) => bytes != *contents,
+                Err(_) => true,
+            })
+    }
+
+    /// Sets up the TLS connection to the server at `server_url` in
+    /// `ldap_conn_settings`, with the CA certificate, server name and pinned
+    /// keys of `settings`.
+    pub async fn configure(
+        &self,
+        settings: &VkConnectionSettings,
+        server_url: &Url,
+        ldap_conn_settings: LdapConnSettings,
+    ) -> Result<LdapConnSettings> {
+        self.backend
+            .configure(settings, server_url, ldap_conn_settings)
+            .await
+    }
+
+    /// Returns the error of a failed connection with this material, mapping
+    /// the handshake errors that the TLS backend recognizes, such as a pin
+    /// mismatch, to their own error.
+    pub fn connection_error(&self, err: LdapError) -> VkLdapError {
+        VkTlsBackend::connection_error(err)
+    }
+
+    /// Verifies the certificate of the established connection `ldap` to the
+    /// server at `server_url`, for the checks that the TLS backend cannot run
+    /// in the handshake. The connection must not be used if it fails.
+    pub async fn verify_peer(
+        &self,
+        settings: &VkConnectionSettings,
+        server_url: &Url,
+        ldap: &mut Ldap,
+    ) -> Result<()> {
+        self.backend.verify_peer(settings, server_url, ldap).await
+    }
+}
@@ -0,0 +1,329 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use ldap3::{Ldap, LdapConnSettings, LdapError};
+use native_tls::{Certificate, Identity, Protocol, TlsConnector};
+use openssl::error::ErrorStack;
+use openssl::pkey::PKey;
+use openssl::stack::Stack;
+use openssl::x509::store::X509StoreBuilder;
+use openssl::x509::verify::{X509VerifyFlags, X509VerifyParam};
+use openssl::x509::{X509, X509StoreContext};
+use url::Url;
+
+use crate::configs::LdapTlsVersion;
+use crate::handle_tls_error;
+use crate::vkldap::Result;
+use crate::vkldap::errors::VkLdapError;
+use crate::vkldap::settings::{VkConnectionSettings, VkTlsPolicy};
+
+use super::{
+    check_public_key_pin, is_legacy_encrypted, is_pkcs12_bundle, pem_label, public_key_pin,
+    read_file,
+};
+
+/// The TLS files parsed for native-tls.
+pub(super) struct VkTlsBackend {
+    policy: VkTlsPolicy,
+    ca_certs: HashMap<String, Certificate>,
+    ca_dir_certs: Vec<Certificate>,
+    identity: Option<Identity>,
+}
+
+impl From<LdapTlsVersion> for Protocol {
+    fn from(value: LdapTlsVersion) -> Self {
+        match value {
+            LdapTlsVersion::Tls10 => Protocol::Tlsv10,
+            LdapTlsVersion::Tls11 => Protocol::Tlsv11,
+            LdapTlsVersion::Tls12 => Protocol::Tlsv12,
+            LdapTlsVersion::Tls13 => Protocol::Tlsv13,
+        }
+    }
+}
+
+fn load_ca_cert(path: &str, files: &mut Vec<(String, Vec<u8>)>) -> Result<Certificate> {
+    let bytes = read_file(path, "CA cert", files)?;
+    let ca_cert = handle_tls_error!(
+        Certificate::from_pem(&bytes),
+        "failed to load CA certificate".to_string()
+    );
+    Ok(ca_cert)
+}
+
+/// Parses every certificate of the PEM files, which may hold several.
+fn parse_ca_cert_dir(ca_dir_files: &[(String, Vec<u8>)]) -> Result<Vec<Certificate>> {
+    let mut ca_certs = Vec::new();
+    for (path, bytes) in ca_dir_files {
+        let msg = format!("failed to load CA certificates of {path}");
+        let certs = handle_tls_error!(X509::stack_from_pem(bytes), msg);
+        for cert in certs {
+            let der = handle_tls_error!(cert.to_der(), msg);
+            ca_certs.push(handle_tls_error!(Certificate::from_der(&der), msg));
+        }
+    }
+    Ok(ca_certs)
+}
+
+/// Converts the client key to the unencrypted PKCS#8 PEM format, which is the
+/// only format accepted by `Identity::from_pkcs8`. PKCS#1 (RSA) and SEC1 (EC)
+/// keys, and keys encrypted with `passphrase`, are supported.
+fn client_key_to_pkcs8(bytes: &[u8], passphrase: Option<&String>) -> Result<Vec<u8>> {
+    let Some(label) = pem_label(bytes) else {
+        return Err(VkLdapError::TLSError(
+            "failed to load client key".to_string(),
+            "the file is not PEM encoded (PKCS#12 bundles must be set in ldap.tls_cert_path)"
+                .to_string(),
+        ));
+    };
+
+    let (format, encrypted) = match label {
+        "PRIVATE KEY" => return Ok(bytes.to_vec()),
+        "ENCRYPTED PRIVATE KEY" => ("PKCS#8", true),
+        "RSA PRIVATE KEY" => ("PKCS#1", is_legacy_encrypted(bytes)),
+        "EC PRIVATE KEY" => ("SEC1", is_legacy_encrypted(bytes)),
+        _ => {
+            return Err(VkLdapError::TLSError(
+                "failed to load client key".to_string(),
+                format!("unsupported PEM block '{label}'"),
+            ));
+        }
+    };
+
+    let key = if encrypted {
+        let Some(passphrase) = passphrase else {
+            return Err(VkLdapError::TLSError(
+                format!("failed to load {format} client key"),
+                "the key is encrypted but ldap.tls_key_passphrase is not set".to_string(),
+            ));
+        };
+        handle_tls_error!(
+            PKey::private_key_from_pem_passphrase(bytes, passphrase.as_bytes()),
+            format!("failed to decrypt {format} client key, check ldap.tls_key_passphrase")
+        )
+    } else {
+        handle_tls_error!(
+            PKey::private_key_from_pem(bytes),
+            format!("failed to parse {format} client key")
+        )
+    };
+
+    let pkcs8 = handle_tls_error!(
+        key.private_key_to_pem_pkcs8(),
+        format!("failed to convert {format} client key to PKCS#8")
+    );
+    Ok(pkcs8)
+}
+
+fn load_identity(
+    settings: &VkConnectionSettings,
+    files: &mut Vec<(String, Vec<u8>)>,
+) -> Result<Option<Identity>> {
+    let Some(cert_path) = &settings.client_cert_path else {
+        return Ok(None);
+    };
+
+    let cert_bytes = read_file(cert_path, "client certificate", files)?;
+
+    if is_pkcs12_bundle(cert_path, &cert_bytes) {
+        let passphrase = settings.client_key_passphrase.as_deref().unwrap_or("");
+        let identity = handle_tls_error!(
+            Identity::from_pkcs12(&cert_bytes, passphrase),
+            "failed to load PKCS#12 client certificate bundle, check ldap.tls_key_passphrase"
+                .to_string()
+        );
+        return Ok(Some(identity));
+    }
+
+    let Some(key_path) = &settings.client_key_path else {
+        return Err(VkLdapError::NoTLSKeyPathSet);
+    };
+
+    let key_bytes = read_file(key_path, "client key", files)?;
+    let key_bytes = client_key_to_pkcs8(&key_bytes, settings.client_key_passphrase.as_ref())?;
+    let identity = handle_tls_error!(
+        Identity::from_pkcs8(&cert_bytes, &key_bytes),
+        "failed to load client certificate".to_string()
+    );
+    Ok(Some(identity))
+}
+
+/// Returns the public key pin of `cert`.
+fn certificate_pin(cert: &X509) -> std::result::Result<String, ErrorStack> {
+    let spki = cert.public_key()?.public_key_to_der()?;
+    Ok(public_key_pin(&spki))
+}
+
+/// Checks that `cert` is valid for `server_name`, with the host name and IP
+/// address checks of OpenSSL (`X509_check_host` and `X509_check_ip`). The
+/// certificate chain was already verified in the TLS handshake, so the
+/// certificate is trusted as is, and only its name is verified.
+fn is_valid_for_name(cert: &X509, server_name: &str) -> std::result::Result<bool, ErrorStack> {
+    let mut param = X509VerifyParam::new()?;
+    match server_name.parse::<IpAddr>() {
+        Ok(ip) => param.set_ip(ip)?,
+        Err(_) => param.set_host(server_name)?,
+    }
+
+    let mut store = X509StoreBuilder::new()?;
+    store.add_cert(cert.clone())?;
+    store.set_flags(X509VerifyFlags::PARTIAL_CHAIN)?;
+    store.set_param(&param)?;
+    let store = store.build();
+
+    let chain: Stack<X509> = Stack::new()?;
+    let mut store_ctx = X509StoreContext::new()?;
+    store_ctx.init(&store, cert, &chain, |store_ctx| store_ctx.verify_cert())
+}
+
+impl VkTlsBackend {
+    /// Every client certificate and key format is supported.
+    pub fn check_client_file(_path: &str, _bytes: &[u8]) -> Result<()> {
+        Ok(())
+    }
+
+    /// Encrypted client keys are supported.
+    pub fn check_key_passphrase() -> Result<()> {
+        Ok(())
+    }
+
+    pub fn load(
+        settings: &VkConnectionSettings,
+        server_ca_paths: &[String],
+        ca_dir_files: &[(String, Vec<u8>)],
+        files: &mut Vec<(String, Vec<u8>)>,
+    ) -> Result<VkTlsBackend> {
+        let mut ca_certs = HashMap::new();
+        for path in settings.ca_cert_path.iter().chain(server_ca_paths) {
+            if !ca_certs.contains_key(path) {
+                ca_certs.insert(path.clone(), load_ca_cert(path, files)?);
+            }
+        }
+
+        let backend = VkTlsBackend {
+            policy: settings.tls_policy.clone(),
+            ca_certs,
+            ca_dir_certs: parse_ca_cert_dir(ca_dir_files)?,
+            identity: load_identity(settings, files)?,
+        };
+
+        // Building a connector validates the whole material, e.g., that the
+        // client key matches the client certificate.
+        for path in backend.ca_certs.keys() {
+            backend.connector(Some(path), true)?;
+        }
+        backend.connector(None, true)?;
+
+        Ok(backend)
+    }
+
+    /// Builds the TLS connector with the CA certificate in `ca_cert_path`. A
+    /// CA certificate that was not loaded with the material, which happens
+    /// when a server is added after the material was loaded, is read now.
+    /// When `verify_hostname` is false, the certificate chain is still
+    /// verified, but not the name of the server.
+    fn connector(
+        &self,
+        ca_cert_path: Option<&String>,
+        verify_hostname: bool,
+    ) -> Result<TlsConnector> {
+        let mut tls_builder = TlsConnector::builder();
+
+        tls_builder.min_protocol_version(Some(self.policy.min_version.clone().into()));
+        tls_builder.disable_built_in_roots(!self.policy.use_system_ca);
+
+        if self.policy.skip_verify {
+            tls_builder.danger_accept_invalid_certs(true);
+            tls_builder.danger_accept_invalid_hostnames(true);
+        } else if !verify_hostname {
+            tls_builder.danger_accept_invalid_hostnames(true);
+        }
+
+        for ca_cert in &self.ca_dir_certs {
+            tls_builder.add_root_certificate(ca_cert.clone());
+        }
+
+        if let Some(path) = ca_cert_path {
+            match self.ca_certs.get(path) {
+                Some(ca_cert) => tls_builder.add_root_certificate(ca_cert.clone()),
+                None => tls_builder.add_root_certificate(load_ca_cert(path, &mut Vec::new())?),
+            };
+        }
+
+        if let Some(identity) = &self.identity {
+            tls_builder.identity(identity.clone());
+        }
+
+        let tls_connector = handle_tls_error!(
+            tls_builder.build(),
+            "failed to setup TLS connection".to_string()
+        );
+        Ok(tls_connector)
+    }
+
+    pub async fn configure(
+        &self,
+        settings: &VkConnectionSettings,
+        _server_url: &Url,
+        ldap_conn_settings: LdapConnSettings,
+    ) -> Result<LdapConnSettings> {
+        let policy = &settings.tls_policy;
+        let tls_connector =
+            self.connector(settings.ca_cert_path.as_ref(), policy.server_name.is_none())?;
+        Ok(ldap_conn_settings.set_connector(tls_connector))
+    }
+
+    /// Returns the error of a failed connection. The pinned keys are checked
+    /// by `verify_peer` once connected, so no handshake error is a pin
+    /// mismatch.
+    pub fn connection_error(err: LdapError) -> VkLdapError {
+        VkLdapError::LdapConnectionError(err)
+    }
+
+    /// Verifies the certificate of the LDAP connection `ldap` to the server at
+    /// `server_url`: that it is valid for the overridden server name, if any,
+    /// and that its public key matches one of the pinned keys, if any.
+    /// native-tls can only verify the certificates against the host of the
+    /// URL, so the connections with an overridden name skip that check in the
+    /// handshake, and the name is checked here instead, before the connection
+    /// is used.
+    pub async fn verify_peer(
+        &self,
+        settings: &VkConnectionSettings,
+        server_url: &Url,
+        ldap: &mut Ldap,
+    ) -> Result<()> {
+        let policy = &settings.tls_policy;
+        let server_name = policy.server_name.as_ref().filter(|_| !policy.skip_verify);
+        if server_name.is_none() && policy.pinned_keys.is_empty() {
+            return Ok(());
+        }
+
+        let msg = format!("failed to verify the certificate of {server_url}");
+        let der = match ldap.get_peer_certificate().await {
+            Ok(Some(der)) => der,
+            Ok(None) => {
+                return Err(VkLdapError::TLSError(
+                    msg,
+                    "no certificate was sent".to_string(),
+                ));
+            }
+            Err(err) => return Err(VkLdapError::LdapConnectionError(err)),
+        };
+        let cert = handle_tls_error!(X509::from_der(&der), msg);
+
+        if let Some(server_name) = server_name {
+            if !handle_tls_error!(is_valid_for_name(&cert, server_name), msg) {
+                return Err(VkLdapError::TLSError(
+                    msg,
+                    format!("the certificate is not valid for '{server_name}'"),
+                ));
+            }
+        }
+
+        if policy.pinned_keys.is_empty() {
+            return Ok(());
+        }
+        let pin = handle_tls_error!(certificate_pin(&cert), msg);
+        check_public_key_pin(server_url.as_str(), pin, &policy.pinned_keys)
+    }
+}
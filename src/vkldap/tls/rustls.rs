@@ -0,0 +1,407 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use ldap3::{Ldap, LdapConnSettings, LdapError};
+use log::warn;
+use rustls::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+use rustls::{
+    Certificate, CertificateError, ClientConfig, PrivateKey, RootCertStore, ServerName,
+    SupportedProtocolVersion,
+};
+use rustls_pemfile::Item;
+use url::Url;
+
+use crate::configs::LdapTlsVersion;
+use crate::vkldap::Result;
+use crate::vkldap::errors::VkLdapError;
+use crate::vkldap::settings::{VkConnectionSettings, VkTlsPolicy};
+use crate::{handle_io_error, handle_tls_error};
+
+use super::{
+    check_public_key_pin, is_legacy_encrypted, is_pkcs12_bundle, pem_label, public_key_pin,
+    read_file,
+};
+
+/// The TLS files parsed for rustls.
+pub(super) struct VkTlsBackend {
+    skip_verify: bool,
+    // The trusted CA certificates that apply to every server: the ones of the
+    // system, if enabled, and of the CA cert directory.
+    roots: RootCertStore,
+    ca_certs: HashMap<String, Vec<Certificate>>,
+    identity: Option<(Vec<Certificate>, PrivateKey)>,
+}
+
+fn parse_certs(bytes: &[u8], what: &str) -> Result<Vec<Certificate>> {
+    let certs = handle_io_error!(
+        rustls_pemfile::certs(&mut &bytes[..]),
+        format!("failed to parse {what}")
+    );
+    if certs.is_empty() {
+        return Err(VkLdapError::TLSError(
+            format!("failed to parse {what}"),
+            "no PEM certificate found".to_string(),
+        ));
+    }
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+fn load_ca_certs(path: &str, files: &mut Vec<(String, Vec<u8>)>) -> Result<Vec<Certificate>> {
+    let bytes = read_file(path, "CA cert", files)?;
+    parse_certs(&bytes, "CA certificate")
+}
+
+fn add_roots(roots: &mut RootCertStore, certs: &[Certificate], path: &str) -> Result<()> {
+    for cert in certs {
+        handle_tls_error!(
+            roots.add(cert),
+            format!("failed to load CA certificates of {path}")
+        );
+    }
+    Ok(())
+}
+
+fn unsupported(what: &str, reason: &str) -> VkLdapError {
+    VkLdapError::TLSError(
+        format!("failed to load {what}"),
+        format!(
+            "{reason} are not supported with the rustls TLS backend, convert the file to an \
+             unencrypted PEM file, e.g., with 'openssl pkcs12' or 'openssl pkey'"
+        ),
+    )
+}
+
+fn is_encrypted_key(bytes: &[u8]) -> bool {
+    pem_label(bytes) == Some("ENCRYPTED PRIVATE KEY") || is_legacy_encrypted(bytes)
+}
+
+/// Parses the client key, in the PKCS#8, PKCS#1 (RSA) or SEC1 (EC) PEM
+/// formats. Encrypted keys are not supported.
+fn parse_client_key(bytes: &[u8]) -> Result<PrivateKey> {
+    if is_encrypted_key(bytes) {
+        return Err(unsupported("client key", "encrypted keys"));
+    }
+
+    let mut reader = bytes;
+    loop {
+        let item = handle_io_error!(
+            rustls_pemfile::read_one(&mut reader),
+            "failed to parse client key".to_string()
+        );
+        match item {
+            Some(Item::PKCS8Key(key)) | Some(Item::RSAKey(key)) | Some(Item::ECKey(key)) => {
+                return Ok(PrivateKey(key));
+            }
+            Some(_) => continue,
+            None => {
+                return Err(VkLdapError::TLSError(
+                    "failed to parse client key".to_string(),
+                    "no PEM private key found".to_string(),
+                ));
+            }
+        }
+    }
+}
+
+fn load_identity(
+    settings: &VkConnectionSettings,
+    files: &mut Vec<(String, Vec<u8>)>,
+) -> Result<Option<(Vec<Certificate>, PrivateKey)>> {
+    let Some(cert_path) = &settings.client_cert_path else {
+        return Ok(None);
+    };
+
+    let cert_bytes = read_file(cert_path, "client certificate", files)?;
+    if is_pkcs12_bundle(cert_path, &cert_bytes) {
+        return Err(unsupported("client certificate", "PKCS#12 bundles"));
+    }
+
+    let Some(key_path) = &settings.client_key_path else {
+        return Err(VkLdapError::NoTLSKeyPathSet);
+    };
+
+    let key_bytes = read_file(key_path, "client key", files)?;
+    let certs = parse_certs(&cert_bytes, "client certificate")?;
+    let key = parse_client_key(&key_bytes)?;
+    Ok(Some((certs, key)))
+}
+
+/// The handshake error of a server whose public key is not pinned. It is
+/// mapped back to `VkLdapError::TLSPinMismatch` when the connection fails.
+#[derive(Debug)]
+struct PinMismatch {
+    server_url: String,
+    pin: String,
+}
+
+impl fmt::Display for PinMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the public key of server {} (sha256//{}) is not pinned",
+            self.server_url, self.pin
+        )
+    }
+}
+
+impl std::error::Error for PinMismatch {}
+
+/// Verifies the server certificates with the trusted CA certificates, against
+/// the overridden server name, if any, and checks the pinned keys, if any.
+struct VkServerCertVerifier {
+    webpki: WebPkiVerifier,
+    server_url: String,
+    server_name: Option<ServerName>,
+    pinned_keys: Vec<String>,
+    skip_verify: bool,
+}
+
+impl ServerCertVerifier for VkServerCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        if !self.skip_verify {
+            let server_name = self.server_name.as_ref().unwrap_or(server_name);
+            self.webpki.verify_server_cert(
+                end_entity,
+                intermediates,
+                server_name,
+                scts,
+                ocsp_response,
+                now,
+            )?;
+        }
+
+        if !self.pinned_keys.is_empty() {
+            let (_, cert) = x509_parser::parse_x509_certificate(&end_entity.0)
+                .map_err(|err| rustls::Error::General(err.to_string()))?;
+            let pin = public_key_pin(cert.public_key().raw);
+            if let Err(VkLdapError::TLSPinMismatch(server_url, pin)) =
+                check_public_key_pin(&self.server_url, pin, &self.pinned_keys)
+            {
+                return Err(rustls::Error::InvalidCertificate(CertificateError::Other(
+                    Arc::new(PinMismatch { server_url, pin }),
+                )));
+            }
+        }
+
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+impl VkTlsBackend {
+    /// Rejects the PKCS#12 bundles and the encrypted keys, which rustls
+    /// cannot load.
+    pub fn check_client_file(path: &str, bytes: &[u8]) -> Result<()> {
+        if is_pkcs12_bundle(path, bytes) {
+            return Err(unsupported(path, "PKCS#12 bundles"));
+        }
+        if is_encrypted_key(bytes) {
+            return Err(unsupported(path, "encrypted keys"));
+        }
+        Ok(())
+    }
+
+    /// Rejects any passphrase, since encrypted keys cannot be loaded.
+    pub fn check_key_passphrase() -> Result<()> {
+        Err(VkLdapError::TLSError(
+            "ldap.tls_key_passphrase cannot be used".to_string(),
+            "encrypted keys are not supported with the rustls TLS backend".to_string(),
+        ))
+    }
+
+    pub fn load(
+        settings: &VkConnectionSettings,
+        server_ca_paths: &[String],
+        ca_dir_files: &[(String, Vec<u8>)],
+        files: &mut Vec<(String, Vec<u8>)>,
+    ) -> Result<VkTlsBackend> {
+        let policy = &settings.tls_policy;
+
+        // rustls only implements TLS 1.2 and TLS 1.3.
+        if matches!(
+            policy.min_version,
+            LdapTlsVersion::Tls10 | LdapTlsVersion::Tls11
+        ) {
+            warn!(
+                "ldap.tls_min_version is ignored: the rustls TLS backend always requires TLS 1.2 \
+                 or later"
+            );
+        }
+
+        let mut roots = RootCertStore::empty();
+        if policy.use_system_ca {
+            let system_certs = handle_io_error!(
+                rustls_native_certs::load_native_certs(),
+                "failed to load the system CA certificates".to_string()
+            );
+            for cert in system_certs {
+                if let Err(err) = roots.add(&Certificate(cert.0)) {
+                    warn!("skipping invalid system CA certificate: {err}");
+                }
+            }
+        }
+        for (path, bytes) in ca_dir_files {
+            add_roots(&mut roots, &parse_certs(bytes, "CA certificate")?, path)?;
+        }
+
+        let mut ca_certs = HashMap::new();
+        for path in settings.ca_cert_path.iter().chain(server_ca_paths) {
+            if !ca_certs.contains_key(path) {
+                ca_certs.insert(path.clone(), load_ca_certs(path, files)?);
+            }
+        }
+
+        let backend = VkTlsBackend {
+            skip_verify: policy.skip_verify,
+            roots,
+            ca_certs,
+            identity: load_identity(settings, files)?,
+        };
+
+        // Building a client config validates the whole material, e.g., that
+        // the client key is supported.
+        for path in backend.ca_certs.keys() {
+            backend.client_config(Some(path), policy, "")?;
+        }
+        backend.client_config(None, policy, "")?;
+
+        Ok(backend)
+    }
+
+    /// Builds the TLS client config with the CA certificate in `ca_cert_path`.
+    /// A CA certificate that was not loaded with the material, which happens
+    /// when a server is added after the material was loaded, is read now.
+    fn client_config(
+        &self,
+        ca_cert_path: Option<&String>,
+        policy: &VkTlsPolicy,
+        server_url: &str,
+    ) -> Result<Arc<ClientConfig>> {
+        let mut roots = self.roots.clone();
+        if let Some(path) = ca_cert_path {
+            match self.ca_certs.get(path) {
+                Some(certs) => add_roots(&mut roots, certs, path)?,
+                None => add_roots(&mut roots, &load_ca_certs(path, &mut Vec::new())?, path)?,
+            }
+        }
+
+        let server_name = match &policy.server_name {
+            Some(name) => Some(handle_tls_error!(
+                ServerName::try_from(name.as_str()),
+                format!("invalid TLS server name '{name}'")
+            )),
+            None => None,
+        };
+
+        let verifier = VkServerCertVerifier {
+            webpki: WebPkiVerifier::new(roots, None),
+            server_url: server_url.to_string(),
+            server_name,
+            pinned_keys: policy.pinned_keys.clone(),
+            skip_verify: self.skip_verify,
+        };
+
+        let versions: &[&SupportedProtocolVersion] = match policy.min_version {
+            LdapTlsVersion::Tls13 => &[&rustls::version::TLS13],
+            _ => rustls::DEFAULT_VERSIONS,
+        };
+        let builder = handle_tls_error!(
+            ClientConfig::builder()
+                .with_safe_default_cipher_suites()
+                .with_safe_default_kx_groups()
+                .with_protocol_versions(versions),
+            "unsupported TLS versions".to_string()
+        )
+        .with_custom_certificate_verifier(Arc::new(verifier));
+
+        let config = match &self.identity {
+            Some((certs, key)) => handle_tls_error!(
+                builder.with_client_auth_cert(certs.clone(), key.clone()),
+                "failed to load client certificate".to_string()
+            ),
+            None => builder.with_no_client_auth(),
+        };
+        Ok(Arc::new(config))
+    }
+
+    pub async fn configure(
+        &self,
+        settings: &VkConnectionSettings,
+        server_url: &Url,
+        ldap_conn_settings: LdapConnSettings,
+    ) -> Result<LdapConnSettings> {
+        let config = self.client_config(
+            settings.ca_cert_path.as_ref(),
+            &settings.tls_policy,
+            server_url.as_str(),
+        )?;
+        Ok(ldap_conn_settings.set_config(config))
+    }
+
+    /// Returns the error of a failed connection, which is a pin mismatch when
+    /// `VkServerCertVerifier` rejected the public key of the server.
+    pub fn connection_error(err: LdapError) -> VkLdapError {
+        let pin_mismatch = match &err {
+            LdapError::Io { source } => source
+                .get_ref()
+                .and_then(|inner| inner.downcast_ref::<rustls::Error>())
+                .and_then(|tls_err| match tls_err {
+                    rustls::Error::InvalidCertificate(CertificateError::Other(other)) => {
+                        other.downcast_ref::<PinMismatch>()
+                    }
+                    _ => None,
+                }),
+            _ => None,
+        };
+        match pin_mismatch {
+            Some(PinMismatch { server_url, pin }) => {
+                VkLdapError::TLSPinMismatch(server_url.clone(), pin.clone())
+            }
+            None => VkLdapError::LdapConnectionError(err),
+        }
+    }
+
+    /// Does nothing, the certificates are fully verified in the handshake by
+    /// `VkServerCertVerifier`.
+    pub async fn verify_peer(
+        &self,
+        _settings: &VkConnectionSettings,
+        _server_url: &Url,
+        _ldap: &mut Ldap,
+    ) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connection_error_pin_mismatch() {
+        let tls_err =
+            rustls::Error::InvalidCertificate(CertificateError::Other(Arc::new(PinMismatch {
+                server_url: "ldaps://ldap".to_string(),
+                pin: "abc".to_string(),
+            })));
+        let source = std::io::Error::new(std::io::ErrorKind::InvalidData, tls_err);
+        let err = VkTlsBackend::connection_error(LdapError::Io { source });
+        assert!(matches!(
+            err,
+            VkLdapError::TLSPinMismatch(url, pin) if url == "ldaps://ldap" && pin == "abc"
+        ));
+
+        let source = std::io::Error::new(std::io::ErrorKind::InvalidData, "handshake failed");
+        let err = VkTlsBackend::connection_error(LdapError::Io { source });
+        assert!(matches!(err, VkLdapError::LdapConnectionError(_)));
+    }
+}
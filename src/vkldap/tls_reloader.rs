@@ -0,0 +1,73 @@
+use lazy_static::lazy_static;
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use log::{debug, error};
+
+use super::context;
+use super::scheduler::BackgroundLoop;
+
+async fn tls_reloader_loop() {
+    debug!("initiating TLS reloader loop");
+
+    loop {
+        let interval = Duration::from_millis(TLS_RELOADER.interval_ms.load(Ordering::Relaxed));
+
+        // A zero interval disables the checks until the interval changes.
+        let interrupted = TLS_RELOADER
+            .background_loop
+            .sleep((!interval.is_zero()).then_some(interval))
+            .await;
+
+        if TLS_RELOADER.background_loop.should_stop() {
+            debug!("exiting TLS reloader loop");
+            break;
+        }
+
+        if interrupted {
+            continue;
+        }
+
+        if let Err(err) = context::reload_tls_material(false).await {
+            error!("failed to reload the TLS files, keeping the current ones: {err}");
+        }
+    }
+}
+
+struct TlsReloader {
+    background_loop: BackgroundLoop,
+    interval_ms: AtomicU64,
+}
+
+impl TlsReloader {
+    fn new() -> TlsReloader {
+        TlsReloader {
+            background_loop: BackgroundLoop::new("TLS reloader"),
+            interval_ms: AtomicU64::new(0),
+        }
+    }
+}
+
+lazy_static! {
+    static ref TLS_RELOADER: TlsReloader = TlsReloader::new();
+}
+
+pub fn start_tls_reloader() {
+    TLS_RELOADER.background_loop.start(tls_reloader_loop());
+}
+
+pub fn shutdown_tls_reloader() {
+    if let Err(err) = TLS_RELOADER.background_loop.shutdown() {
+        error!("{err}");
+    }
+}
+
+/// Sets the interval between the checks for changes of the TLS files on disk.
+/// A zero interval disables the checks.
+pub fn set_tls_reload_interval(interval: Duration) {
+    TLS_RELOADER
+        .interval_ms
+        .store(interval.as_millis() as u64, Ordering::Relaxed);
+    TLS_RELOADER.background_loop.notify_settings_changed();
+}
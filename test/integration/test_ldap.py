@@ -1,12 +1,28 @@
+import hashlib
+import hmac
+import json
+import os
+import socket
+import subprocess
 import time
-from unittest import TestCase
+from unittest import TestCase, skipIf, skipUnless
 from threading import Thread
 from urllib.parse import urlparse
 
-from valkey.exceptions import AuthenticationError, ConnectionError
+from valkey.exceptions import AuthenticationError, ConnectionError, ResponseError
 import valkey
 
-from util import DOCKER_SERVICES, LdapTestCase, parse_valkey_info_section
+from util import (
+    DOCKER_SERVICES,
+    LdapTestCase,
+    find_acl_log_entries,
+    get_ldap_servers,
+    parse_valkey_info_section,
+    wait_for_idle_pools,
+)
+
+GSSAPI_TESTS = bool(os.environ.get("VALKEY_LDAP_GSSAPI_TESTS"))
+RUSTLS_TESTS = bool(os.environ.get("VALKEY_LDAP_RUSTLS_TESTS"))
 
 
 class LdapModuleTest(TestCase):
@@ -69,6 +85,355 @@ class LdapModuleBindTest(LdapTestCase):
         with self.assertRaises(AuthenticationError) as ctx:
             self.vk.execute_command("AUTH", "user1", "wrongpass")
 
+    def test_ldap_wrong_pass_acl_log(self):
+        self.vk.execute_command("ACL", "LOG", "RESET")
+        with self.assertRaises(AuthenticationError) as ctx:
+            self.vk.execute_command("AUTH", "user1", "wrongpass")
+
+        # The module falls back to the password of the ACL user, so only
+        # Valkey logs the failure.
+        entries = find_acl_log_entries(self.vk, "AUTH")
+        self.assertEqual(len(self.vk.execute_command("ACL", "LOG")), 1)
+        self.assertEqual(len(entries), 1)
+        self.assertEqual(entries[0]["count"], 1)
+        self.assertEqual(entries[0]["username"], "user1")
+        self.assertEqual(entries[0]["reason"], "auth")
+        self.assertEqual(len(find_acl_log_entries(self.vk, "ldap:invalid-credentials")), 0)
+
+    def test_ldap_wrong_pass_acl_log_without_fallback(self):
+        self.vk.execute_command("CONFIG", "SET", "ldap.acl_password_fallback", "no")
+        self.vk.execute_command("ACL", "LOG", "RESET")
+        try:
+            with self.assertRaises(AuthenticationError) as ctx:
+                self.vk.execute_command("AUTH", "user1", "pass")
+        finally:
+            self.vk.execute_command(
+                "CONFIG", "SET", "ldap.acl_password_fallback", "yes"
+            )
+
+        # The password of the ACL user is not checked, so only the module logs
+        # the failure.
+        entries = find_acl_log_entries(self.vk, "ldap:invalid-credentials")
+        self.assertEqual(len(self.vk.execute_command("ACL", "LOG")), 1)
+        self.assertEqual(len(entries), 1)
+        self.assertEqual(entries[0]["count"], 1)
+        self.assertEqual(entries[0]["username"], "user1")
+
+    def test_ldap_audit_log(self):
+        audit_log = "/tmp/ldap-audit.log"
+        audit_key = "/tmp/ldap-audit.key"
+        DOCKER_SERVICES.remove_file("valkey", audit_log)
+        DOCKER_SERVICES.write_file("valkey", audit_key, "audit-secret\n")
+        self.vk.execute_command("CONFIG", "SET", "ldap.audit_log_key_file", audit_key)
+        self.vk.execute_command("CONFIG", "SET", "ldap.audit_log_file", audit_log)
+        self.vk.execute_command("CONFIG", "SET", "ldap.audit_log_events", "all")
+
+        self.vk.execute_command("AUTH", "user1", "user1@123")
+        with self.assertRaises(AuthenticationError):
+            self.vk.execute_command("AUTH", "user1", "wrongpass")
+        # A password sent as the username must not end up in the log.
+        with self.assertRaises(AuthenticationError):
+            self.vk.execute_command("AUTH", "user1@123", "user1@123")
+
+        time.sleep(1)
+        self.vk.execute_command("CONFIG", "SET", "ldap.audit_log_file", "")
+        self.vk.execute_command("CONFIG", "SET", "ldap.audit_log_key_file", "")
+
+        content = DOCKER_SERVICES.read_file("valkey", audit_log)
+        self.assertNotIn("user1@123", content)
+        self.assertNotIn("wrongpass", content)
+
+        lines = content.splitlines()
+        self.assertEqual(len(lines), 3)
+        records = [json.loads(line) for line in lines]
+
+        self.assertEqual(records[0]["event"], "auth")
+        self.assertEqual(records[0]["username"], "user1")
+        self.assertEqual(records[0]["user_dn"], "cn=user1,OU=devops,DC=valkey,DC=io")
+        self.assertEqual(records[0]["outcome"], "success")
+        self.assertIsNone(records[0]["reason"])
+        self.assertIsNotNone(records[0]["server"])
+        self.assertIsNotNone(records[0]["client_addr"])
+        self.assertEqual(records[1]["outcome"], "failure")
+        self.assertEqual(records[1]["reason"], "invalid-credentials")
+        self.assertEqual(records[2]["username"], "[REDACTED]")
+
+        # Each record hash is the HMAC of the record and of the hash of the
+        # previous one.
+        prev_hash = "0" * 64
+        for line, record in zip(lines, records):
+            payload = line[: line.rindex(',"hash":')]
+            expected = hmac.new(
+                b"audit-secret", (prev_hash + payload).encode("utf-8"), hashlib.sha256
+            )
+            self.assertEqual(record["hash"], expected.hexdigest())
+            prev_hash = record["hash"]
+
+    def test_ldap_audit_log_chain_continues_after_rotation(self):
+        audit_log = "/tmp/ldap-audit-rotation.log"
+        DOCKER_SERVICES.remove_file("valkey", audit_log)
+        DOCKER_SERVICES.remove_file("valkey", f"{audit_log}.1")
+        DOCKER_SERVICES.remove_file("valkey", f"{audit_log}.2")
+        self.vk.execute_command("CONFIG", "SET", "ldap.audit_log_file", audit_log)
+        self.vk.execute_command("CONFIG", "SET", "ldap.audit_log_max_size", "10")
+
+        self.vk.execute_command("AUTH", "user1", "user1@123")
+        self.vk.execute_command("AUTH", "user1", "user1@123")
+
+        # Re-opening the file, as on a restart, continues the same chain.
+        time.sleep(1)
+        self.vk.execute_command("CONFIG", "SET", "ldap.audit_log_file", "")
+        self.vk.execute_command("CONFIG", "SET", "ldap.audit_log_file", audit_log)
+        self.vk.execute_command("AUTH", "user1", "user1@123")
+
+        time.sleep(1)
+        self.vk.execute_command("CONFIG", "SET", "ldap.audit_log_file", "")
+        self.vk.execute_command("CONFIG", "SET", "ldap.audit_log_max_size", "104857600")
+
+        rotated = DOCKER_SERVICES.read_file("valkey", f"{audit_log}.1").splitlines()
+        current = DOCKER_SERVICES.read_file("valkey", audit_log).splitlines()
+        self.assertEqual(len(rotated), 1)
+        self.assertEqual(len(current), 1)
+
+        prev_hash = json.loads(rotated[0])["hash"]
+        payload = current[0][: current[0].rindex(',"hash":')]
+        expected = hashlib.sha256((prev_hash + payload).encode("utf-8"))
+        self.assertEqual(json.loads(current[0])["hash"], expected.hexdigest())
+
+    def test_ldap_audit_log_failures_only(self):
+        audit_log = "/tmp/ldap-audit-failures.log"
+        DOCKER_SERVICES.remove_file("valkey", audit_log)
+        self.vk.execute_command("CONFIG", "SET", "ldap.audit_log_file", audit_log)
+        self.vk.execute_command("CONFIG", "SET", "ldap.audit_log_events", "failures")
+
+        self.vk.execute_command("AUTH", "user1", "user1@123")
+        with self.assertRaises(AuthenticationError):
+            self.vk.execute_command("AUTH", "user1", "wrongpass")
+
+        time.sleep(1)
+        self.vk.execute_command("CONFIG", "SET", "ldap.audit_log_file", "")
+        self.vk.execute_command("CONFIG", "SET", "ldap.audit_log_events", "all")
+
+        records = [
+            json.loads(line)
+            for line in DOCKER_SERVICES.read_file("valkey", audit_log).splitlines()
+        ]
+        self.assertEqual(len(records), 1)
+        self.assertEqual(records[0]["outcome"], "failure")
+
+    def test_ldap_auth_events_stream(self):
+        self.vk.execute_command("DEL", "ldap:auth-events")
+        self.vk.execute_command(
+            "CONFIG", "SET", "ldap.auth_events_stream", "ldap:auth-events"
+        )
+
+        self.vk.execute_command("AUTH", "user1", "user1@123")
+        with self.assertRaises(AuthenticationError):
+            self.vk.execute_command("AUTH", "user1", "wrongpass")
+        # The events of clients that selected another DB go to the same stream.
+        vk_db1 = valkey.Valkey(host="localhost", port=6379, db=1)
+        vk_db1.execute_command("AUTH", "user1", "user1@123")
+        vk_db1.close()
+
+        self.vk.execute_command("CONFIG", "SET", "ldap.auth_events_stream", "")
+
+        entries = self.vk.xrange("ldap:auth-events")
+        self.assertEqual(len(entries), 3)
+        success = entries[0][1]
+        self.assertEqual(success[b"user"], b"user1")
+        self.assertEqual(success[b"outcome"], b"success")
+        self.assertEqual(success[b"reason"], b"")
+        self.assertIn(b"latency_ms", success)
+        failure = entries[1][1]
+        self.assertEqual(failure[b"outcome"], b"failure")
+        self.assertEqual(failure[b"reason"], b"invalid-credentials")
+
+    def test_ldap_auth_events_channel(self):
+        pubsub = valkey.Valkey(host="localhost", port=6379, db=0).pubsub()
+        pubsub.subscribe("ldap:auth-events")
+        self.assertEqual(pubsub.get_message(timeout=1)["type"], "subscribe")
+
+        self.vk.execute_command(
+            "CONFIG", "SET", "ldap.auth_events_channel", "ldap:auth-events"
+        )
+        with self.assertRaises(AuthenticationError):
+            self.vk.execute_command("AUTH", "user1", "wrongpass")
+        self.vk.execute_command("CONFIG", "SET", "ldap.auth_events_channel", "")
+
+        message = pubsub.get_message(timeout=5)
+        pubsub.close()
+        event = json.loads(message["data"])
+        self.assertEqual(event["username"], "user1")
+        self.assertEqual(event["outcome"], "failure")
+        self.assertEqual(event["reason"], "invalid-credentials")
+        self.assertNotIn("wrongpass", message["data"].decode("utf-8"))
+
+    def test_ldap_auth_stats(self):
+        def get_auth_stats():
+            info = self.vk.execute_command("INFO", "ldap_auth").decode("utf-8")
+            return parse_valkey_info_section(info)
+
+        stats = get_auth_stats()
+        successes = int(stats["ldap_auth_successes"])
+        failures = stats.get("ldap_auth_failures", {})
+        invalid_credentials = int(failures.get("invalid-credentials", "0"))
+
+        self.vk.execute_command("AUTH", "user1", "user1@123")
+        with self.assertRaises(AuthenticationError):
+            self.vk.execute_command("AUTH", "user1", "wrongpass")
+
+        stats = get_auth_stats()
+        self.assertEqual(int(stats["ldap_auth_successes"]), successes + 1)
+        self.assertEqual(
+            int(stats["ldap_auth_failures"]["invalid-credentials"]),
+            invalid_credentials + 1,
+        )
+
+    def test_ldap_auth_password_policy_stats(self):
+        # The password policy of the test server does not expire passwords, so
+        # no warnings are counted.
+        self.vk.execute_command("AUTH", "user1", "user1@123")
+
+        info = self.vk.execute_command("INFO", "ldap_auth").decode("utf-8")
+        stats = parse_valkey_info_section(info)
+        self.assertEqual(stats["ldap_auth_password_expiration_warnings"], "0")
+        self.assertEqual(stats["ldap_auth_grace_logins"], "0")
+
+    def test_ldap_passwd(self):
+        # Use a single server so that the password changes on every server used
+        # in the authentication.
+        self.vk.execute_command("CONFIG", "SET", "ldap.servers", "ldap://ldap")
+
+        self.vk.execute_command("AUTH", "user1", "user1@123")
+        self.vk.execute_command("LDAP.PASSWD", "user1@123", "user1@456")
+        try:
+            with self.assertRaises(AuthenticationError):
+                self.vk.execute_command("AUTH", "user1", "user1@123")
+            self.vk.execute_command("AUTH", "user1", "user1@456")
+        finally:
+            self.vk.execute_command("LDAP.PASSWD", "user1", "user1@456", "user1@123")
+
+        self.vk.execute_command("AUTH", "user1", "user1@123")
+
+    def test_ldap_passwd_must_change(self):
+        self.vk.execute_command("CONFIG", "SET", "ldap.servers", "ldap://ldap")
+        self.vk.execute_command("ACL", "SETUSER", "user4", "ON", "resetpass", "allcommands")
+        try:
+            # The password of user4 was reset, so the server only lets the user
+            # bind to change it.
+            with self.assertRaises(AuthenticationError):
+                self.vk.execute_command("AUTH", "user4", "user4@123")
+
+            self.vk.execute_command("LDAP.PASSWD", "user4", "user4@123", "user4@456")
+            self.vk.execute_command("AUTH", "user4", "user4@456")
+            resp = self.vk.execute_command("ACL", "WHOAMI")
+            self.assertEqual(resp.decode(), "user4")
+        finally:
+            DOCKER_SERVICES.ldap_modify(
+                "ldap",
+                "dn: cn=user4,ou=devops,dc=valkey,dc=io\n"
+                "changetype: modify\n"
+                "replace: userPassword\n"
+                "userPassword: user4@123\n"
+                "-\n"
+                "replace: pwdReset\n"
+                "pwdReset: TRUE\n",
+            )
+            self.vk.execute_command("ACL", "DELUSER", "user4")
+
+    def test_ldap_passwd_wrong_old_password(self):
+        with self.assertRaises(ResponseError):
+            self.vk.execute_command("LDAP.PASSWD", "user1", "wrongpass", "user1@456")
+
+    def test_ldap_passwd_attempts_are_recorded(self):
+        audit_log = "/tmp/ldap-audit-passwd.log"
+        DOCKER_SERVICES.remove_file("valkey", audit_log)
+        self.vk.execute_command("CONFIG", "SET", "ldap.audit_log_file", audit_log)
+        self.vk.execute_command("ACL", "LOG", "RESET")
+
+        with self.assertRaises(ResponseError):
+            self.vk.execute_command("LDAP.PASSWD", "user1", "wrongpass", "user1@456")
+
+        time.sleep(1)
+        self.vk.execute_command("CONFIG", "SET", "ldap.audit_log_file", "")
+
+        entries = find_acl_log_entries(self.vk, "ldap:invalid-credentials")
+        self.assertEqual(len(entries), 1)
+        self.assertEqual(entries[0]["username"], "user1")
+
+        content = DOCKER_SERVICES.read_file("valkey", audit_log)
+        self.assertNotIn("wrongpass", content)
+        self.assertNotIn("user1@456", content)
+        records = [json.loads(line) for line in content.splitlines()]
+        self.assertEqual(len(records), 1)
+        self.assertEqual(records[0]["event"], "passwd")
+        self.assertEqual(records[0]["username"], "user1")
+        self.assertEqual(records[0]["outcome"], "failure")
+        self.assertEqual(records[0]["reason"], "invalid-credentials")
+
+    def test_ldap_passwd_without_ldap_session(self):
+        with self.assertRaises(ResponseError) as ctx:
+            self.vk.execute_command("LDAP.PASSWD", "user1@123", "user1@456")
+        self.assertIn("not authenticated with an LDAP user", str(ctx.exception))
+
+    def test_ldap_passwd_of_another_user(self):
+        self.vk.execute_command("ACL", "SETUSER", "limited", "ON", ">pass", "+ldap.passwd")
+        client = valkey.Valkey(host="localhost", port=6379, db=0)
+        try:
+            client.execute_command("AUTH", "limited", "pass")
+            self.vk.execute_command("ACL", "LOG", "RESET")
+
+            # Only the clients allowed to run ACL SETUSER can change the
+            # password of other users, so the old password is never checked.
+            with self.assertRaises(ResponseError) as ctx:
+                client.execute_command("LDAP.PASSWD", "user1", "user1@123", "user1@456")
+            self.assertIn("NOPERM", str(ctx.exception))
+
+            entries = find_acl_log_entries(self.vk, "ldap.passwd")
+            self.assertEqual(len(entries), 1)
+            self.assertEqual(entries[0]["username"], "limited")
+            self.assertEqual(entries[0]["reason"], "command")
+        finally:
+            client.close()
+            self.vk.execute_command("ACL", "DELUSER", "limited")
+
+    def test_ldap_passwd_acl_category(self):
+        commands = [c.decode() for c in self.vk.execute_command("ACL", "CAT", "ldap")]
+        self.assertIn("ldap.passwd", commands)
+
+    def test_ldap_auth_client_disconnects(self):
+        service = DOCKER_SERVICES.pause_service("ldap")
+        service2 = DOCKER_SERVICES.pause_service("ldap-2")
+        try:
+            sock = socket.create_connection(("localhost", 6379))
+            sock.sendall(b"AUTH user1 user1@123\r\n")
+
+            # Wait for the bind to be sent to one of the paused servers.
+            deadline = time.monotonic() + 5
+            servers = get_ldap_servers(self.vk)
+            while sum(server["borrowed_connections"] for server in servers) == 0:
+                self.assertLess(time.monotonic(), deadline)
+                time.sleep(0.1)
+                servers = get_ldap_servers(self.vk)
+            sock.close()
+
+            # The bind is cancelled, and its connection released, long before
+            # the LDAP operation timeout.
+            self._assert_idle_pools(wait_for_idle_pools(self.vk, timeout=3))
+        finally:
+            DOCKER_SERVICES.unpause_service(service)
+            DOCKER_SERVICES.unpause_service(service2)
+
+        for _ in range(20):
+            sock = socket.create_connection(("localhost", 6379))
+            sock.sendall(b"AUTH user1 user1@123\r\n")
+            sock.close()
+
+        # The cancelled authentications must not leak pooled connections.
+        self._assert_idle_pools(wait_for_idle_pools(self.vk))
+        for _ in range(20):
+            self.test_ldap_auth()
+
     def test_ldap_ssl_auth(self):
         self.vk.execute_command("CONFIG", "SET", "ldap.servers", "ldaps://ldap")
         self.vk.execute_command("AUTH", "user1", "user1@123")
@@ -81,6 +446,181 @@ class LdapModuleBindTest(LdapTestCase):
         resp = self.vk.execute_command("ACL", "WHOAMI")
         self.assertTrue(resp.decode() == "user1")
 
+    def test_ldap_ssl_auth_pkcs1_key(self):
+        self.vk.execute_command(
+            "CONFIG",
+            "SET",
+            "ldap.tls_key_path",
+            "/valkey-ldap/valkey-ldap-client.pkcs1.key",
+        )
+        self.test_ldap_ssl_auth()
+
+    @skipIf(RUSTLS_TESTS, "the rustls backend does not support encrypted keys")
+    def test_ldap_ssl_auth_encrypted_key(self):
+        self.vk.execute_command("CONFIG", "SET", "ldap.servers", "ldaps://ldap")
+        self.vk.execute_command(
+            "CONFIG",
+            "SET",
+            "ldap.tls_key_path",
+            "/valkey-ldap/valkey-ldap-client.enc.key",
+        )
+
+        # The key cannot be decrypted without the passphrase.
+        with self.assertRaises(AuthenticationError):
+            self.vk.execute_command("AUTH", "user1", "user1@123")
+
+        self.vk.execute_command("CONFIG", "SET", "ldap.tls_key_passphrase", "valkey")
+        self.test_ldap_ssl_auth()
+
+    @skipIf(RUSTLS_TESTS, "the rustls backend does not support PKCS#12 bundles")
+    def test_ldap_ssl_auth_pkcs12_bundle(self):
+        self.vk.execute_command("CONFIG", "SET", "ldap.tls_key_passphrase", "valkey")
+        self.vk.execute_command(
+            "CONFIG", "SET", "ldap.tls_cert_path", "/valkey-ldap/valkey-ldap-client.p12"
+        )
+        self.vk.execute_command("CONFIG", "SET", "ldap.tls_key_path", "")
+        self.test_ldap_ssl_auth()
+
+    @skipUnless(RUSTLS_TESTS, "requires the rustls backend, set VALKEY_LDAP_RUSTLS_TESTS")
+    def test_ldap_rustls_rejects_encrypted_keys(self):
+        with self.assertRaises(ResponseError) as ctx:
+            self.vk.execute_command(
+                "CONFIG",
+                "SET",
+                "ldap.tls_key_path",
+                "/valkey-ldap/valkey-ldap-client.enc.key",
+            )
+        self.assertIn("not supported with the rustls TLS backend", str(ctx.exception))
+
+        with self.assertRaises(ResponseError) as ctx:
+            self.vk.execute_command(
+                "CONFIG", "SET", "ldap.tls_cert_path", "/valkey-ldap/valkey-ldap-client.p12"
+            )
+        self.assertIn("not supported with the rustls TLS backend", str(ctx.exception))
+
+        with self.assertRaises(ResponseError):
+            self.vk.execute_command("CONFIG", "SET", "ldap.tls_key_passphrase", "valkey")
+
+        # The previous files are kept.
+        self.test_ldap_ssl_auth()
+
+    def test_ldap_tls_policy(self):
+        self.vk.execute_command("CONFIG", "SET", "ldap.tls_min_version", "tls1.2")
+        self.vk.execute_command("CONFIG", "SET", "ldap.tls_use_system_ca", "no")
+        self.test_ldap_ssl_auth()
+        self.test_ldap_tls_auth()
+
+        self.vk.execute_command("CONFIG", "SET", "ldap.tls_min_version", "tls1.3")
+        self.test_ldap_ssl_auth()
+
+    def test_ldap_tls_ca_cert_dir(self):
+        self.vk.execute_command("CONFIG", "SET", "ldap.servers", "ldaps://ldap")
+        self.vk.execute_command("CONFIG", "SET", "ldap.tls_use_system_ca", "no")
+        self.vk.execute_command("CONFIG", "SET", "ldap.tls_ca_cert_path", "")
+
+        with self.assertRaises(AuthenticationError):
+            self.vk.execute_command("AUTH", "user1", "user1@123")
+
+        self.vk.execute_command("CONFIG", "SET", "ldap.tls_ca_cert_dir", "/valkey-ldap")
+        self.test_ldap_ssl_auth()
+
+    def test_ldap_tls_server_name(self):
+        # The certificate of both LDAP servers is only valid for "ldap".
+        self.vk.execute_command("CONFIG", "SET", "ldap.servers", "ldaps://ldap-2")
+        with self.assertRaises(AuthenticationError):
+            self.vk.execute_command("AUTH", "user1", "user1@123")
+
+        self.vk.execute_command("CONFIG", "SET", "ldap.tls_server_name", "ldap")
+        self.vk.execute_command("AUTH", "user1", "user1@123")
+        resp = self.vk.execute_command("ACL", "WHOAMI")
+        self.assertEqual(resp.decode(), "user1")
+
+        self.vk.execute_command("CONFIG", "SET", "ldap.tls_server_name", "")
+        self.vk.execute_command(
+            "CONFIG", "SET", "ldap.servers", "ldap://ldap-2?starttls=yes&server_name=ldap"
+        )
+        self.vk.execute_command("AUTH", "user1", "user1@123")
+        resp = self.vk.execute_command("ACL", "WHOAMI")
+        self.assertEqual(resp.decode(), "user1")
+
+    def test_ldap_tls_skip_verify(self):
+        self.vk.execute_command("CONFIG", "SET", "ldap.servers", "ldaps://ldap-2")
+        self.vk.execute_command("CONFIG", "SET", "ldap.tls_use_system_ca", "no")
+        self.vk.execute_command("CONFIG", "SET", "ldap.tls_ca_cert_path", "")
+
+        with self.assertRaises(AuthenticationError):
+            self.vk.execute_command("AUTH", "user1", "user1@123")
+
+        self.vk.execute_command("CONFIG", "SET", "ldap.tls_skip_verify", "yes")
+        self.vk.execute_command("AUTH", "user1", "user1@123")
+        resp = self.vk.execute_command("ACL", "WHOAMI")
+        self.assertEqual(resp.decode(), "user1")
+
+    def _server_public_key_pin(self):
+        cert = os.path.join(
+            os.path.dirname(__file__), "../../scripts/docker/certs/valkey-ldap.crt"
+        )
+        return subprocess.check_output(
+            f"openssl x509 -in {cert} -pubkey -noout"
+            " | openssl pkey -pubin -outform der"
+            " | openssl dgst -sha256 -binary | base64",
+            shell=True,
+        ).decode().strip()
+
+    def _get_pin_mismatches(self):
+        info = self.vk.execute_command("INFO", "ldap_tls").decode("utf-8")
+        return int(parse_valkey_info_section(info)["tls_pin_mismatches"])
+
+    def test_ldap_tls_pinned_keys(self):
+        with self.assertRaises(ResponseError):
+            self.vk.execute_command("CONFIG", "SET", "ldap.tls_pinned_keys", "invalid")
+
+        pin = self._server_public_key_pin()
+        self.vk.execute_command("CONFIG", "SET", "ldap.tls_pinned_keys", f"sha256//{pin}")
+        self.test_ldap_ssl_auth()
+
+        mismatches = self._get_pin_mismatches()
+        other_pin = "sha256//" + "A" * 43 + "="
+        self.vk.execute_command("CONFIG", "SET", "ldap.tls_pinned_keys", other_pin)
+        with self.assertRaises(AuthenticationError):
+            self.vk.execute_command("AUTH", "user1", "user1@123")
+        self.assertGreater(self._get_pin_mismatches(), mismatches)
+
+        self.vk.execute_command("CONFIG", "SET", "ldap.tls_pinned_keys", "")
+
+    def test_ldap_tls_pinned_keys_per_server(self):
+        pin = self._server_public_key_pin().replace("+", "%2B")
+        self.vk.execute_command(
+            "CONFIG", "SET", "ldap.servers", f"ldaps://ldap?pin=sha256//{pin}"
+        )
+        self.vk.execute_command("AUTH", "user1", "user1@123")
+        resp = self.vk.execute_command("ACL", "WHOAMI")
+        self.assertEqual(resp.decode(), "user1")
+
+    def test_ldap_reloadtls_command(self):
+        self.vk.execute_command("CONFIG", "SET", "ldap.servers", "ldaps://ldap")
+        self.test_ldap_ssl_auth()
+
+        resp = self.vk.execute_command("LDAP.RELOADTLS")
+        self.assertEqual(resp, b"OK")
+
+        # The connections are rolled to the reloaded files without failing the
+        # authentications in the meantime.
+        for _ in range(10):
+            self.test_ldap_ssl_auth()
+
+        with self.assertRaises(ResponseError):
+            self.vk.execute_command("LDAP.RELOADTLS", "now")
+
+    def test_ldap_tls_reload_interval(self):
+        self.vk.execute_command("CONFIG", "SET", "ldap.tls_reload_interval", "1")
+        self.test_ldap_ssl_auth()
+
+        time.sleep(2)
+        self.test_ldap_ssl_auth()
+
+        self.vk.execute_command("CONFIG", "SET", "ldap.tls_reload_interval", "0")
+
     def test_ldap_disabled(self):
         self.vk.execute_command("CONFIG", "SET", "ldap.servers", "")
         self.vk.execute_command("AUTH", "user1", "pass")
@@ -93,6 +633,101 @@ class LdapModuleBindTest(LdapTestCase):
         self.assertTrue(resp.decode() == "user1")
 
 
+class LdapModuleServerOptionsTest(LdapTestCase):
+    def setUp(self):
+        super(LdapModuleServerOptionsTest, self).setUp()
+
+        self.vk.execute_command("CONFIG", "SET", "ldap.auth_mode", "bind")
+
+        self.vk.execute_command(
+            "CONFIG", "SET", "ldap.bind_dn_suffix", ",OU=devops,DC=valkey,DC=io"
+        )
+
+    def test_ldap_auth_with_server_options(self):
+        self.vk.execute_command(
+            "CONFIG",
+            "SET",
+            "ldap.servers",
+            "ldap://ldap?starttls=yes&ca=/valkey-ldap/valkey-ldap-ca.crt&pool_size=4&priority=1 "
+            "ldaps://ldap-2?timeout=5&weight=10&priority=0",
+        )
+        self.vk.execute_command("AUTH", "user1", "user1@123")
+        resp = self.vk.execute_command("ACL", "WHOAMI")
+        self.assertTrue(resp.decode() == "user1")
+
+    def test_unknown_server_option(self):
+        with self.assertRaises(ResponseError) as ctx:
+            self.vk.execute_command(
+                "CONFIG", "SET", "ldap.servers", "ldap://ldap ldap://ldap-2?foo=bar"
+            )
+        self.assertIn("ldap-2", str(ctx.exception))
+
+    def test_invalid_server_option_value(self):
+        with self.assertRaises(ResponseError) as ctx:
+            self.vk.execute_command(
+                "CONFIG", "SET", "ldap.servers", "ldap://ldap?pool_size=0 ldap://ldap-2"
+            )
+        self.assertIn("ldap://ldap", str(ctx.exception))
+
+    def test_mixed_server_weights(self):
+        with self.assertRaises(ResponseError) as ctx:
+            self.vk.execute_command(
+                "CONFIG", "SET", "ldap.servers", "ldap://ldap?weight=5 ldap://ldap-2"
+            )
+        self.assertIn("priority 0", str(ctx.exception))
+
+        # The weights only need to match within the same priority.
+        self.vk.execute_command(
+            "CONFIG", "SET", "ldap.servers", "ldap://ldap?weight=5 ldap://ldap-2?priority=1"
+        )
+
+
+class LdapModuleUnixSocketTest(LdapTestCase):
+    LDAPI_URL = "ldapi://%2Fldapi%2Fldapi"
+
+    def setUp(self):
+        super(LdapModuleUnixSocketTest, self).setUp()
+
+        self.vk.execute_command("CONFIG", "SET", "ldap.servers", self.LDAPI_URL)
+        self.vk.execute_command("CONFIG", "SET", "ldap.auth_mode", "bind")
+        self.vk.execute_command(
+            "CONFIG", "SET", "ldap.bind_dn_suffix", ",OU=devops,DC=valkey,DC=io"
+        )
+
+    def test_ldap_auth(self):
+        self.vk.execute_command("AUTH", "user1", "user1@123")
+        resp = self.vk.execute_command("ACL", "WHOAMI")
+        self.assertTrue(resp.decode() == "user1")
+
+    def test_ldap_auth_ignores_tls_configs(self):
+        self.vk.execute_command("CONFIG", "SET", "ldap.use_starttls", "yes")
+        self.vk.execute_command(
+            "CONFIG", "SET", "ldap.servers", f"{self.LDAPI_URL}?starttls=yes"
+        )
+        self.test_ldap_auth()
+
+    def test_ldap_status_shows_socket_path(self):
+        self.test_ldap_auth()
+
+        result = self.vk.execute_command("INFO", "ldap_status")
+        status = parse_valkey_info_section(result.decode("utf-8"))
+        hosts = [server["host"] for server in status.values()]
+        self.assertEqual(hosts, ["/ldapi/ldapi"])
+
+    def test_ldap_sasl_external_peer_credentials(self):
+        self.vk.execute_command(
+            "CONFIG", "SET", "ldap.search_bind_mechanism", "external"
+        )
+        resp = self.vk.execute_command(
+            "LDAP.SEARCH", "", "base", "(objectClass=*)", "ATTRIBUTES", "namingContexts"
+        )
+        self.assertEqual(resp, [[b"", [b"namingContexts", [b"dc=valkey,dc=io"]]]])
+
+    def test_ldapi_url_without_socket_path(self):
+        with self.assertRaises(ResponseError):
+            self.vk.execute_command("CONFIG", "SET", "ldap.servers", "ldapi://")
+
+
 class LdapModuleBindAndSearchTest(LdapTestCase):
     def setUp(self):
         super(LdapModuleBindAndSearchTest, self).setUp()
@@ -111,17 +746,299 @@ class LdapModuleBindAndSearchTest(LdapTestCase):
         resp = self.vk.execute_command("ACL", "WHOAMI")
         self.assertTrue(resp.decode() == "u2")
 
+    def test_ldap_auth_username_is_escaped(self):
+        self.vk.execute_command("CONFIG", "SET", "ldap.acl_password_fallback", "no")
+        self.vk.execute_command("ACL", "LOG", "RESET")
+        try:
+            # Unescaped, the username would match the entry of u2.
+            with self.assertRaises(AuthenticationError):
+                self.vk.execute_command("AUTH", "u*", "user2@123")
+        finally:
+            self.vk.execute_command(
+                "CONFIG", "SET", "ldap.acl_password_fallback", "yes"
+            )
+
+        entries = find_acl_log_entries(self.vk, "ldap:user-not-found")
+        self.assertEqual(len(entries), 1)
+        self.assertEqual(entries[0]["username"], "u*")
+
+    def test_ldap_search_command(self):
+        resp = self.vk.execute_command(
+            "LDAP.SEARCH",
+            "dc=valkey,dc=io",
+            "sub",
+            "(uid=u2)",
+            "ATTRIBUTES",
+            "cn",
+            "sn",
+        )
+        self.assertEqual(
+            resp,
+            [
+                [
+                    b"cn=user2,ou=appdev,dc=valkey,dc=io",
+                    [b"cn", [b"user2"], b"sn", [b"User2"]],
+                ]
+            ],
+        )
+
+    def test_ldap_search_command_size_limit(self):
+        resp = self.vk.execute_command(
+            "LDAP.SEARCH", "dc=valkey,dc=io", "sub", "(objectClass=*)", "SIZELIMIT", "2"
+        )
+        self.assertEqual(len(resp), 2)
+
+    def test_ldap_search_command_errors(self):
+        with self.assertRaises(ResponseError):
+            self.vk.execute_command(
+                "LDAP.SEARCH", "dc=valkey,dc=io", "children", "(objectClass=*)"
+            )
+        with self.assertRaises(ResponseError):
+            self.vk.execute_command("LDAP.SEARCH", "dc=valkey,dc=io", "sub", "(uid=u2")
+
     def test_ldap_ssl_auth(self):
         self.vk.execute_command("CONFIG", "SET", "ldap.servers", "ldaps://ldap")
         self.vk.execute_command("AUTH", "u2", "user2@123")
         resp = self.vk.execute_command("ACL", "WHOAMI")
         self.assertTrue(resp.decode() == "u2")
 
+    def test_ldap_auth_sasl_external_search_bind(self):
+        self.vk.execute_command("CONFIG", "SET", "ldap.servers", "ldaps://ldap")
+        self.vk.execute_command(
+            "CONFIG", "SET", "ldap.search_bind_mechanism", "external"
+        )
+        # The password is not used by the SASL EXTERNAL bind
+        self.vk.execute_command("CONFIG", "SET", "ldap.search_bind_passwd", "wrong")
+
+        self.vk.execute_command("AUTH", "u2", "user2@123")
+        resp = self.vk.execute_command("ACL", "WHOAMI")
+        self.assertTrue(resp.decode() == "u2")
+
+        resp = self.vk.execute_command(
+            "LDAP.SEARCH", "dc=valkey,dc=io", "sub", "(uid=u2)", "ATTRIBUTES", "cn"
+        )
+        self.assertEqual(len(resp), 1)
+
+    def test_ldap_auth_sasl_external_search_bind_without_tls(self):
+        self.vk.execute_command(
+            "CONFIG", "SET", "ldap.search_bind_mechanism", "external"
+        )
+        with self.assertRaises(AuthenticationError):
+            self.vk.execute_command("AUTH", "u2", "user2@123")
+
+    def test_ldap_search_bind_passwd_file(self):
+        path = "/tmp/search_bind_passwd"
+        DOCKER_SERVICES.write_file("valkey", path, "admin123!\n")
+        try:
+            # The file takes precedence over ldap.search_bind_passwd
+            self.vk.execute_command("CONFIG", "SET", "ldap.search_bind_passwd", "wrong")
+            self.vk.execute_command(
+                "CONFIG", "SET", "ldap.search_bind_passwd_file", path
+            )
+            self.test_ldap_auth()
+        finally:
+            self.vk.execute_command("CONFIG", "SET", "ldap.search_bind_passwd_file", "")
+            DOCKER_SERVICES.remove_file("valkey", path)
+
+    def test_ldap_search_bind_passwd_file_rotation(self):
+        path = "/tmp/search_bind_passwd"
+        DOCKER_SERVICES.write_file("valkey", path, "admin123!\n")
+        try:
+            self.vk.execute_command(
+                "CONFIG", "SET", "ldap.search_bind_passwd_file", path
+            )
+            self.test_ldap_auth()
+
+            # The new password is not set in the directory yet, so the bind
+            # falls back to the secondary password
+            DOCKER_SERVICES.write_file("valkey", path, "new-admin-passwd\nadmin123!\n")
+            self.test_ldap_auth()
+
+            DOCKER_SERVICES.write_file("valkey", path, "new-admin-passwd\n")
+            with self.assertRaises(AuthenticationError):
+                self.vk.execute_command("AUTH", "u2", "user2@123")
+        finally:
+            self.vk.execute_command("CONFIG", "SET", "ldap.search_bind_passwd_file", "")
+            DOCKER_SERVICES.remove_file("valkey", path)
+
+    def test_ldap_search_bind_passwd_file_errors(self):
+        with self.assertRaises(ResponseError):
+            self.vk.execute_command(
+                "CONFIG", "SET", "ldap.search_bind_passwd_file", "/tmp/does-not-exist"
+            )
+
+        path = "/tmp/search_bind_passwd"
+        DOCKER_SERVICES.write_file("valkey", path, "a\nb\nc\n")
+        try:
+            with self.assertRaises(ResponseError):
+                self.vk.execute_command(
+                    "CONFIG", "SET", "ldap.search_bind_passwd_file", path
+                )
+        finally:
+            DOCKER_SERVICES.remove_file("valkey", path)
+
+    @skipIf(GSSAPI_TESTS, "the module is built with the gssapi feature")
+    def test_ldap_auth_gssapi_not_supported(self):
+        self.vk.execute_command(
+            "CONFIG", "SET", "ldap.search_bind_mechanism", "gssapi"
+        )
+        with self.assertRaises(AuthenticationError):
+            self.vk.execute_command("AUTH", "u2", "user2@123")
+
+    @skipIf(GSSAPI_TESTS, "the module is built with the gssapi feature")
+    def test_ldap_gssapi_configs_not_supported(self):
+        with self.assertRaises(ResponseError) as ctx:
+            self.vk.execute_command(
+                "CONFIG", "SET", "ldap.gssapi_keytab", "/kerberos/valkey.keytab"
+            )
+        self.assertIn("gssapi feature", str(ctx.exception))
+        with self.assertRaises(ResponseError):
+            self.vk.execute_command(
+                "CONFIG", "SET", "ldap.gssapi_principal", "valkey-svc@VALKEY.IO"
+            )
+
     def test_ldap_auth_no_user(self):
         self.vk.execute_command("CONFIG", "SET", "ldap.servers", "ldaps://ldap")
         with self.assertRaises(AuthenticationError) as ctx:
             self.vk.execute_command("AUTH", "user2", "user2@123")
 
+    def test_ldap_auth_no_user_acl_log(self):
+        self.vk.execute_command("ACL", "LOG", "RESET")
+        with self.assertRaises(AuthenticationError) as ctx:
+            self.vk.execute_command("AUTH", "user2", "user2@123")
+
+        # Only Valkey logs the failure of the password of the ACL user.
+        entries = find_acl_log_entries(self.vk, "AUTH")
+        self.assertEqual(len(self.vk.execute_command("ACL", "LOG")), 1)
+        self.assertEqual(len(entries), 1)
+        self.assertEqual(entries[0]["count"], 1)
+        self.assertEqual(entries[0]["username"], "user2")
+
+    def test_ldap_auth_no_acl_user_acl_log(self):
+        self.vk.execute_command("ACL", "DELUSER", "u2")
+        self.vk.execute_command("ACL", "LOG", "RESET")
+        with self.assertRaises(AuthenticationError) as ctx:
+            self.vk.execute_command("AUTH", "u2", "user2@123")
+
+        # The module rejects the authentication itself, and logs the reason.
+        entries = find_acl_log_entries(self.vk, "ldap:user-not-found")
+        self.assertEqual(len(self.vk.execute_command("ACL", "LOG")), 1)
+        self.assertEqual(len(entries), 1)
+        self.assertEqual(entries[0]["count"], 1)
+        self.assertEqual(entries[0]["username"], "u2")
+
+    def test_ldap_auth_with_account_status_check(self):
+        self.vk.execute_command(
+            "CONFIG", "SET", "ldap.search_account_status_check", "yes"
+        )
+        try:
+            self.test_ldap_auth()
+        finally:
+            self.vk.execute_command(
+                "CONFIG", "SET", "ldap.search_account_status_check", "no"
+            )
+
+    def test_ldap_auth_account_disabled_acl_log(self):
+        self.vk.execute_command("ACL", "SETUSER", "u3", "ON", ">pass", "allcommands")
+        self.vk.execute_command(
+            "CONFIG", "SET", "ldap.search_account_status_check", "yes"
+        )
+        # The directory refused the account, so the password of the ACL user is
+        # not checked even with the fallback enabled.
+        self.vk.execute_command("ACL", "LOG", "RESET")
+        try:
+            for password in ["user3@123", "pass"]:
+                with self.assertRaises(AuthenticationError):
+                    self.vk.execute_command("AUTH", "u3", password)
+        finally:
+            self.vk.execute_command(
+                "CONFIG", "SET", "ldap.search_account_status_check", "no"
+            )
+            self.vk.execute_command("ACL", "DELUSER", "u3")
+
+        entries = find_acl_log_entries(self.vk, "ldap:account-disabled")
+        self.assertEqual(len(self.vk.execute_command("ACL", "LOG")), 1)
+        self.assertEqual(len(entries), 1)
+        self.assertEqual(entries[0]["count"], 2)
+        self.assertEqual(entries[0]["username"], "u3")
+
+    def test_ldap_revalidation_disconnects_client(self):
+        sock = socket.create_connection(("localhost", 6379))
+        sock.sendall(b"AUTH u2 user2@123\r\n")
+        self.assertEqual(sock.recv(1024), b"+OK\r\n")
+
+        try:
+            self.vk.execute_command("CONFIG", "SET", "ldap.revalidation_interval", "1")
+            self.vk.execute_command(
+                "CONFIG", "SET", "ldap.revalidation_grace_period", "2"
+            )
+            time.sleep(2)
+            sock.sendall(b"PING\r\n")
+            self.assertEqual(sock.recv(1024), b"+PONG\r\n")
+
+            # The user no longer matches the revalidation filter, but the client
+            # is only disconnected after the grace period.
+            self.vk.execute_command(
+                "CONFIG", "SET", "ldap.revalidation_filter", "uid=nobody"
+            )
+            time.sleep(1.5)
+            sock.sendall(b"PING\r\n")
+            self.assertEqual(sock.recv(1024), b"+PONG\r\n")
+
+            time.sleep(3)
+            sock.settimeout(5)
+            self.assertEqual(sock.recv(1024), b"")
+        finally:
+            sock.close()
+            self.vk.execute_command("CONFIG", "SET", "ldap.revalidation_interval", "0")
+            self.vk.execute_command(
+                "CONFIG", "SET", "ldap.revalidation_grace_period", "0"
+            )
+            self.vk.execute_command(
+                "CONFIG", "SET", "ldap.revalidation_filter", "objectClass=*"
+            )
+
+    def test_ldap_max_session_lifetime(self):
+        self.vk.execute_command("CONFIG", "SET", "ldap.max_session_lifetime", "2")
+        sock = socket.create_connection(("localhost", 6379))
+        try:
+            sock.sendall(b"AUTH u2 user2@123\r\n")
+            self.assertEqual(sock.recv(1024), b"+OK\r\n")
+
+            info = self.vk.execute_command("INFO", "ldap_sessions").decode("utf-8")
+            sessions = parse_valkey_info_section(info)
+            self.assertEqual(sessions["ldap_active_sessions"], "1")
+
+            sock.settimeout(5)
+            self.assertEqual(sock.recv(1024), b"")
+
+            info = self.vk.execute_command("INFO", "ldap_sessions").decode("utf-8")
+            sessions = parse_valkey_info_section(info)
+            self.assertEqual(sessions["ldap_active_sessions"], "0")
+        finally:
+            sock.close()
+            self.vk.execute_command("CONFIG", "SET", "ldap.max_session_lifetime", "0")
+
+    def test_ldap_reauth_removes_session(self):
+        client = valkey.Valkey(host="localhost", port=6379, db=0)
+        try:
+            client.execute_command("AUTH", "u2", "user2@123")
+            info = self.vk.execute_command("INFO", "ldap_sessions").decode("utf-8")
+            sessions = parse_valkey_info_section(info)
+            self.assertEqual(sessions["ldap_active_sessions"], "1")
+
+            # Re-authenticating as a local ACL user ends the LDAP session.
+            client.execute_command("AUTH", "u2", "pass")
+            info = self.vk.execute_command("INFO", "ldap_sessions").decode("utf-8")
+            sessions = parse_valkey_info_section(info)
+            self.assertEqual(sessions["ldap_active_sessions"], "0")
+
+            with self.assertRaises(ResponseError) as ctx:
+                client.execute_command("LDAP.PASSWD", "user2@123", "user2@456")
+            self.assertIn("not authenticated with an LDAP user", str(ctx.exception))
+        finally:
+            client.close()
+
     def test_ldap_bind_password_hidden(self):
         res = self.vk.execute_command("CONFIG", "GET", "ldap.search_bind_passwd")
         self.assertEqual(res[1].decode("utf-8"), "admin123!")
@@ -148,9 +1065,49 @@ class LdapModuleFailoverTest(LdapTestCase):
         resp = self.vk.execute_command("ACL", "WHOAMI")
         self.assertTrue(resp.decode() == "user1")
 
+    def _get_servers(self):
+        return get_ldap_servers(self.vk)
+
+    def test_servers_command(self):
+        self.test_ldap_auth()
+
+        servers = self._get_servers()
+        self.assertEqual(
+            [server["url"] for server in servers], [b"ldap://ldap", b"ldap://ldap-2"]
+        )
+        self.assertEqual(servers[0]["id"], 0)
+        self.assertEqual(servers[0]["status"], b"healthy")
+        self.assertEqual(servers[0]["pool_size"], 2)
+        self.assertEqual(servers[0]["waiters"], 0)
+        self.assertGreaterEqual(servers[0]["operations"], 1)
+
+    def test_servers_mark_down_and_up(self):
+        self.vk.execute_command("LDAP.SERVERS", "DOWN", "ldap")
+        try:
+            servers = self._get_servers()
+            self.assertEqual(servers[0]["status"], b"down")
+
+            # The authentications are served by the other server.
+            operations = servers[0]["operations"]
+            for _ in range(5):
+                self.test_ldap_auth()
+            self.assertEqual(self._get_servers()[0]["operations"], operations)
+        finally:
+            self.vk.execute_command("LDAP.SERVERS", "UP", "ldap")
+
+        self.assertEqual(self._get_servers()[0]["status"], b"healthy")
+
+    def test_servers_refresh_pool(self):
+        epoch = self._get_servers()[1]["pool_epoch"]
+        self.vk.execute_command("LDAP.SERVERS", "REFRESH", "1")
+        self.assertEqual(self._get_servers()[1]["pool_epoch"], epoch + 1)
+
+        with self.assertRaises(ResponseError):
+            self.vk.execute_command("LDAP.SERVERS", "REFRESH", "ldap-3")
+
     def _wait_for_ldap_server_status(self, server_name, status_desc):
         while True:
-            result = self.vk.execute_command("INFO LDAP")
+            result = self.vk.execute_command("INFO", "ldap_status")
             status = parse_valkey_info_section(result.decode("utf-8"))
 
             for server in status.values():
@@ -160,6 +1117,54 @@ class LdapModuleFailoverTest(LdapTestCase):
 
             time.sleep(2)
 
+    def test_auth_with_hedging(self):
+        self.vk.execute_command("CONFIG", "SET", "ldap.hedge_delay_ms", "1")
+        self.vk.execute_command("CONFIG", "SET", "ldap.hedge_binds", "yes")
+        try:
+            for _ in range(50):
+                self.test_ldap_auth()
+            with self.assertRaises(AuthenticationError) as ctx:
+                self.vk.execute_command("AUTH", "user1", "wrongpass")
+
+            self.vk.execute_command("CONFIG", "SET", "ldap.hedge_delay_ms", "100")
+            operations = [s["operations"] for s in self._get_servers()]
+            service = DOCKER_SERVICES.pause_service("ldap")
+            try:
+                # The bind is answered by the second server after the hedging
+                # delay, instead of waiting for the operation timeout.
+                start = time.monotonic()
+                self.test_ldap_auth()
+                self.assertLess(time.monotonic() - start, 2)
+
+                servers = self._get_servers()
+                self.assertEqual(servers[0]["operations"], operations[0])
+                self.assertEqual(servers[1]["operations"], operations[1] + 1)
+            finally:
+                DOCKER_SERVICES.unpause_service(service)
+
+            # The connection of the cancelled bind is replaced.
+            self._assert_idle_pools(wait_for_idle_pools(self.vk))
+
+            self.vk.execute_command("CONFIG", "SET", "ldap.hedge_delay_ms", "1")
+            self.vk.execute_command("CONFIG", "SET", "ldap.hedge_delay_percentile", "95")
+            for _ in range(50):
+                self.test_ldap_auth()
+        finally:
+            self.vk.execute_command("CONFIG", "SET", "ldap.hedge_delay_ms", "0")
+            self.vk.execute_command("CONFIG", "SET", "ldap.hedge_delay_percentile", "0")
+            self.vk.execute_command("CONFIG", "SET", "ldap.hedge_binds", "no")
+
+    def test_failover_with_millisecond_interval(self):
+        self.vk.execute_command("CONFIG", "SET", "ldap.failure_detector_interval_ms", "200")
+        self.vk.execute_command("CONFIG", "SET", "ldap.timeout_connection_ms", "500")
+        self.vk.execute_command("CONFIG", "SET", "ldap.timeout_ldap_operation_ms", "500")
+        try:
+            self.test_single_auth_with_failover()
+        finally:
+            self.vk.execute_command("CONFIG", "SET", "ldap.failure_detector_interval_ms", "0")
+            self.vk.execute_command("CONFIG", "SET", "ldap.timeout_connection_ms", "0")
+            self.vk.execute_command("CONFIG", "SET", "ldap.timeout_ldap_operation_ms", "0")
+
     def test_single_auth_with_failover(self):
         service = DOCKER_SERVICES.stop_service("ldap")
         self._wait_for_ldap_server_status("ldap", "unhealthy")
@@ -169,6 +1174,63 @@ class LdapModuleFailoverTest(LdapTestCase):
         DOCKER_SERVICES.restart_service(service)
         self._wait_for_ldap_server_status("ldap", "healthy")
 
+    def test_auth_total_timeout(self):
+        self.vk.execute_command("CONFIG", "SET", "ldap.timeout_auth_total_ms", "500")
+        service = DOCKER_SERVICES.pause_service("ldap")
+        service2 = DOCKER_SERVICES.pause_service("ldap-2")
+        try:
+            # The authentication fails at the deadline, long before the
+            # operation timeout of each server.
+            start = time.monotonic()
+            with self.assertRaises(ResponseError) as ctx:
+                self.vk.execute_command("AUTH", "user1", "user1@123")
+            elapsed = time.monotonic() - start
+            self.assertIn("timed out", str(ctx.exception))
+            self.assertGreaterEqual(elapsed, 0.5)
+            self.assertLess(elapsed, 2)
+        finally:
+            DOCKER_SERVICES.unpause_service(service)
+            DOCKER_SERVICES.unpause_service(service2)
+            self.vk.execute_command("CONFIG", "SET", "ldap.timeout_auth_total_ms", "0")
+
+        self._assert_idle_pools(wait_for_idle_pools(self.vk))
+        self.test_ldap_auth()
+
+    def test_pool_keeps_its_size_during_outage(self):
+        self.vk.execute_command(
+            "CONFIG", "SET", "ldap.servers", "ldaps://ldap ldaps://ldap-2"
+        )
+        self.vk.execute_command("CONFIG", "SET", "ldap.timeout_auth_total_ms", "500")
+        self.vk.execute_command("CONFIG", "SET", "ldap.timeout_connection_ms", "500")
+        pool_size = self._get_servers()[0]["pool_size"]
+        service = DOCKER_SERVICES.pause_service("ldap")
+        try:
+            # The deadline interrupts the bind on the paused server, whose
+            # connection cannot be replaced until the server is back.
+            with self.assertRaises(ResponseError):
+                self.vk.execute_command("AUTH", "user1", "user1@123")
+
+            deadline = time.monotonic() + 5
+            while self._get_servers()[0]["reconnecting_connections"] == 0:
+                self.assertLess(time.monotonic(), deadline)
+                time.sleep(0.1)
+            self.assertEqual(self._get_servers()[0]["pool_size"], pool_size)
+
+            # The other server answers while the pool is reconnecting.
+            self.test_ldap_auth()
+        finally:
+            DOCKER_SERVICES.unpause_service(service)
+            self.vk.execute_command("CONFIG", "SET", "ldap.timeout_auth_total_ms", "0")
+            self.vk.execute_command("CONFIG", "SET", "ldap.timeout_connection_ms", "0")
+
+        # The pool gets all of its connections back once the server is back.
+        servers = wait_for_idle_pools(self.vk, timeout=15)
+        self._assert_idle_pools(servers)
+        self.assertEqual(servers[0]["pool_size"], pool_size)
+        self.assertEqual(servers[0]["reconnecting_connections"], 0)
+        self._wait_for_ldap_server_status("ldap", "healthy")
+        self.test_ldap_auth()
+
     def test_single_auth_failure_and_recovery(self):
         service = DOCKER_SERVICES.stop_service("ldap")
         service2 = DOCKER_SERVICES.stop_service("ldap-2")
@@ -214,6 +1276,58 @@ class LdapModuleFailoverTest(LdapTestCase):
         self.assertIsNone(worker_result["error"])
 
 
+class LdapModuleHealthCheckTest(LdapModuleFailoverTest):
+    def setUp(self):
+        super(LdapModuleHealthCheckTest, self).setUp()
+
+        self.vk.execute_command(
+            "CONFIG", "SET", "ldap.search_bind_dn", "cn=admin,dc=valkey,dc=io"
+        )
+        self.vk.execute_command("CONFIG", "SET", "ldap.search_bind_passwd", "admin123!")
+        self.vk.execute_command("CONFIG", "SET", "ldap.search_base", "dc=valkey,dc=io")
+
+    def tearDown(self):
+        self.vk.execute_command("CONFIG", "SET", "ldap.health_check", "whoami")
+        super(LdapModuleHealthCheckTest, self).tearDown()
+
+    def _check_probe(self, probe):
+        self.vk.execute_command("CONFIG", "SET", "ldap.health_check", probe)
+        self.test_single_auth_with_failover()
+        self.test_ldap_auth()
+
+    def test_rootdse_probe(self):
+        self._check_probe("rootdse")
+
+    def test_service_bind_probe(self):
+        self._check_probe("service-bind")
+
+    def test_search_probe(self):
+        self._check_probe("search")
+
+    def test_service_bind_probe_wrong_password(self):
+        self.vk.execute_command("CONFIG", "SET", "ldap.search_bind_passwd", "wrong")
+        self.vk.execute_command("CONFIG", "SET", "ldap.health_check", "service-bind")
+        self._wait_for_ldap_server_status("ldap", "unhealthy")
+        self._wait_for_ldap_server_status("ldap-2", "unhealthy")
+
+        self.vk.execute_command("CONFIG", "SET", "ldap.search_bind_passwd", "admin123!")
+        self._wait_for_ldap_server_status("ldap", "healthy")
+        self._wait_for_ldap_server_status("ldap-2", "healthy")
+
+    def test_service_bind_probe_sasl_external(self):
+        self.vk.execute_command(
+            "CONFIG", "SET", "ldap.servers", "ldaps://ldap ldaps://ldap-2"
+        )
+        self.vk.execute_command(
+            "CONFIG", "SET", "ldap.search_bind_mechanism", "external"
+        )
+        self.vk.execute_command("CONFIG", "SET", "ldap.search_bind_passwd", "wrong")
+        self.vk.execute_command("CONFIG", "SET", "ldap.health_check", "service-bind")
+        self._wait_for_ldap_server_status("ldap", "healthy")
+        self._wait_for_ldap_server_status("ldap-2", "healthy")
+        self.test_ldap_auth()
+
+
 class LdapModuleSearchAndBindFailoverTest(LdapModuleFailoverTest):
     def setUp(self):
         super(LdapModuleSearchAndBindFailoverTest, self).setUp()
@@ -230,3 +1344,36 @@ class LdapModuleSearchAndBindFailoverTest(LdapModuleFailoverTest):
         self.vk.execute_command("AUTH", "u2", "user2@123")
         resp = self.vk.execute_command("ACL", "WHOAMI")
         self.assertTrue(resp.decode() == "u2")
+
+
+@skipUnless(GSSAPI_TESTS, "requires the KDC containers, set VALKEY_LDAP_GSSAPI_TESTS")
+class LdapModuleGssapiTest(LdapTestCase):
+    def setUp(self):
+        super(LdapModuleGssapiTest, self).setUp()
+
+        self.vk.execute_command("CONFIG", "SET", "ldap.servers", "ldap://ldap-gssapi")
+        self.vk.execute_command("CONFIG", "SET", "ldap.auth_mode", "search+bind")
+        self.vk.execute_command("CONFIG", "SET", "ldap.search_base", "dc=valkey,dc=io")
+        self.vk.execute_command("CONFIG", "SET", "ldap.search_bind_dn", "")
+        self.vk.execute_command("CONFIG", "SET", "ldap.search_bind_passwd", "")
+
+        self.vk.execute_command(
+            "CONFIG", "SET", "ldap.search_bind_mechanism", "gssapi"
+        )
+        self.vk.execute_command(
+            "CONFIG", "SET", "ldap.gssapi_keytab", "/kerberos/valkey.keytab"
+        )
+        self.vk.execute_command(
+            "CONFIG", "SET", "ldap.gssapi_principal", "valkey-svc@VALKEY.IO"
+        )
+
+    def test_ldap_auth(self):
+        self.vk.execute_command("AUTH", "u2", "user2@123")
+        resp = self.vk.execute_command("ACL", "WHOAMI")
+        self.assertTrue(resp.decode() == "u2")
+
+    def test_ldap_search_command(self):
+        resp = self.vk.execute_command(
+            "LDAP.SEARCH", "dc=valkey,dc=io", "sub", "(uid=u2)", "ATTRIBUTES", "cn"
+        )
+        self.assertEqual(len(resp), 1)
@@ -1,3 +1,4 @@
+import time
 from unittest import TestCase
 import docker
 import valkey
@@ -28,6 +29,50 @@ class DockerServices:
     def restart_service(self, serv):
         serv.restart()
 
+    def pause_service(self, name: str):
+        ct = self._find_container(name)
+        assert ct is not None, f"service {name} is not running"
+        ct.pause()
+        return ct
+
+    def unpause_service(self, serv):
+        serv.unpause()
+
+    def read_file(self, name: str, path: str) -> str:
+        ct = self._find_container(name)
+        assert ct is not None, f"service {name} is not running"
+        exit_code, output = ct.exec_run(["cat", path])
+        assert exit_code == 0, f"failed to read {path} from {name}"
+        return output.decode("utf-8")
+
+    def write_file(self, name: str, path: str, contents: str):
+        ct = self._find_container(name)
+        assert ct is not None, f"service {name} is not running"
+        exit_code, _ = ct.exec_run(
+            ["sh", "-c", 'printf "%s" "$0" > "$1"', contents, path]
+        )
+        assert exit_code == 0, f"failed to write {path} in {name}"
+
+    def ldap_modify(self, name: str, ldif: str):
+        ct = self._find_container(name)
+        assert ct is not None, f"service {name} is not running"
+        exit_code, output = ct.exec_run(
+            [
+                "sh",
+                "-c",
+                'printf "%s" "$0" | ldapmodify -x -D "$1" -w "$2"',
+                ldif,
+                "cn=admin,dc=valkey,dc=io",
+                "admin123!",
+            ]
+        )
+        assert exit_code == 0, f"failed to modify {name}: {output.decode('utf-8')}"
+
+    def remove_file(self, name: str, path: str):
+        ct = self._find_container(name)
+        assert ct is not None, f"service {name} is not running"
+        ct.exec_run(["rm", "-f", path])
+
 
 DOCKER_SERVICES = DockerServices()
 
@@ -49,14 +94,32 @@ class LdapTestCase(TestCase):
         vk.execute_command(
             "CONFIG", "SET", "ldap.tls_key_path", "/valkey-ldap/valkey-ldap-client.key"
         )
+        vk.execute_command("CONFIG", "SET", "ldap.tls_key_passphrase", "")
+        vk.execute_command("CONFIG", "SET", "ldap.tls_ca_cert_dir", "")
+        vk.execute_command("CONFIG", "SET", "ldap.tls_min_version", "tls1.2")
+        vk.execute_command("CONFIG", "SET", "ldap.tls_use_system_ca", "yes")
+        vk.execute_command("CONFIG", "SET", "ldap.tls_server_name", "")
+        vk.execute_command("CONFIG", "SET", "ldap.tls_skip_verify", "no")
+        vk.execute_command("CONFIG", "SET", "ldap.tls_pinned_keys", "")
         vk.execute_command("CONFIG", "SET", "ldap.use_starttls", "no")
 
+        # Search bind configuration
+        vk.execute_command("CONFIG", "SET", "ldap.search_bind_mechanism", "simple")
+        vk.execute_command("CONFIG", "SET", "ldap.search_bind_passwd_file", "")
+        vk.execute_command("CONFIG", "SET", "ldap.gssapi_keytab", "")
+        vk.execute_command("CONFIG", "SET", "ldap.gssapi_principal", "")
+
         # Add users in Valkey
         vk.execute_command("ACL", "SETUSER", "user1", "ON", ">pass", "allcommands")
         vk.execute_command("ACL", "SETUSER", "u2", "ON", ">pass", "allcommands")
 
         self.vk = vk
 
+    def _assert_idle_pools(self, servers):
+        for server in servers:
+            self.assertEqual(server["borrowed_connections"], 0)
+            self.assertEqual(server["idle_connections"], server["pool_size"])
+
     def tearDown(self):
         assert self.vk is not None, "Valkey instance should not be None"
         self.vk.close()
@@ -75,9 +138,47 @@ def parse_valkey_info_section(section: str) -> dict:
             result[key.strip()] = value.strip()
         else:
             dict_key, dict_values = line.split(":", 1)
+            if "=" not in dict_values:
+                result[dict_key.strip()] = dict_values.strip()
+                continue
             nested_dict = {}
             for key_value_pair in dict_values.split(","):
                 key, value = key_value_pair.strip().split("=", 1)
                 nested_dict[key.strip()] = value.strip()
             result[dict_key.strip()] = nested_dict
     return result
+
+
+def get_ldap_servers(vk) -> list:
+    servers = vk.execute_command("LDAP.SERVERS")
+    return [
+        {name.decode(): value for name, value in zip(server[::2], server[1::2])}
+        for server in servers
+    ]
+
+
+def wait_for_idle_pools(vk, timeout: float = 5) -> list:
+    deadline = time.monotonic() + timeout
+    while True:
+        servers = get_ldap_servers(vk)
+        idle = all(
+            server["borrowed_connections"] == 0
+            and server["idle_connections"] == server["pool_size"]
+            for server in servers
+        )
+        if idle or time.monotonic() > deadline:
+            return servers
+        time.sleep(0.1)
+
+
+def find_acl_log_entries(vk, object_name: str) -> list:
+    entries = []
+    for raw_entry in vk.execute_command("ACL", "LOG"):
+        entry = {}
+        for i in range(0, len(raw_entry), 2):
+            key = raw_entry[i].decode("utf-8")
+            value = raw_entry[i + 1]
+            entry[key] = value.decode("utf-8") if isinstance(value, bytes) else value
+        if entry.get("object") == object_name:
+            entries.append(entry)
+    return entries